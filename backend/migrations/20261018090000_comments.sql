@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS comments
+(
+    id         UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    note_id    UUID        NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    user_id    UUID        NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    parent_id  UUID REFERENCES comments (id) ON DELETE CASCADE,
+    body       TEXT        NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ,
+    -- Comments are soft deleted so that replies keep their place in the thread
+    deleted_at TIMESTAMPTZ,
+    deleted_by UUID REFERENCES users (id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS comments_note_id_idx ON comments (note_id, created_at);
+CREATE INDEX IF NOT EXISTS comments_parent_id_idx ON comments (parent_id);
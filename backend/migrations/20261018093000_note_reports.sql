@@ -0,0 +1,23 @@
+ALTER TABLE notes
+    ADD COLUMN IF NOT EXISTS is_hidden BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS note_reports
+(
+    id              UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    note_id         UUID        NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    reporter_id     UUID        NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    reason          VARCHAR(20) NOT NULL
+        CHECK (reason IN ('wrong_content', 'spam', 'copyright', 'inappropriate', 'duplicate', 'other')),
+    details         TEXT,
+    status          VARCHAR(20) NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'resolved', 'dismissed')),
+    assigned_to     UUID REFERENCES users (id) ON DELETE SET NULL,
+    resolved_by     UUID REFERENCES users (id) ON DELETE SET NULL,
+    resolution_note TEXT,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at     TIMESTAMPTZ
+);
+
+-- A user can only have one open report per note
+CREATE UNIQUE INDEX IF NOT EXISTS note_reports_open_unique ON note_reports (note_id, reporter_id) WHERE status = 'open';
+CREATE INDEX IF NOT EXISTS note_reports_status_idx ON note_reports (status, created_at);
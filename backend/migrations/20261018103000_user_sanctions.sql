@@ -0,0 +1,7 @@
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS suspended_until TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS banned_at       TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS sanction_reason TEXT,
+    ADD COLUMN IF NOT EXISTS sanctioned_by   UUID REFERENCES users (id) ON DELETE SET NULL,
+    -- Set when a user is banned and their existing notes should disappear from listings
+    ADD COLUMN IF NOT EXISTS notes_hidden    BOOLEAN NOT NULL DEFAULT FALSE;
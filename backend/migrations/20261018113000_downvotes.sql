@@ -0,0 +1,28 @@
+-- Downvotes must say what is wrong with the note
+ALTER TABLE votes
+    ADD COLUMN IF NOT EXISTS downvote_reason VARCHAR(32)
+        CHECK (downvote_reason IN ('outdated', 'wrong_course', 'illegible', 'incomplete', 'other'));
+
+UPDATE votes SET downvote_reason = 'other' WHERE NOT is_upvote AND downvote_reason IS NULL;
+
+ALTER TABLE votes
+    ADD CONSTRAINT votes_downvote_reason_required CHECK (is_upvote = (downvote_reason IS NULL));
+
+-- Lower bound of the Wilson score interval (95% confidence) over a note's votes, kept up to date
+-- whenever a vote changes and used to rank notes by quality
+ALTER TABLE notes
+    ADD COLUMN IF NOT EXISTS wilson_score DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+UPDATE notes n
+SET wilson_score = (
+    (s.p + 1.9208 / s.total - 1.96 * SQRT((s.p * (1 - s.p) + 0.9604 / s.total) / s.total))
+        / (1 + 3.8416 / s.total)
+)
+FROM (SELECT note_id,
+             COUNT(*)::DOUBLE PRECISION                                     AS total,
+             COUNT(*) FILTER (WHERE is_upvote)::DOUBLE PRECISION / COUNT(*) AS p
+      FROM votes
+      GROUP BY note_id) s
+WHERE n.id = s.note_id;
+
+CREATE INDEX IF NOT EXISTS notes_wilson_score_idx ON notes (wilson_score DESC, created_at DESC);
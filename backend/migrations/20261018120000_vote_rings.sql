@@ -0,0 +1,38 @@
+-- Uploaders can no longer vote on their own notes
+DELETE FROM votes v
+USING notes n
+WHERE v.note_id = n.id AND v.user_id = n.uploader_user_id;
+
+UPDATE notes n
+SET wilson_score = COALESCE((
+    SELECT (s.p + 1.9208 / s.total - 1.96 * SQRT((s.p * (1 - s.p) + 0.9604 / s.total) / s.total))
+               / (1 + 3.8416 / s.total)
+    FROM (SELECT COUNT(*)::DOUBLE PRECISION                                     AS total,
+                 COUNT(*) FILTER (WHERE is_upvote)::DOUBLE PRECISION / COUNT(*) AS p
+          FROM votes
+          WHERE note_id = n.id
+          HAVING COUNT(*) > 0) s
+), 0);
+
+-- Votes cast within a confirmed vote ring do not count towards reputation
+ALTER TABLE votes
+    ADD COLUMN IF NOT EXISTS excluded BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Clusters of accounts that mostly upvote each other, found by the vote ring job
+CREATE TABLE IF NOT EXISTS vote_rings
+(
+    id           UUID PRIMARY KEY          DEFAULT gen_random_uuid(),
+    -- Sorted, so that the same cluster is only flagged once
+    member_ids   UUID[]           NOT NULL,
+    mutual_votes INT              NOT NULL,
+    total_votes  INT              NOT NULL,
+    status       VARCHAR(16)      NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'confirmed', 'cleared')),
+    reviewed_by  UUID REFERENCES users (id) ON DELETE SET NULL,
+    reviewed_at  TIMESTAMPTZ,
+    detected_at  TIMESTAMPTZ      NOT NULL DEFAULT NOW(),
+    updated_at   TIMESTAMPTZ      NOT NULL DEFAULT NOW(),
+    UNIQUE (member_ids)
+);
+
+CREATE INDEX IF NOT EXISTS vote_rings_status_idx ON vote_rings (status, detected_at);
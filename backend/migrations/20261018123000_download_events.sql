@@ -0,0 +1,20 @@
+-- One row per counted download. Downloads are keyed by user when signed in and by a keyed hash of
+-- the client IP otherwise; repeat downloads within the dedup window are not recorded.
+CREATE TABLE IF NOT EXISTS download_events
+(
+    id         UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    note_id    UUID        NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    user_id    UUID REFERENCES users (id) ON DELETE SET NULL,
+    ip_hash    VARCHAR(64),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS download_events_user_idx ON download_events (note_id, user_id, created_at);
+CREATE INDEX IF NOT EXISTS download_events_ip_idx ON download_events (note_id, ip_hash, created_at);
+
+-- Counts from before download events existed cannot be rebuilt, so they are kept separately and
+-- `downloads` becomes `legacy_downloads` plus the number of events
+ALTER TABLE notes
+    ADD COLUMN IF NOT EXISTS legacy_downloads INT NOT NULL DEFAULT 0;
+
+UPDATE notes SET legacy_downloads = downloads;
@@ -0,0 +1,39 @@
+-- Reputation stats per user, kept up to date as notes, votes and downloads change so that the
+-- leaderboard does not aggregate every note and vote on each request
+CREATE TABLE IF NOT EXISTS leaderboard_stats
+(
+    user_id         UUID PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    total_notes     BIGINT           NOT NULL DEFAULT 0,
+    total_upvotes   BIGINT           NOT NULL DEFAULT 0,
+    total_downloads BIGINT           NOT NULL DEFAULT 0,
+    reputation      DOUBLE PRECISION NOT NULL DEFAULT 0,
+    updated_at      TIMESTAMPTZ      NOT NULL DEFAULT NOW()
+);
+
+-- Matches the leaderboard order, ranks are counted off this index
+CREATE INDEX IF NOT EXISTS leaderboard_stats_rank_idx ON leaderboard_stats (reputation DESC, total_notes DESC);
+
+WITH note_stats AS (
+    SELECT
+        n.uploader_user_id as user_id,
+        COUNT(*) as total_notes,
+        SUM((SELECT COUNT(*) FROM votes v WHERE v.note_id = n.id AND v.is_upvote AND NOT v.excluded)) as total_upvotes,
+        SUM(n.downloads) as total_downloads
+    FROM notes n
+    GROUP BY n.uploader_user_id
+)
+INSERT INTO leaderboard_stats (user_id, total_notes, total_upvotes, total_downloads, reputation)
+SELECT
+    u.id,
+    COALESCE(s.total_notes, 0),
+    COALESCE(s.total_upvotes, 0),
+    COALESCE(s.total_downloads, 0),
+    CASE
+        WHEN COALESCE(s.total_notes, 0) > 0 THEN
+            (s.total_upvotes::FLOAT / s.total_notes::FLOAT) *
+            (s.total_notes + s.total_upvotes + s.total_downloads)
+        ELSE 0
+    END
+FROM users u
+LEFT JOIN note_stats s ON s.user_id = u.id
+ON CONFLICT (user_id) DO NOTHING;
@@ -0,0 +1,19 @@
+-- Leaderboard stats are kept for each window: all time, and the current week, month and semester.
+-- Windowed stats only count notes, votes and downloads from within the window.
+ALTER TABLE leaderboard_stats
+    ADD COLUMN IF NOT EXISTS period VARCHAR(16) NOT NULL DEFAULT 'all'
+        CHECK (period IN ('all', 'week', 'month', 'semester'));
+
+ALTER TABLE leaderboard_stats DROP CONSTRAINT IF EXISTS leaderboard_stats_pkey;
+ALTER TABLE leaderboard_stats ADD PRIMARY KEY (period, user_id);
+ALTER TABLE leaderboard_stats ALTER COLUMN period DROP DEFAULT;
+
+DROP INDEX IF EXISTS leaderboard_stats_rank_idx;
+CREATE INDEX IF NOT EXISTS leaderboard_stats_rank_idx
+    ON leaderboard_stats (period, reputation DESC, total_notes DESC);
+
+-- Used to count a user's notes and the votes on them within a window
+CREATE INDEX IF NOT EXISTS notes_uploader_created_idx ON notes (uploader_user_id, created_at);
+CREATE INDEX IF NOT EXISTS votes_note_created_idx ON votes (note_id, created_at);
+
+-- The windowed rows are filled in by the first refresh after startup
@@ -0,0 +1,4 @@
+-- Downvotes feed into the configurable reputation formulas. Reputation itself is now scored in
+-- the application, existing values are recomputed by the first refresh after startup.
+ALTER TABLE leaderboard_stats
+    ADD COLUMN IF NOT EXISTS total_downvotes BIGINT NOT NULL DEFAULT 0;
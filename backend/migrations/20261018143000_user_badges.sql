@@ -0,0 +1,11 @@
+-- Badges awarded to users by the badge job. Badges that can be earned more than once, such as a
+-- top ranking in a semester, are told apart by their context, e.g. 'Autumn 2026'.
+CREATE TABLE IF NOT EXISTS user_badges
+(
+    id         UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    user_id    UUID        NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    badge      VARCHAR(64) NOT NULL,
+    context    VARCHAR(64) NOT NULL DEFAULT '',
+    awarded_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT user_badges_unique UNIQUE (user_id, badge, context)
+);
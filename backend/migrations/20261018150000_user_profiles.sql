@@ -0,0 +1,18 @@
+-- Profile fields users can edit themselves. `full_name` and `picture` always hold what Google
+-- last sent, a display name set by the user takes precedence over the full name.
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS display_name       VARCHAR(100),
+    ADD COLUMN IF NOT EXISTS bio                TEXT,
+    ADD COLUMN IF NOT EXISTS department         VARCHAR(100),
+    ADD COLUMN IF NOT EXISTS graduation_year    INT,
+    ADD COLUMN IF NOT EXISTS show_initials_only BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The name shown to everyone else, e.g. 'A. B.' for 'Alice Bob' when only initials are shown
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS public_name TEXT NOT NULL GENERATED ALWAYS AS (
+        CASE
+            WHEN show_initials_only THEN
+                TRIM(REGEXP_REPLACE(TRIM(COALESCE(display_name, full_name)), '(\S)\S*\s*', '\1. ', 'g'))
+            ELSE COALESCE(display_name, full_name)
+        END
+    ) STORED;
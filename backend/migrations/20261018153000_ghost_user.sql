@@ -0,0 +1,14 @@
+-- Notes of users who delete their account but keep their notes up are reassigned to this user.
+-- It is banned so that nobody can sign in as it, which also keeps it out of badges.
+INSERT INTO users (id, google_id, email, full_name, picture, banned_at, sanction_reason)
+VALUES (
+    '00000000-0000-0000-0000-000000000000',
+    'deleted-user',
+    'deleted-user@invalid',
+    'Deleted user',
+    'https://lh3.googleusercontent.com/-XdUIqdMkCWA/AAAAAAAAAAI/AAAAAAAAAAA/4252rscbv5M/photo.jpg',
+    NOW(),
+    'Placeholder for deleted accounts'
+)
+ON CONFLICT (id) DO NOTHING;
+
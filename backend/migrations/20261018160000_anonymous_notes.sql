@@ -0,0 +1,4 @@
+-- Anonymous notes are shown without their uploader's name. The uploader still owns the note and
+-- it still counts towards their reputation.
+ALTER TABLE notes
+    ADD COLUMN IF NOT EXISTS is_anonymous BOOLEAN NOT NULL DEFAULT FALSE;
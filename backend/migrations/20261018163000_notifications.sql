@@ -0,0 +1,20 @@
+-- In-app notifications. Similar events are grouped into a single unread notification, e.g. every
+-- upvote on a note until the uploader reads it, with `count` holding the number of events.
+CREATE TABLE IF NOT EXISTS notifications
+(
+    id          UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    user_id     UUID        NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    kind        VARCHAR(32) NOT NULL,
+    note_id     UUID        NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+    -- What events are grouped by: the note, or the comment that was replied to
+    subject_id  UUID        NOT NULL,
+    course_code VARCHAR(50) NOT NULL,
+    count       INT         NOT NULL DEFAULT 1,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    read_at     TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS notifications_unread_group_idx
+    ON notifications (user_id, kind, subject_id) WHERE read_at IS NULL;
+CREATE INDEX IF NOT EXISTS notifications_user_idx ON notifications (user_id, updated_at DESC);
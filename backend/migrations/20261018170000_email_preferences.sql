@@ -0,0 +1,11 @@
+-- Email settings of each user. Digests are opt-out, users without a row get the defaults.
+CREATE TABLE IF NOT EXISTS email_preferences
+(
+    user_id         UUID PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    weekly_digest   BOOLEAN     NOT NULL DEFAULT TRUE,
+    -- Sections of the digest: new notes in the user's courses and activity on their uploads
+    course_notes    BOOLEAN     NOT NULL DEFAULT TRUE,
+    upload_activity BOOLEAN     NOT NULL DEFAULT TRUE,
+    last_digest_at  TIMESTAMPTZ,
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
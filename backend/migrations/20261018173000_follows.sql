@@ -0,0 +1,15 @@
+-- Courses, professors and users followed by each user, whose new notes make up their feed
+CREATE TABLE IF NOT EXISTS follows
+(
+    id               UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    user_id          UUID        NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    kind             VARCHAR(16) NOT NULL,
+    -- Upper case course code, professor name as entered, or user ID
+    target           TEXT        NOT NULL,
+    -- Set when following a user, so that the follow goes away with their account
+    followed_user_id UUID REFERENCES users (id) ON DELETE CASCADE,
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS follows_target_idx ON follows (user_id, kind, LOWER(target));
+CREATE INDEX IF NOT EXISTS notes_course_code_upper_idx ON notes (UPPER(course_code));
@@ -0,0 +1,49 @@
+-- Endpoints registered by admins to be told about note events, e.g. by chat bots
+CREATE TABLE IF NOT EXISTS webhooks
+(
+    id          UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    url         TEXT        NOT NULL,
+    -- Shared with the receiver, every payload is signed with it
+    secret      TEXT        NOT NULL,
+    event_types TEXT[]      NOT NULL,
+    description TEXT,
+    is_active   BOOLEAN     NOT NULL DEFAULT TRUE,
+    created_by  UUID REFERENCES users (id) ON DELETE SET NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One row per event sent to a webhook. Pending rows are the queue read by the delivery worker.
+CREATE TABLE IF NOT EXISTS webhook_deliveries
+(
+    id              UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    webhook_id      UUID        NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    -- The same for every webhook the event is sent to
+    event_id        UUID        NOT NULL,
+    event_type      VARCHAR(32) NOT NULL,
+    payload         JSONB       NOT NULL,
+    -- pending, delivered, or failed once out of attempts
+    status          VARCHAR(16) NOT NULL DEFAULT 'pending',
+    attempts        INT         NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at    TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due_idx
+    ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS webhook_deliveries_webhook_idx ON webhook_deliveries (webhook_id, created_at DESC);
+
+-- Every attempt at a delivery, for the delivery log
+CREATE TABLE IF NOT EXISTS webhook_attempts
+(
+    id              UUID PRIMARY KEY     DEFAULT gen_random_uuid(),
+    delivery_id     UUID        NOT NULL REFERENCES webhook_deliveries (id) ON DELETE CASCADE,
+    response_status INT,
+    -- Why the attempt failed: the connection error, or the start of the response body
+    error           TEXT,
+    duration_ms     INT         NOT NULL,
+    attempted_at    TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS webhook_attempts_delivery_idx ON webhook_attempts (delivery_id, attempted_at);
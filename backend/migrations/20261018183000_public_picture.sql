@@ -0,0 +1,10 @@
+-- The picture shown to everyone else. Users who only show their initials get the default
+-- picture from the init migration instead of their Google picture.
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS public_picture TEXT NOT NULL GENERATED ALWAYS AS (
+        CASE
+            WHEN show_initials_only THEN
+                'https://lh3.googleusercontent.com/-XdUIqdMkCWA/AAAAAAAAAAI/AAAAAAAAAAA/4252rscbv5M/photo.jpg'
+            ELSE picture
+        END
+    ) STORED;
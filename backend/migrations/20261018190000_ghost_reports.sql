@@ -0,0 +1,5 @@
+-- Reports filed by deleted users are kept under the ghost user, which can end up holding several
+-- open reports on the same note
+DROP INDEX IF EXISTS note_reports_open_unique;
+CREATE UNIQUE INDEX IF NOT EXISTS note_reports_open_unique ON note_reports (note_id, reporter_id)
+    WHERE status = 'open' AND reporter_id <> '00000000-0000-0000-0000-000000000000';
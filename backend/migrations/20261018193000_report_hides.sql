@@ -0,0 +1,10 @@
+-- Whether a note is hidden only because it reached the report threshold. Dismissing its reports
+-- restores such a note, while notes hidden by an upheld report stay hidden.
+ALTER TABLE notes
+    ADD COLUMN IF NOT EXISTS hidden_by_reports BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE notes n
+SET hidden_by_reports = TRUE
+WHERE n.is_hidden
+  AND EXISTS (SELECT 1 FROM note_reports r WHERE r.note_id = n.id AND r.status = 'open')
+  AND NOT EXISTS (SELECT 1 FROM note_reports r WHERE r.note_id = n.id AND r.status = 'resolved');
@@ -6,6 +6,8 @@ pub enum AppError {
     Note(NoteError),
     User(UserError),
     Auth(AuthError),
+    Comment(CommentError),
+    Moderation(ModerationError),
 }
 
 impl IntoResponse for AppError {
@@ -14,11 +16,15 @@ impl IntoResponse for AppError {
             AppError::Note(err) => err.into_response(),
             AppError::User(err) => err.into_response(),
             AppError::Auth(err) => err.into_response(),
+            AppError::Comment(err) => err.into_response(),
+            AppError::Moderation(err) => err.into_response(),
         }
     }
 }
 
 pub enum UserError {
+    InvalidData(String),
+    NotFound(String),
     Conflict(String, Box<dyn std::error::Error>),
     Unknown(String, Box<dyn std::error::Error>),
 }
@@ -32,6 +38,8 @@ impl From<UserError> for AppError {
 impl IntoResponse for UserError {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
+            UserError::InvalidData(msg) => (StatusCode::BAD_REQUEST, msg),
+            UserError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
             UserError::Conflict(msg, err) => {
                 tracing::error!("User conflict error: {}", err);
                 (StatusCode::CONFLICT, msg)
@@ -106,9 +114,15 @@ impl IntoResponse for AuthError {
 #[derive(Debug)]
 pub enum NoteError {
     InvalidData(String),
+    NotFound(String),
+    Forbidden(String),
     UploadFailed(String),
     DatabaseError(String, Box<dyn std::error::Error>),
     BadVote(String),
+    /// A feature that depends on something this server does not have, e.g. the PDF library
+    Unavailable(String),
+    /// Anything else that went wrong on the server's side, e.g. building a course pack
+    Internal(String, Box<dyn std::error::Error>),
 }
 
 impl From<NoteError> for AppError {
@@ -121,6 +135,8 @@ impl IntoResponse for NoteError {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
             NoteError::InvalidData(msg) => (StatusCode::BAD_REQUEST, msg),
+            NoteError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            NoteError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
             NoteError::UploadFailed(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             NoteError::DatabaseError(msg, err) => {
                 tracing::error!("Database error: {:?}", err);
@@ -130,6 +146,85 @@ impl IntoResponse for NoteError {
                 )
             }
             NoteError::BadVote(msg) => (StatusCode::BAD_REQUEST, msg),
+            NoteError::Unavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            NoteError::Internal(msg, err) => {
+                tracing::error!("Internal error: {}: {:?}", msg, err);
+                (StatusCode::INTERNAL_SERVER_ERROR, msg)
+            }
+        };
+
+        Response::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Methods", "*")
+            .header("Access-Control-Allow-Headers", "content-type, authorization, accept, origin, x-requested-with")
+            .body(Body::from(format!(r#"{{"error": "{}"}}"#, error_message)))
+            .unwrap()
+    }
+}
+
+#[derive(Debug)]
+pub enum CommentError {
+    InvalidData(String),
+    NotFound(String),
+    Forbidden(String),
+    DatabaseError(String, Box<dyn std::error::Error>),
+}
+
+impl From<CommentError> for AppError {
+    fn from(err: CommentError) -> Self {
+        AppError::Comment(err)
+    }
+}
+
+impl IntoResponse for CommentError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = match self {
+            CommentError::InvalidData(msg) => (StatusCode::BAD_REQUEST, msg),
+            CommentError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            CommentError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
+            CommentError::DatabaseError(msg, err) => {
+                tracing::error!("Comment database error: {:?}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, msg)
+            }
+        };
+
+        Response::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Methods", "*")
+            .header("Access-Control-Allow-Headers", "content-type, authorization, accept, origin, x-requested-with")
+            .body(Body::from(format!(r#"{{"error": "{}"}}"#, error_message)))
+            .unwrap()
+    }
+}
+
+#[derive(Debug)]
+pub enum ModerationError {
+    InvalidData(String),
+    NotFound(String),
+    Conflict(String),
+    DatabaseError(String, Box<dyn std::error::Error>),
+}
+
+impl From<ModerationError> for AppError {
+    fn from(err: ModerationError) -> Self {
+        AppError::Moderation(err)
+    }
+}
+
+impl IntoResponse for ModerationError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = match self {
+            ModerationError::InvalidData(msg) => (StatusCode::BAD_REQUEST, msg),
+            ModerationError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            ModerationError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            ModerationError::DatabaseError(msg, err) => {
+                tracing::error!("Moderation database error: {:?}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, msg)
+            }
         };
 
         Response::builder()
@@ -0,0 +1,230 @@
+use crate::api::errors::{AppError, UserError};
+use crate::api::handlers::audit;
+use crate::api::handlers::bulk::{unique_name, zip_response};
+use crate::api::handlers::files::note_file_name;
+use crate::api::models::{ResponseBadge, ResponseCurrentUser, ResponseFollow};
+use crate::api::router::RouterState;
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::badges::get_badges_for_users;
+use crate::db::handlers::comments::get_comments_by_user;
+use crate::db::handlers::follows::get_follows;
+use crate::db::handlers::notes::{get_downloads_by_user, get_notes_by_uploader};
+use crate::db::handlers::notifications::get_all_notifications;
+use crate::db::handlers::reports::get_reports_by_reporter;
+use crate::db::handlers::users::{delete_user_account, AccountNotes};
+use crate::db::handlers::votes::get_votes_by_user;
+use crate::db::models::{Comment, DBVote, DownloadEvent, Note, NoteReport, Notification, User};
+use crate::events::{self, Event};
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tokio::io::DuplexStream;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct DeleteAccountQuery {
+    pub notes: AccountNotes,
+}
+
+#[derive(Serialize)]
+struct ExportedNote {
+    /// Path of the PDF within the archive, `None` if the file could not be read
+    file: Option<String>,
+    #[serde(flatten)]
+    note: Note,
+}
+
+/// Everything stored about a user, gathered before the archive is written
+struct AccountExport {
+    profile: ResponseCurrentUser,
+    notes: Vec<Note>,
+    votes: Vec<DBVote>,
+    comments: Vec<Comment>,
+    follows: Vec<ResponseFollow>,
+    reports: Vec<NoteReport>,
+    downloads: Vec<DownloadEvent>,
+    notifications: Vec<Notification>,
+}
+
+async fn write_json<T: Serialize>(
+    zip: &mut ZipFileWriter<Compat<DuplexStream>>,
+    name: &str,
+    value: &T,
+) -> color_eyre::Result<()> {
+    zip.write_entry_whole(
+        ZipEntryBuilder::new(name.to_string().into(), Compression::Deflate),
+        &serde_json::to_vec_pretty(value)?,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Writes each uploaded note under `notes/`, then the profile, notes, votes, comments, follows,
+/// reports, downloads and notifications as JSON
+async fn write_export(
+    state: &RouterState,
+    export: AccountExport,
+    writer: DuplexStream,
+) -> color_eyre::Result<()> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    let mut used_names = HashSet::new();
+    let mut notes = Vec::with_capacity(export.notes.len());
+
+    for note in export.notes {
+        let path = state.env_vars.paths.get_note_path(&format!("{}.pdf", note.id));
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(err) => {
+                tracing::warn!("Skipping note {} in account export: {}", note.id, err);
+                notes.push(ExportedNote { file: None, note });
+                continue;
+            }
+        };
+
+        let name = format!(
+            "notes/{}",
+            unique_name(
+                &mut used_names,
+                note_file_name(&note.course_code, note.note_year, &note.note_semester),
+            )
+        );
+        let mut entry = zip
+            .write_entry_stream(ZipEntryBuilder::new(name.clone().into(), Compression::Stored))
+            .await?;
+        futures_util::io::copy(&mut file.compat(), &mut entry).await?;
+        entry.close().await?;
+
+        notes.push(ExportedNote { file: Some(name), note });
+    }
+
+    write_json(&mut zip, "profile.json", &export.profile).await?;
+    write_json(&mut zip, "notes.json", &notes).await?;
+    write_json(&mut zip, "votes.json", &export.votes).await?;
+    write_json(&mut zip, "comments.json", &export.comments).await?;
+    write_json(&mut zip, "follows.json", &export.follows).await?;
+    write_json(&mut zip, "reports.json", &export.reports).await?;
+    write_json(&mut zip, "downloads.json", &export.downloads).await?;
+    write_json(&mut zip, "notifications.json", &export.notifications).await?;
+    zip.close().await?;
+
+    Ok(())
+}
+
+/// API handler to download a ZIP archive of everything stored about the signed in user: their
+/// profile, votes, comments, follows, reports, downloads and notifications, and the notes they
+/// uploaded
+pub async fn export_account(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+) -> Result<(StatusCode, Response), AppError> {
+    let badges = get_badges_for_users(&state.db_wrapper, &[user.id])
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch badges".to_string(), err.into()))?;
+    let notes = get_notes_by_uploader(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch notes".to_string(), err.into()))?;
+    let votes = get_votes_by_user(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch votes".to_string(), err.into()))?;
+    let comments = get_comments_by_user(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch comments".to_string(), err.into()))?;
+    let follows = get_follows(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch follows".to_string(), err.into()))?;
+    let reports = get_reports_by_reporter(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch reports".to_string(), err.into()))?;
+    let downloads = get_downloads_by_user(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch downloads".to_string(), err.into()))?;
+    let notifications = get_all_notifications(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch notifications".to_string(), err.into()))?;
+
+    let export = AccountExport {
+        profile: ResponseCurrentUser {
+            badges: ResponseBadge::group_by_user(badges)
+                .remove(&user.id)
+                .unwrap_or_default(),
+            user,
+        },
+        notes,
+        votes,
+        comments,
+        follows: follows.into_iter().map(ResponseFollow::from).collect(),
+        reports,
+        downloads,
+        notifications,
+    };
+
+    let file_name = format!("cfmn_account_{}.zip", Utc::now().format("%Y-%m-%d"));
+    let response = zip_response(&file_name, move |writer| async move {
+        write_export(&state, export, writer).await
+    });
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Serialize)]
+struct DeletedAccount {
+    notes: AccountNotes,
+    deleted_notes: Vec<Uuid>,
+    deleted_at: DateTime<Utc>,
+}
+
+/// API handler to delete the signed in user's account. `?notes=delete` deletes their notes along
+/// with it, `?notes=anonymise` keeps them up under the ghost user.
+pub async fn delete_account(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Query(query): Query<DeleteAccountQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    let deleted_notes = delete_user_account(&state.db_wrapper, user.id, query.notes)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to delete account".to_string(), err.into()))?;
+
+    for note in &deleted_notes {
+        let file_path = state.env_vars.paths.get_note_path(&format!("{}.pdf", note.id));
+        let preview_path = state.env_vars.paths.get_preview_path(&format!("{}.jpg", note.id));
+        let _ = tokio::fs::remove_file(file_path).await;
+        let _ = tokio::fs::remove_file(preview_path).await;
+    }
+
+    let deleted = DeletedAccount {
+        notes: query.notes,
+        deleted_notes: deleted_notes.iter().map(|note| note.id).collect(),
+        deleted_at: Utc::now(),
+    };
+
+    // This entry only holds the user's ID. Earlier entries about them, such as profile changes,
+    // stay in the audit log as it cannot be edited.
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "user.delete", "user", user.id).after(&deleted),
+    )
+    .await;
+    for note in deleted_notes {
+        events::publish(
+            &state.db_wrapper,
+            &state.env_vars.paths,
+            Event::NoteDeleted {
+                note_id: note.id,
+                course_code: note.course_code,
+                course_name: note.course_name,
+            },
+        )
+        .await;
+    }
+
+    Ok((StatusCode::OK, Json(deleted).into_response()))
+}
@@ -0,0 +1,290 @@
+use crate::api::errors::{AppError, UserError};
+use crate::api::handlers::audit;
+use crate::api::router::RouterState;
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::leaderboard::{get_leaderboard_stats, LeaderboardWindow};
+use crate::db::handlers::users::{
+    ban_user, find_user_by_id, reinstate_user, set_user_role, suspend_user,
+};
+use crate::db::models::{Role, User};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{Duration, Utc};
+use crate::scoring::{ReputationFormula, ReputationStats};
+use serde::{Deserialize, Serialize};
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct RoleRequest {
+    pub role: Role,
+}
+
+#[derive(Deserialize)]
+pub struct SuspendRequest {
+    pub duration_hours: i64,
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct BanRequest {
+    pub reason: String,
+    #[serde(default)]
+    pub hide_notes: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ReputationPreviewRequest {
+    /// The proposed formula, e.g. `"formula": "weighted", "upvote": 4.0`
+    #[serde(flatten)]
+    pub formula: ReputationFormula,
+    #[serde(default)]
+    pub window: LeaderboardWindow,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ReputationPreviewEntry {
+    id: Uuid,
+    display_name: String,
+    total_notes: i64,
+    total_upvotes: i64,
+    total_downvotes: i64,
+    total_downloads: i64,
+    current_reputation: f64,
+    current_rank: i64,
+    reputation: f64,
+    rank: i64,
+}
+
+#[derive(Serialize)]
+struct ReputationPreview {
+    current: ReputationFormula,
+    proposed: ReputationFormula,
+    window: &'static str,
+    entries: Vec<ReputationPreviewEntry>,
+}
+
+/// Looks up the target of a sanction, making sure admins cannot sanction themselves or each other
+async fn fetch_sanction_target(
+    state: &RouterState,
+    admin: &User,
+    user_id: Uuid,
+) -> Result<User, UserError> {
+    if admin.id == user_id {
+        return Err(UserError::InvalidData("Admins cannot sanction themselves".to_string()));
+    }
+
+    let user = find_user_by_id(&state.db_wrapper, user_id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch user".to_string(), err.into()))?
+        .ok_or(UserError::NotFound("User not found".to_string()))?;
+
+    if user.role == Role::Admin {
+        return Err(UserError::InvalidData(
+            "Admins must be demoted before they can be sanctioned".to_string(),
+        ));
+    }
+    Ok(user)
+}
+
+fn validate_reason(reason: &str) -> Result<String, UserError> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(UserError::InvalidData("A reason is required".to_string()));
+    }
+    Ok(reason.to_string())
+}
+
+/// API handler to change the role of a user
+pub async fn set_role_handler(
+    State(state): State<RouterState>,
+    Extension(admin): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<RoleRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    if admin.id == user_id && payload.role != Role::Admin {
+        return Err(UserError::InvalidData("Admins cannot demote themselves".to_string()).into());
+    }
+
+    let existing_user = find_user_by_id(&state.db_wrapper, user_id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch user".to_string(), err.into()))?
+        .ok_or(UserError::NotFound("User not found".to_string()))?;
+
+    let user = set_user_role(&state.db_wrapper, user_id, payload.role)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to update user role".to_string(), err.into()))?
+        .ok_or(UserError::NotFound("User not found".to_string()))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(admin.id), "user.role", "user", user_id)
+            .before(&existing_user)
+            .after(&user),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(user).into_response()))
+}
+
+/// API handler to suspend a user for a number of hours
+pub async fn suspend_user_handler(
+    State(state): State<RouterState>,
+    Extension(admin): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<SuspendRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let reason = validate_reason(&payload.reason)?;
+    if payload.duration_hours <= 0 {
+        return Err(UserError::InvalidData("Suspension duration must be positive".to_string()).into());
+    }
+    let until = Duration::try_hours(payload.duration_hours)
+        .and_then(|duration| Utc::now().checked_add_signed(duration))
+        .ok_or(UserError::InvalidData("Suspension duration is too long".to_string()))?;
+
+    let existing_user = fetch_sanction_target(&state, &admin, user_id).await?;
+
+    let user = suspend_user(&state.db_wrapper, user_id, until, &reason, admin.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to suspend user".to_string(), err.into()))?
+        .ok_or(UserError::NotFound("User not found".to_string()))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(admin.id), "user.suspend", "user", user_id)
+            .before(&existing_user)
+            .after(&user),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(user).into_response()))
+}
+
+/// API handler to ban a user permanently
+pub async fn ban_user_handler(
+    State(state): State<RouterState>,
+    Extension(admin): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<BanRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let reason = validate_reason(&payload.reason)?;
+    let existing_user = fetch_sanction_target(&state, &admin, user_id).await?;
+
+    let user = ban_user(&state.db_wrapper, user_id, &reason, payload.hide_notes, admin.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to ban user".to_string(), err.into()))?
+        .ok_or(UserError::NotFound("User not found".to_string()))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(admin.id), "user.ban", "user", user_id)
+            .before(&existing_user)
+            .after(&user),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(user).into_response()))
+}
+
+/// API handler to lift a suspension or ban
+pub async fn reinstate_user_handler(
+    State(state): State<RouterState>,
+    Extension(admin): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(user_id): Path<Uuid>,
+) -> Result<(StatusCode, Response), AppError> {
+    let existing_user = find_user_by_id(&state.db_wrapper, user_id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch user".to_string(), err.into()))?
+        .ok_or(UserError::NotFound("User not found".to_string()))?;
+
+    let user = reinstate_user(&state.db_wrapper, user_id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to reinstate user".to_string(), err.into()))?
+        .ok_or(UserError::NotFound("User not found".to_string()))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(admin.id), "user.reinstate", "user", user_id)
+            .before(&existing_user)
+            .after(&user),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(user).into_response()))
+}
+
+/// API handler to preview the leaderboard under a proposed reputation formula. Nothing is
+/// changed, the formula in use is set through the config.
+pub async fn preview_reputation_handler(
+    State(state): State<RouterState>,
+    Json(payload): Json<ReputationPreviewRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let limit = payload.limit.unwrap_or(20).clamp(1, 200) as usize;
+    if let ReputationFormula::Weighted(weights) = &payload.formula {
+        weights.validate().map_err(UserError::InvalidData)?;
+    }
+
+    let stats = get_leaderboard_stats(&state.db_wrapper, payload.window)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch leaderboard".to_string(), err.into()))?;
+
+    let scorer = payload.formula.scorer();
+    let mut entries: Vec<ReputationPreviewEntry> = stats
+        .into_iter()
+        .map(|entry| ReputationPreviewEntry {
+            reputation: scorer.score(&ReputationStats {
+                notes: entry.total_notes,
+                upvotes: entry.total_upvotes,
+                downvotes: entry.total_downvotes,
+                downloads: entry.total_downloads,
+            }),
+            rank: 0,
+            id: entry.id,
+            display_name: entry.display_name,
+            total_notes: entry.total_notes,
+            total_upvotes: entry.total_upvotes,
+            total_downvotes: entry.total_downvotes,
+            total_downloads: entry.total_downloads,
+            current_reputation: entry.reputation,
+            current_rank: entry.rank,
+        })
+        .collect();
+
+    // Same order and tie breaking as the leaderboard
+    entries.sort_by(|a, b| {
+        b.reputation
+            .total_cmp(&a.reputation)
+            .then(b.total_notes.cmp(&a.total_notes))
+    });
+    for i in 0..entries.len() {
+        entries[i].rank = match i.checked_sub(1).map(|prev| &entries[prev]) {
+            Some(prev)
+                if prev.reputation == entries[i].reputation
+                    && prev.total_notes == entries[i].total_notes =>
+            {
+                prev.rank
+            }
+            _ => i as i64 + 1,
+        };
+    }
+    entries.truncate(limit);
+
+    let preview = ReputationPreview {
+        current: state.env_vars.reputation,
+        proposed: payload.formula,
+        window: payload.window.as_str(),
+        entries,
+    };
+    Ok((StatusCode::OK, Json(preview).into_response()))
+}
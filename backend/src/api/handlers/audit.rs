@@ -0,0 +1,63 @@
+use crate::api::errors::{AppError, UserError};
+use crate::api::router::RouterState;
+use crate::db::handlers::audit::{get_audit_events, insert_audit_event, AuditFilter, NewAuditEvent};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+const MAX_AUDIT_EVENTS: i64 = 500;
+
+/// Appends an event to the audit log, tagged with the id of the current request.
+///
+/// A failure to write the log is reported but never fails the action it describes.
+pub(crate) async fn record(state: &RouterState, request_id: &RequestId, event: NewAuditEvent) {
+    let request_id = request_id.header_value().to_str().ok().map(String::from);
+    let action = event.action.clone();
+
+    if let Err(err) = insert_audit_event(&state.db_wrapper, event.request_id(request_id)).await {
+        tracing::error!("Failed to write audit event {}: {:?}", action, err);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    pub actor_id: Option<Uuid>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<Uuid>,
+    pub request_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+/// API handler to search the audit log
+pub async fn list_audit_events(
+    State(state): State<RouterState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    let filter = AuditFilter {
+        actor_id: query.actor_id,
+        action: query.action,
+        target_type: query.target_type,
+        target_id: query.target_id,
+        request_id: query.request_id,
+        since: query.since,
+        until: query.until,
+        limit: query.limit.unwrap_or(100).clamp(1, MAX_AUDIT_EVENTS),
+    };
+
+    match get_audit_events(&state.db_wrapper, filter).await {
+        Ok(events) => Ok((StatusCode::OK, Json(events).into_response())),
+        Err(err) => Err(UserError::Unknown(
+            "Failed to fetch audit events".to_string(),
+            err.into(),
+        )
+        .into()),
+    }
+}
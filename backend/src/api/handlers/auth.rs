@@ -1,7 +1,9 @@
 use crate::api::middleware::AppClaims;
-use crate::api::errors::AppError;
+use crate::api::errors::{AppError, UserError};
+use crate::api::models::{ResponseBadge, ResponseCurrentUser};
 use crate::api::router::RouterState;
-use crate::db::handlers::users::{find_or_create_user, GoogleUserInfo};
+use crate::db::handlers::badges::get_badges_for_users;
+use crate::db::handlers::users::{find_or_create_user, set_user_role, GoogleUserInfo};
 use axum::extract::State;
 use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
@@ -11,7 +13,7 @@ use reqwest;
 use serde::Deserialize;
 use std::collections::{HashMap};
 use serde_json::json;
-use crate::db::models::User;
+use crate::db::models::{Role, User};
 
 #[derive(Deserialize)]
 pub struct AuthRequest {
@@ -96,7 +98,7 @@ pub async fn google_auth_callback(
         )
     })?;
 
-    let google_client_id = state.env_vars.google_client_id;
+    let google_client_id = state.env_vars.google_client_id.clone();
     let mut validation = Validation::new(header.alg);
     validation.set_audience(&[google_client_id]);
 
@@ -136,6 +138,21 @@ pub async fn google_auth_callback(
             )),
         })?;
 
+    // Bootstrap the configured admins, every other role change goes through the admin API
+    let user = if user.role != Role::Admin && state.env_vars.is_admin_email(&user.email) {
+        set_user_role(&state.db_wrapper, user.id, Role::Admin)
+            .await
+            .map_err(|e| {
+                AppError::User(crate::api::errors::UserError::Unknown(
+                    "Failed to update user role".to_string(),
+                    e.into(),
+                ))
+            })?
+            .unwrap_or(user)
+    } else {
+        user
+    };
+
     let expiration = Utc::now() + Duration::seconds(state.env_vars.expiration_time_seconds);
     let app_claims = AppClaims {
         google_id: user.google_id.clone(),
@@ -159,9 +176,16 @@ pub async fn google_auth_callback(
 }
 
 pub async fn get_current_user(
-    State(_state): State<RouterState>,
+    State(state): State<RouterState>,
     Extension(user): Extension<User>,
 ) -> Result<Response, AppError> {
-    Ok(Json(user).into_response())
+    let badges = get_badges_for_users(&state.db_wrapper, &[user.id])
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch badges".to_string(), err.into()))?;
+    let badges = ResponseBadge::group_by_user(badges)
+        .remove(&user.id)
+        .unwrap_or_default();
+
+    Ok(Json(ResponseCurrentUser { user, badges }).into_response())
 }
 
@@ -0,0 +1,277 @@
+use crate::api::errors::{AppError, NoteError};
+use crate::api::handlers::files::{note_file_name, safe_file_stem};
+use crate::api::router::RouterState;
+use crate::db::handlers::notes::{
+    get_downloadable_notes_by_course, get_downloadable_notes_by_ids, record_note_download,
+};
+use crate::db::models::{DownloadableNote, User};
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+use std::io;
+use tokio::fs::File;
+use tokio::io::DuplexStream;
+use tokio::sync::oneshot;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+const MAX_NOTES_PER_ARCHIVE: usize = 100;
+
+#[derive(Deserialize)]
+pub struct BulkDownloadRequest {
+    pub note_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    id: Uuid,
+    file: String,
+    course_code: String,
+    course_name: String,
+    year: i64,
+    semester: String,
+    professors: Vec<String>,
+    description: Option<String>,
+    uploader: String,
+    uploaded_at: DateTime<Utc>,
+}
+
+/// Written into every archive as `manifest.json`
+#[derive(Serialize)]
+struct Manifest {
+    generated_at: DateTime<Utc>,
+    notes: Vec<ManifestEntry>,
+    /// Notes whose file could not be read
+    missing: Vec<Uuid>,
+}
+
+/// Picks a file name that is not already in the archive by adding a counter before the extension
+pub(crate) fn unique_name(used: &mut HashSet<String>, name: String) -> String {
+    let (stem, ext) = name.rsplit_once('.').unwrap_or((&name, ""));
+    let mut candidate = name.clone();
+    let mut counter = 2;
+    while used.contains(&candidate) {
+        candidate = format!("{}_{}.{}", stem, counter, ext);
+        counter += 1;
+    }
+    used.insert(candidate.clone());
+    candidate
+}
+
+/// Opens the file of every note before anything is streamed, so notes whose file is missing or
+/// unreadable are listed as missing in the manifest rather than breaking the archive partway.
+async fn open_note_files(
+    state: &RouterState,
+    notes: Vec<DownloadableNote>,
+) -> (Vec<(DownloadableNote, File)>, Vec<Uuid>) {
+    let mut files = Vec::with_capacity(notes.len());
+    let mut missing = Vec::new();
+    for note in notes {
+        let path = state.env_vars.paths.get_note_path(&format!("{}.pdf", note.id));
+        match File::open(&path).await {
+            Ok(file) => files.push((note, file)),
+            Err(err) => {
+                tracing::warn!("Skipping note {} in archive: {}", note.id, err);
+                missing.push(note.id);
+            }
+        }
+    }
+    (files, missing)
+}
+
+/// Writes the notes and the manifest into a ZIP archive, one file at a time.
+async fn write_archive(
+    state: &RouterState,
+    user: &User,
+    files: Vec<(DownloadableNote, File)>,
+    missing: Vec<Uuid>,
+    writer: DuplexStream,
+) -> color_eyre::Result<()> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    let mut used_names = HashSet::new();
+    let mut manifest = Manifest {
+        generated_at: Utc::now(),
+        notes: Vec::with_capacity(files.len()),
+        missing,
+    };
+    let dedup_since = Utc::now() - state.env_vars.download_dedup_window;
+
+    for (note, file) in files {
+        let name = unique_name(
+            &mut used_names,
+            note_file_name(&note.course_code, note.note_year, &note.note_semester),
+        );
+
+        // PDFs are already compressed, so they are stored as they are
+        let mut entry = zip
+            .write_entry_stream(ZipEntryBuilder::new(name.clone().into(), Compression::Stored))
+            .await?;
+        futures_util::io::copy(&mut file.compat(), &mut entry).await?;
+        entry.close().await?;
+
+        if let Err(err) =
+            record_note_download(&state.db_wrapper, note.id, Some(user.id), None, dedup_since).await
+        {
+            tracing::error!("Failed to record download of note {}: {:?}", note.id, err);
+        }
+
+        manifest.notes.push(ManifestEntry {
+            id: note.id,
+            file: name,
+            course_code: note.course_code,
+            course_name: note.course_name,
+            year: note.note_year,
+            semester: note.note_semester,
+            professors: note.professor_names.unwrap_or_default(),
+            description: note.description,
+            uploader: note.uploader_name,
+            uploaded_at: note.created_at,
+        });
+    }
+
+    zip.write_entry_whole(
+        ZipEntryBuilder::new("manifest.json".to_string().into(), Compression::Deflate),
+        &serde_json::to_vec_pretty(&manifest)?,
+    )
+    .await?;
+    zip.close().await?;
+
+    Ok(())
+}
+
+/// Streams a ZIP archive of the notes along with their manifest
+async fn stream_archive(
+    state: RouterState,
+    user: User,
+    notes: Vec<DownloadableNote>,
+    file_name: &str,
+) -> (StatusCode, Response) {
+    let (files, missing) = open_note_files(&state, notes).await;
+    let response = zip_response(file_name, move |writer| async move {
+        write_archive(&state, &user, files, missing, writer).await
+    });
+    (StatusCode::OK, response)
+}
+
+/// Responds with a ZIP archive that `write` writes on a separate task into a small in-memory
+/// pipe, so only a chunk of it is held in memory at any time. Should writing fail partway, the
+/// body ends with an error, which aborts the response instead of passing a truncated archive off
+/// as a complete one.
+pub(crate) fn zip_response<F, Fut>(file_name: &str, write: F) -> Response
+where
+    F: FnOnce(DuplexStream) -> Fut + Send + 'static,
+    Fut: Future<Output = color_eyre::Result<()>> + Send + 'static,
+{
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let (written_tx, written_rx) = oneshot::channel();
+
+    let archive_name = file_name.to_string();
+    tokio::spawn(async move {
+        let result = write(writer).await;
+        if let Err(err) = &result {
+            tracing::error!("Failed to write ZIP archive {}: {:?}", archive_name, err);
+        }
+        let _ = written_tx.send(result.is_ok());
+    });
+
+    // Polled once the pipe is closed, which happens before the result is sent
+    let failure = stream::once(written_rx).filter_map(|written| async move {
+        (written != Ok(true)).then(|| Err(io::Error::other("Failed to write ZIP archive")))
+    });
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        )
+        .body(Body::from_stream(ReaderStream::new(reader).chain(failure)))
+        .unwrap()
+}
+
+/// API handler to download every note of a course as a ZIP archive
+pub async fn download_course_zip(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Path(course_code): Path<String>,
+) -> Result<(StatusCode, Response), AppError> {
+    let notes =
+        get_downloadable_notes_by_course(&state.db_wrapper, &course_code, MAX_NOTES_PER_ARCHIVE as i64)
+            .await
+            .map_err(|err| NoteError::DatabaseError("Failed to fetch notes".to_string(), err.into()))?;
+    if notes.is_empty() {
+        return Err(NoteError::NotFound("No notes found for this course".to_string()).into());
+    }
+
+    let file_name = format!("{}_notes.zip", safe_file_stem(&course_code));
+    Ok(stream_archive(state, user, notes, &file_name).await)
+}
+
+/// API handler to download a selection of notes as a ZIP archive
+pub async fn bulk_download(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<BulkDownloadRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    if payload.note_ids.is_empty() {
+        return Err(NoteError::InvalidData("No notes selected".to_string()).into());
+    }
+    if payload.note_ids.len() > MAX_NOTES_PER_ARCHIVE {
+        return Err(NoteError::InvalidData(format!(
+            "Only up to {} notes can be downloaded at once",
+            MAX_NOTES_PER_ARCHIVE
+        ))
+        .into());
+    }
+
+    let notes = get_downloadable_notes_by_ids(&state.db_wrapper, &payload.note_ids)
+        .await
+        .map_err(|err| NoteError::DatabaseError("Failed to fetch notes".to_string(), err.into()))?;
+    if notes.is_empty() {
+        return Err(NoteError::NotFound("None of the selected notes were found".to_string()).into());
+    }
+
+    Ok(stream_archive(state, user, notes, "notes.zip").await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_names_are_kept() {
+        let mut used = HashSet::new();
+        assert_eq!(unique_name(&mut used, "CS10001_2024_Autumn.pdf".to_string()), "CS10001_2024_Autumn.pdf");
+        assert_eq!(unique_name(&mut used, "CS10002_2024_Autumn.pdf".to_string()), "CS10002_2024_Autumn.pdf");
+    }
+
+    #[test]
+    fn repeated_names_get_a_counter_before_the_extension() {
+        let mut used = HashSet::new();
+        let names: Vec<String> =
+            (0..3).map(|_| unique_name(&mut used, "CS10001_2024_Autumn.pdf".to_string())).collect();
+        assert_eq!(
+            names,
+            ["CS10001_2024_Autumn.pdf", "CS10001_2024_Autumn_2.pdf", "CS10001_2024_Autumn_3.pdf"]
+        );
+    }
+
+    #[test]
+    fn counters_skip_names_already_taken() {
+        let mut used = HashSet::from(["notes.pdf".to_string(), "notes_2.pdf".to_string()]);
+        assert_eq!(unique_name(&mut used, "notes.pdf".to_string()), "notes_3.pdf");
+        // Only the last extension is split off
+        used.insert("notes.v2.pdf".to_string());
+        assert_eq!(unique_name(&mut used, "notes.v2.pdf".to_string()), "notes.v2_2.pdf");
+    }
+}
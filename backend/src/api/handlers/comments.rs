@@ -0,0 +1,227 @@
+use crate::api::errors::{AppError, CommentError};
+use crate::api::handlers::audit;
+use crate::api::handlers::files::fetch_visible_note;
+use crate::api::models::{ResponseComment, ResponseCommentPage};
+use crate::api::router::RouterState;
+use crate::events::{self, Event};
+use crate::db::handlers::comments::{
+    count_root_comments, create_comment, delete_comment, get_comment_by_id, get_comment_threads,
+    update_comment,
+};
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::models::{Comment, User};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+const MAX_COMMENT_LENGTH: usize = 5000;
+const MAX_COMMENTS_PER_PAGE: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct CommentPageQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCommentRequest {
+    pub body: String,
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCommentRequest {
+    pub body: String,
+}
+
+fn validate_body(body: &str) -> Result<String, CommentError> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(CommentError::InvalidData("Comment cannot be empty".to_string()));
+    }
+    if body.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(CommentError::InvalidData(format!(
+            "Comment is too long. Only up to {} characters are allowed.",
+            MAX_COMMENT_LENGTH
+        )));
+    }
+    Ok(body.to_string())
+}
+
+/// Fetches a comment and makes sure it belongs to the note in the request path.
+async fn fetch_note_comment(
+    state: &RouterState,
+    note_id: Uuid,
+    comment_id: Uuid,
+) -> Result<Comment, CommentError> {
+    let comment = get_comment_by_id(&state.db_wrapper, comment_id)
+        .await
+        .map_err(|err| {
+            CommentError::DatabaseError("Failed to fetch comment".to_string(), err.into())
+        })?;
+
+    match comment {
+        Some(comment) if comment.note_id == note_id => Ok(comment),
+        _ => Err(CommentError::NotFound("Comment not found".to_string())),
+    }
+}
+
+/// API handler to list the comment threads on a note, paginated by top-level comment. The
+/// comments on a hidden note are only visible to those who can see the note.
+pub async fn list_comments(
+    State(state): State<RouterState>,
+    Extension(user): Extension<Option<User>>,
+    Path(note_id): Path<Uuid>,
+    Query(query): Query<CommentPageQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    let per_page = query.per_page.unwrap_or(20).clamp(1, MAX_COMMENTS_PER_PAGE);
+    let page = query.page.unwrap_or(1).clamp(1, i64::MAX / per_page);
+
+    fetch_visible_note(&state, user.as_ref(), note_id).await?;
+
+    let comments = get_comment_threads(&state.db_wrapper, note_id, per_page, (page - 1) * per_page)
+        .await
+        .map_err(|err| {
+            CommentError::DatabaseError("Failed to fetch comments".to_string(), err.into())
+        })?;
+    let total = count_root_comments(&state.db_wrapper, note_id)
+        .await
+        .map_err(|err| {
+            CommentError::DatabaseError("Failed to count comments".to_string(), err.into())
+        })?;
+
+    let response = ResponseCommentPage {
+        comments: ResponseComment::build_threads(comments),
+        page,
+        per_page,
+        total,
+    };
+    Ok((StatusCode::OK, Json(response).into_response()))
+}
+
+/// API handler to comment on a note or reply to an existing comment. Hidden notes can only be
+/// commented on by those who can see them.
+pub async fn add_comment(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(note_id): Path<Uuid>,
+    Json(payload): Json<CreateCommentRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let body = validate_body(&payload.body)?;
+    fetch_visible_note(&state, Some(&user), note_id).await?;
+
+    if let Some(parent_id) = payload.parent_id {
+        let parent = fetch_note_comment(&state, note_id, parent_id).await?;
+        if parent.deleted_at.is_some() {
+            return Err(CommentError::InvalidData(
+                "Cannot reply to a deleted comment".to_string(),
+            )
+            .into());
+        }
+    }
+
+    let comment = create_comment(&state.db_wrapper, note_id, user.id, payload.parent_id, &body)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::Database(err) if err.constraint() == Some("comments_note_id_fkey") => {
+                CommentError::NotFound("Note not found".to_string())
+            }
+            err => CommentError::DatabaseError("Failed to add comment".to_string(), err.into()),
+        })?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "comment.create", "comment", comment.id).after(&comment),
+    )
+    .await;
+    events::publish(
+        &state.db_wrapper,
+        &state.env_vars.paths,
+        Event::CommentAdded {
+            note_id,
+            author_id: user.id,
+            parent_id: comment.parent_id,
+        },
+    )
+    .await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ResponseComment::from_comment(comment, &user)).into_response(),
+    ))
+}
+
+/// API handler to edit a comment (author only)
+pub async fn update_comment_handler(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path((note_id, comment_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<UpdateCommentRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let body = validate_body(&payload.body)?;
+    let existing_comment = fetch_note_comment(&state, note_id, comment_id).await?;
+
+    if existing_comment.user_id != user.id {
+        return Err(CommentError::Forbidden("You can only edit your own comments".to_string()).into());
+    }
+    if existing_comment.deleted_at.is_some() {
+        return Err(CommentError::InvalidData("Cannot edit a deleted comment".to_string()).into());
+    }
+
+    let comment = update_comment(&state.db_wrapper, comment_id, &body)
+        .await
+        .map_err(|err| {
+            CommentError::DatabaseError("Failed to update comment".to_string(), err.into())
+        })?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "comment.update", "comment", comment_id)
+            .before(&existing_comment)
+            .after(&comment),
+    )
+    .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(ResponseComment::from_comment(comment, &user)).into_response(),
+    ))
+}
+
+/// API handler to delete a comment (author or moderator). Replies to the comment are kept.
+pub async fn delete_comment_handler(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path((note_id, comment_id)): Path<(Uuid, Uuid)>,
+) -> Result<(StatusCode, Response), AppError> {
+    let existing_comment = fetch_note_comment(&state, note_id, comment_id).await?;
+
+    if existing_comment.user_id != user.id && !user.is_moderator() {
+        return Err(CommentError::Forbidden("You can only delete your own comments".to_string()).into());
+    }
+
+    delete_comment(&state.db_wrapper, comment_id, user.id)
+        .await
+        .map_err(|err| {
+            CommentError::DatabaseError("Failed to delete comment".to_string(), err.into())
+        })?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "comment.delete", "comment", comment_id)
+            .before(&existing_comment),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json("Comment deleted successfully").into_response()))
+}
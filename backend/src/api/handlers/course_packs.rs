@@ -0,0 +1,454 @@
+use crate::api::errors::{AppError, NoteError};
+use crate::api::handlers::files::safe_file_stem;
+use crate::api::router::RouterState;
+use crate::db::handlers::notes::{
+    get_downloadable_notes_by_course, get_downloadable_notes_by_ids, record_note_download,
+};
+use crate::db::models::{DownloadableNote, User};
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use axum::{Extension, Json};
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use lopdf::{Bookmark, Object};
+use pdfium_render::prelude::*;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+const MAX_NOTES_PER_PACK: usize = 50;
+/// Bump this whenever the generated pages change, so that packs cached before are not reused
+const PACK_FORMAT_VERSION: u32 = 1;
+
+// Layout of the generated pages, in points on an A4 page
+const MARGIN: f32 = 56.0;
+const TOC_ROW_HEIGHT: f32 = 40.0;
+const TOC_ROWS_PER_PAGE: usize = 17;
+const MAX_LINE_CHARS: usize = 80;
+
+/// Index of a note in the pack and the number of the page it starts on
+type StartPage = (usize, u32);
+
+/// The pdfium library is loaded on the first course pack request. A failed load is retried on
+/// the next request.
+static PDFIUM: OnceLock<Pdfium> = OnceLock::new();
+
+#[derive(Deserialize)]
+pub struct CoursePackRequest {
+    pub note_ids: Vec<Uuid>,
+}
+
+/// A note going into a pack, along with its file
+struct PackNote {
+    note: DownloadableNote,
+    path: PathBuf,
+}
+
+fn pdfium(library_path: &str) -> Option<&'static Pdfium> {
+    if let Some(pdfium) = PDFIUM.get() {
+        return Some(pdfium);
+    }
+
+    let bindings = if library_path.is_empty() {
+        Pdfium::bind_to_system_library()
+    } else {
+        Pdfium::bind_to_library(library_path)
+    };
+    match bindings {
+        Ok(bindings) => Some(PDFIUM.get_or_init(|| Pdfium::new(bindings))),
+        Err(err) => {
+            tracing::error!("Failed to load pdfium, course packs are unavailable: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Cuts a line of text that would run off the page
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars - 3).collect();
+    truncated.push_str("...");
+    truncated
+}
+
+/// Hash of everything that ends up in a pack: the notes' details and the bytes of their files.
+/// Packs are cached under this hash, so any change to a note builds a new pack.
+fn pack_hash(title: &str, notes: &[PackNote]) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("course-pack-v{}\n{}\n", PACK_FORMAT_VERSION, title));
+
+    for PackNote { note, path } in notes {
+        hasher.update(format!(
+            "{}\n{}\n{}\n{}\n{}\n{}\n",
+            note.id,
+            note.course_code,
+            note.course_name,
+            note.note_year,
+            note.note_semester,
+            note.uploader_name
+        ));
+        let mut file = std::fs::File::open(path)?;
+        hasher.update(file.metadata()?.len().to_le_bytes());
+        std::io::copy(&mut file, &mut hasher)?;
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Writes a line of text onto a page, with `y` measured from the top of the page
+fn write_line(
+    page: &mut PdfPage,
+    x: f32,
+    y: f32,
+    text: &str,
+    font: PdfFontToken,
+    size: f32,
+) -> Result<(), PdfiumError> {
+    let top = page.height().value;
+    page.objects_mut().create_text_object(
+        PdfPoints::new(x),
+        PdfPoints::new(top - y),
+        text,
+        font,
+        PdfPoints::new(size),
+    )?;
+    Ok(())
+}
+
+/// Builds the pack: a cover page, the table of contents and then the pages of every note.
+/// Notes whose file cannot be read as a PDF are left out. Returns the PDF along with the
+/// number of the first page of each note that made it in.
+fn build_pack(
+    pdfium: &Pdfium,
+    title: &str,
+    notes: &[PackNote],
+) -> color_eyre::Result<(Vec<u8>, Vec<StartPage>)> {
+    let mut sources = Vec::with_capacity(notes.len());
+    for (index, PackNote { note, path }) in notes.iter().enumerate() {
+        match pdfium.load_pdf_from_file(path, None) {
+            Ok(document) => sources.push((index, document)),
+            Err(err) => tracing::warn!("Leaving note {} out of course pack: {:?}", note.id, err),
+        }
+    }
+    if sources.is_empty() {
+        return Err(eyre!("None of the notes could be read"));
+    }
+
+    let toc_pages = sources.len().div_ceil(TOC_ROWS_PER_PAGE);
+    let mut next_page = 1 + toc_pages as u32 + 1;
+    let mut start_pages = Vec::with_capacity(sources.len());
+    for (index, document) in &sources {
+        start_pages.push((*index, next_page));
+        next_page += document.pages().len() as u32;
+    }
+
+    let mut pack = pdfium.create_new_pdf()?;
+    let regular = pack.fonts_mut().helvetica();
+    let bold = pack.fonts_mut().helvetica_bold();
+
+    // Cover page. Pages write their content when dropped, so each one is dropped before saving.
+    {
+        let mut cover = pack.pages_mut().create_page_at_end(PdfPagePaperSize::a4())?;
+        write_line(&mut cover, MARGIN, 260.0, "Course Pack", bold, 32.0)?;
+        write_line(&mut cover, MARGIN, 300.0, &truncate(title, 40), regular, 20.0)?;
+        write_line(
+            &mut cover,
+            MARGIN,
+            340.0,
+            &format!("{} notes, {} pages", sources.len(), next_page - 1),
+            regular,
+            12.0,
+        )?;
+        write_line(
+            &mut cover,
+            MARGIN,
+            360.0,
+            &format!("Generated on {} by Can't Find My Notes", Utc::now().format("%d %B %Y")),
+            regular,
+            12.0,
+        )?;
+    }
+
+    // Table of contents
+    for (page_index, rows) in start_pages.chunks(TOC_ROWS_PER_PAGE).enumerate() {
+        let mut page = pack.pages_mut().create_page_at_end(PdfPagePaperSize::a4())?;
+        let width = page.width().value;
+        let mut y = MARGIN + 20.0;
+        if page_index == 0 {
+            write_line(&mut page, MARGIN, y, "Contents", bold, 20.0)?;
+        }
+        y += 40.0;
+
+        for (index, start_page) in rows {
+            let note = &notes[*index].note;
+            write_line(
+                &mut page,
+                MARGIN,
+                y,
+                &truncate(&format!("{} {}", note.course_code, note.course_name), MAX_LINE_CHARS - 10),
+                bold,
+                11.0,
+            )?;
+            write_line(
+                &mut page,
+                width - MARGIN - 30.0,
+                y,
+                &start_page.to_string(),
+                regular,
+                11.0,
+            )?;
+            write_line(
+                &mut page,
+                MARGIN,
+                y + 15.0,
+                &truncate(
+                    &format!(
+                        "{} {} - uploaded by {}",
+                        note.note_semester, note.note_year, note.uploader_name
+                    ),
+                    MAX_LINE_CHARS,
+                ),
+                regular,
+                10.0,
+            )?;
+            y += TOC_ROW_HEIGHT;
+        }
+    }
+
+    for (_, document) in &sources {
+        pack.pages_mut().append(document)?;
+    }
+
+    Ok((pack.save_to_bytes()?, start_pages))
+}
+
+/// Adds a bookmark for the table of contents and for every note. pdfium cannot write
+/// bookmarks, so this is done on the saved file.
+fn add_bookmarks(
+    pdf: &[u8],
+    notes: &[PackNote],
+    start_pages: &[StartPage],
+) -> color_eyre::Result<Vec<u8>> {
+    let mut document = lopdf::Document::load_mem(pdf)?;
+    let pages = document.get_pages();
+
+    if let Some(page_id) = pages.get(&2) {
+        document.add_bookmark(Bookmark::new("Contents".to_string(), [0.0; 3], 0, *page_id), None);
+    }
+    for (index, start_page) in start_pages {
+        let note = &notes[*index].note;
+        if let Some(page_id) = pages.get(start_page) {
+            let title = format!(
+                "{} - {} {} ({})",
+                note.course_code, note.note_semester, note.note_year, note.uploader_name
+            );
+            document.add_bookmark(Bookmark::new(title, [0.0; 3], 0, *page_id), None);
+        }
+    }
+
+    if let Some(outline_id) = document.build_outline() {
+        let catalog = document.catalog_mut()?;
+        catalog.set("Outlines", Object::Reference(outline_id));
+        catalog.set("PageMode", Object::Name(b"UseOutlines".to_vec()));
+    }
+
+    let mut bytes = Vec::new();
+    document.save_to(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Opens the cached pack for the notes, building it first if needed. Returns the pack along with
+/// the ids of the notes that made it in, which are kept next to the pack in a JSON file. The pack
+/// is opened before the cache is pruned, so it can be sent even if it is removed right after.
+fn cached_pack(
+    pdfium: &Pdfium,
+    state: &RouterState,
+    title: &str,
+    notes: &[PackNote],
+) -> color_eyre::Result<(File, Vec<Uuid>)> {
+    let hash = pack_hash(title, notes)?;
+    let path = state.env_vars.paths.get_course_pack_path(&hash);
+    let included_path = path.with_extension("json");
+    if let Ok(file) = File::options().read(true).write(true).open(&path) {
+        // Packs cached without the list of included notes are built again
+        if let Some(included) = std::fs::read(&included_path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+        {
+            // Marks the pack as recently used, packs that have not been downloaded in the
+            // longest time are pruned first
+            file.set_modified(SystemTime::now())?;
+            return Ok((file, included));
+        }
+    }
+
+    let (pdf, start_pages) = build_pack(pdfium, title, notes)?;
+    let pdf = add_bookmarks(&pdf, notes, &start_pages)?;
+    let included: Vec<Uuid> = start_pages.iter().map(|(index, _)| notes[*index].note.id).collect();
+    std::fs::write(&included_path, serde_json::to_vec(&included)?)?;
+
+    // Written under a temporary name first so that a half written pack is never served
+    let temp_path = state
+        .env_vars
+        .paths
+        .get_course_packs_dir()
+        .join(format!("{}.tmp", Uuid::new_v4()));
+    std::fs::write(&temp_path, pdf)?;
+    std::fs::rename(&temp_path, &path)?;
+    let file = File::open(&path)?;
+
+    tracing::info!("Built course pack {} with {} notes", hash, included.len());
+    if let Err(err) = prune_pack_cache(state, &path) {
+        tracing::error!("Failed to prune the course pack cache: {:?}", err);
+    }
+    Ok((file, included))
+}
+
+/// Removes the least recently used packs until the cache fits in `course_pack_cache_mb`. The
+/// pack that was just built is kept regardless.
+fn prune_pack_cache(state: &RouterState, keep: &std::path::Path) -> std::io::Result<()> {
+    let max_bytes = state.env_vars.course_pack_cache_mb.saturating_mul(1024 * 1024);
+
+    let mut packs = Vec::new();
+    for entry in std::fs::read_dir(state.env_vars.paths.get_course_packs_dir())? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "pdf") {
+            let metadata = entry.metadata()?;
+            packs.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+    packs.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified));
+
+    let mut total: u64 = 0;
+    for (_, len, path) in packs {
+        total = total.saturating_add(len);
+        if total > max_bytes && path != keep {
+            std::fs::remove_file(&path)?;
+            // The list of included notes may already be gone
+            let _ = std::fs::remove_file(path.with_extension("json"));
+            tracing::info!("Removed course pack {} from the cache", path.display());
+        }
+    }
+    Ok(())
+}
+
+async fn send_course_pack(
+    state: RouterState,
+    user: User,
+    title: String,
+    notes: Vec<DownloadableNote>,
+    file_name: &str,
+) -> Result<(StatusCode, Response), AppError> {
+    let Some(pdfium) = pdfium(&state.env_vars.pdfium_library_path) else {
+        return Err(NoteError::Unavailable("Course packs are not available on this server".to_string()).into());
+    };
+
+    let notes: Vec<PackNote> = notes
+        .into_iter()
+        .map(|note| PackNote {
+            path: state.env_vars.paths.get_note_path(&format!("{}.pdf", note.id)),
+            note,
+        })
+        .filter(|pack_note| pack_note.path.exists())
+        .collect();
+    if notes.is_empty() {
+        return Err(NoteError::NotFound("None of the note files were found".to_string()).into());
+    }
+
+    let (file, included) = {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || cached_pack(pdfium, &state, &title, &notes))
+        .await
+        .map_err(|err| NoteError::Internal("Failed to build course pack".to_string(), err.into()))?
+        .map_err(|err| NoteError::Internal("Failed to build course pack".to_string(), err.into()))?
+    };
+
+    // Only notes whose pages are in the pack count as downloaded
+    let dedup_since = Utc::now() - state.env_vars.download_dedup_window;
+    for note_id in included {
+        if let Err(err) =
+            record_note_download(&state.db_wrapper, note_id, Some(user.id), None, dedup_since).await
+        {
+            tracing::error!("Failed to record download of note {}: {:?}", note_id, err);
+        }
+    }
+
+    let file = tokio::fs::File::from_std(file);
+    let len = file
+        .metadata()
+        .await
+        .map_err(|err| NoteError::Internal("Failed to read course pack".to_string(), err.into()))?
+        .len();
+
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, "application/pdf")
+        .header(header::CONTENT_LENGTH, len)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        )
+        .body(Body::from_stream(ReaderStream::new(file)))
+        .unwrap();
+    Ok((StatusCode::OK, response))
+}
+
+/// API handler to download every note of a course as a single PDF with a table of contents
+pub async fn download_course_pack(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Path(course_code): Path<String>,
+) -> Result<(StatusCode, Response), AppError> {
+    let notes =
+        get_downloadable_notes_by_course(&state.db_wrapper, &course_code, MAX_NOTES_PER_PACK as i64)
+            .await
+            .map_err(|err| NoteError::DatabaseError("Failed to fetch notes".to_string(), err.into()))?;
+    let Some(first) = notes.first() else {
+        return Err(NoteError::NotFound("No notes found for this course".to_string()).into());
+    };
+
+    let title = format!("{} {}", first.course_code, first.course_name);
+    let file_name = format!("{}_course_pack.pdf", safe_file_stem(&first.course_code));
+    send_course_pack(state, user, title, notes, &file_name).await
+}
+
+/// API handler to download a selection of notes as a single PDF with a table of contents
+pub async fn build_course_pack(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<CoursePackRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    if payload.note_ids.is_empty() {
+        return Err(NoteError::InvalidData("No notes selected".to_string()).into());
+    }
+    if payload.note_ids.len() > MAX_NOTES_PER_PACK {
+        return Err(NoteError::InvalidData(format!(
+            "Only up to {} notes can be put in a course pack",
+            MAX_NOTES_PER_PACK
+        ))
+        .into());
+    }
+
+    let notes = get_downloadable_notes_by_ids(&state.db_wrapper, &payload.note_ids)
+        .await
+        .map_err(|err| NoteError::DatabaseError("Failed to fetch notes".to_string(), err.into()))?;
+    if notes.is_empty() {
+        return Err(NoteError::NotFound("None of the selected notes were found".to_string()).into());
+    }
+
+    let title = if notes.iter().all(|note| note.course_code == notes[0].course_code) {
+        format!("{} {}", notes[0].course_code, notes[0].course_name)
+    } else {
+        "Selected notes".to_string()
+    };
+    send_course_pack(state, user, title, notes, "course_pack.pdf").await
+}
@@ -0,0 +1,140 @@
+use crate::api::errors::{AppError, UserError};
+use crate::api::handlers::audit;
+use crate::api::router::RouterState;
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::email::{get_email_preferences, update_email_preferences};
+use crate::db::handlers::users::find_user_by_id;
+use crate::db::models::User;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::Utc;
+use serde::Deserialize;
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+/// Changes to a user's email preferences, fields that are left out are not changed
+#[derive(Deserialize)]
+pub struct UpdateEmailPreferencesRequest {
+    pub weekly_digest: Option<bool>,
+    pub course_notes: Option<bool>,
+    pub upload_activity: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeQuery {
+    pub user: Uuid,
+    /// Unix timestamp the link expires at
+    pub expires: i64,
+    pub sig: String,
+}
+
+/// API handler to get the signed in user's email preferences
+pub async fn get_preferences(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+) -> Result<(StatusCode, Response), AppError> {
+    let preferences = get_email_preferences(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch email preferences".to_string(), err.into()))?;
+
+    Ok((StatusCode::OK, Json(preferences).into_response()))
+}
+
+/// API handler to change the signed in user's email preferences
+pub async fn update_preferences(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Json(payload): Json<UpdateEmailPreferencesRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let existing_preferences = get_email_preferences(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch email preferences".to_string(), err.into()))?;
+
+    let mut preferences = existing_preferences.clone();
+    if let Some(weekly_digest) = payload.weekly_digest {
+        preferences.weekly_digest = weekly_digest;
+    }
+    if let Some(course_notes) = payload.course_notes {
+        preferences.course_notes = course_notes;
+    }
+    if let Some(upload_activity) = payload.upload_activity {
+        preferences.upload_activity = upload_activity;
+    }
+
+    update_email_preferences(&state.db_wrapper, user.id, &preferences)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to update email preferences".to_string(), err.into()))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "user.email_preferences", "user", user.id)
+            .before(&existing_preferences)
+            .after(&preferences),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(preferences).into_response()))
+}
+
+/// Checks that an unsubscribe link was signed by us, has not expired and is for a user that
+/// still exists
+async fn check_unsubscribe_link(state: &RouterState, query: &UnsubscribeQuery) -> Result<(), UserError> {
+    if !state.env_vars.paths.verify_unsubscribe(query.user, query.expires, &query.sig) {
+        return Err(UserError::InvalidData("This link is invalid".to_string()));
+    }
+    if query.expires < Utc::now().timestamp() {
+        return Err(UserError::InvalidData(
+            "This link has expired. You can turn the digest off in your settings instead.".to_string(),
+        ));
+    }
+    find_user_by_id(&state.db_wrapper, query.user)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch user".to_string(), err.into()))?
+        .ok_or_else(|| UserError::NotFound("User not found".to_string()))?;
+    Ok(())
+}
+
+/// API handler for the unsubscribe link in digests. It only asks to confirm, as link scanners
+/// and prefetchers open links without the user meaning to unsubscribe.
+pub async fn unsubscribe_page(
+    State(state): State<RouterState>,
+    Query(query): Query<UnsubscribeQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    check_unsubscribe_link(&state, &query).await?;
+
+    // The form posts back to this URL, query string and all
+    Ok((
+        StatusCode::OK,
+        Html(
+            "<form method=\"post\"><p>Stop receiving the weekly CFMN digest?</p>\
+             <button type=\"submit\">Unsubscribe</button></form>",
+        )
+        .into_response(),
+    ))
+}
+
+/// API handler that turns the digest off without signing in, for the confirmation form of the
+/// unsubscribe page and the one-click `POST` sent by mail clients (RFC 8058)
+pub async fn unsubscribe(
+    State(state): State<RouterState>,
+    Query(query): Query<UnsubscribeQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    check_unsubscribe_link(&state, &query).await?;
+
+    let mut preferences = get_email_preferences(&state.db_wrapper, query.user)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch email preferences".to_string(), err.into()))?;
+    preferences.weekly_digest = false;
+    update_email_preferences(&state.db_wrapper, query.user, &preferences)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to update email preferences".to_string(), err.into()))?;
+
+    Ok((
+        StatusCode::OK,
+        Html("<p>You have been unsubscribed from the weekly CFMN digest.</p>").into_response(),
+    ))
+}
@@ -0,0 +1,269 @@
+//! Public Atom feeds of new notes, rendered from `templates/feeds/notes.xml`.
+
+use crate::api::errors::{AppError, NoteError};
+use crate::api::handlers::files::MAX_SHARE_HOURS;
+use crate::api::router::RouterState;
+use crate::db::handlers::feeds::{get_feed_notes, FeedNote, FeedSource};
+use crate::db::handlers::users::{find_user_by_id, GHOST_USER_ID};
+use askama::Template;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::Response;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Notes listed in each feed
+const FEED_LENGTH: i64 = 30;
+const MAX_COURSE_CODE_LENGTH: usize = 50;
+const MAX_TAG_LENGTH: usize = 100;
+/// Links to note files in feeds stay valid for at least this many days less one, no longer than a
+/// shared link. They are signed to expire at midnight, so a feed's body and ETag only change once
+/// a day unless its notes do.
+const FEED_LINK_TTL_DAYS: i64 = MAX_SHARE_HOURS / 24;
+
+struct Feed {
+    self_url: String,
+    alternate_url: String,
+    title: String,
+    subtitle: String,
+    updated: String,
+    entries: Vec<FeedEntry>,
+}
+
+struct FeedEntry {
+    id: Uuid,
+    title: String,
+    course_code: String,
+    course_name: String,
+    description: Option<String>,
+    professor_names: Vec<String>,
+    tags: Vec<String>,
+    year: i64,
+    semester: String,
+    author: String,
+    file_url: String,
+    preview_image_url: Option<String>,
+    published: String,
+    updated: String,
+    /// The entry as HTML, rendered from `templates/feeds/entry.html`
+    content: String,
+}
+
+#[derive(Template)]
+#[template(path = "feeds/notes.xml")]
+struct FeedXml<'a> {
+    feed: &'a Feed,
+}
+
+#[derive(Template)]
+#[template(path = "feeds/entry.html")]
+struct EntryHtml<'a> {
+    entry: &'a FeedEntry,
+}
+
+fn atom_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Takes the `.atom` extension off the last segment of a feed's path
+fn strip_extension(segment: &str) -> Result<&str, NoteError> {
+    segment
+        .strip_suffix(".atom")
+        .filter(|name| !name.trim().is_empty())
+        .ok_or_else(|| NoteError::NotFound("Feed not found".to_string()))
+}
+
+fn feed_entry(
+    state: &RouterState,
+    note: FeedNote,
+    links_expire_at: DateTime<Utc>,
+) -> Result<FeedEntry, NoteError> {
+    let paths = &state.env_vars.paths;
+    let file_url = paths
+        .get_signed_note_url(note.id, links_expire_at)
+        .map_err(|err| NoteError::Internal("Failed to build feed".to_string(), err.into()))?;
+    let preview_image_url = if note.has_preview_image {
+        paths.get_preview_url(&format!("{}.jpg", note.id)).ok()
+    } else {
+        None
+    };
+
+    let mut entry = FeedEntry {
+        id: note.id,
+        title: format!(
+            "{} {} ({} {})",
+            note.course_code, note.course_name, note.note_semester, note.note_year
+        ),
+        course_code: note.course_code,
+        course_name: note.course_name,
+        description: note.description,
+        professor_names: note.professor_names.unwrap_or_default(),
+        tags: note.tags,
+        year: note.note_year,
+        semester: note.note_semester,
+        author: note.uploader_name.unwrap_or_else(|| "Anonymous".to_string()),
+        file_url,
+        preview_image_url,
+        published: atom_date(note.created_at),
+        updated: atom_date(note.updated_at),
+        content: String::new(),
+    };
+    entry.content = EntryHtml { entry: &entry }
+        .render()
+        .map_err(|err| NoteError::Internal("Failed to build feed".to_string(), err.into()))?
+        .trim()
+        .to_string();
+    Ok(entry)
+}
+
+/// What a feed is about, shown at its top
+struct FeedInfo {
+    /// Path segments of the feed under `/feeds`, e.g. `["courses", "CS10001.atom"]`
+    path: [String; 2],
+    /// Page on the frontend the feed stands for
+    alternate_url: String,
+    title: String,
+    subtitle: String,
+}
+
+async fn fetch_notes(state: &RouterState, source: FeedSource) -> Result<Vec<FeedNote>, NoteError> {
+    get_feed_notes(&state.db_wrapper, &source, FEED_LENGTH)
+        .await
+        .map_err(|err| NoteError::DatabaseError("Failed to fetch notes".to_string(), err.into()))
+}
+
+/// Renders a feed, answering `304 Not Modified` if the client's copy is current
+fn feed_response(
+    state: &RouterState,
+    headers: &HeaderMap,
+    info: FeedInfo,
+    notes: Vec<FeedNote>,
+) -> Result<(StatusCode, Response), AppError> {
+    let today = Utc::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .expect("Midnight is a valid time")
+        .and_utc();
+    let links_expire_at = today + Duration::days(FEED_LINK_TTL_DAYS);
+    let updated = notes.iter().map(|note| note.updated_at).max().unwrap_or(today);
+
+    let self_url = state
+        .env_vars
+        .paths
+        .get_feed_url(&info.path)
+        .map_err(|err| NoteError::Internal("Failed to build feed".to_string(), err.into()))?;
+    let feed = Feed {
+        self_url,
+        alternate_url: info.alternate_url,
+        title: info.title,
+        subtitle: info.subtitle,
+        updated: atom_date(updated),
+        entries: notes
+            .into_iter()
+            .map(|note| feed_entry(state, note, links_expire_at))
+            .collect::<Result<_, _>>()?,
+    };
+    let body = FeedXml { feed: &feed }
+        .render()
+        .map_err(|err| NoteError::Internal("Failed to build feed".to_string(), err.into()))?;
+
+    let etag = format!("\"{}\"", hex::encode(&Sha256::digest(body.as_bytes())[..16]));
+    let builder = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, "public, max-age=300");
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag || tag == "*")
+        });
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, builder.body(Body::empty()).unwrap()));
+    }
+
+    let response = builder
+        .header(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap();
+    Ok((StatusCode::OK, response))
+}
+
+/// API handler for the feed of a course's notes, e.g. `GET /feeds/courses/CS10001.atom`
+pub async fn course_feed(
+    State(state): State<RouterState>,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+) -> Result<(StatusCode, Response), AppError> {
+    let course_code = strip_extension(&file)?.trim().to_uppercase();
+    if course_code.chars().count() > MAX_COURSE_CODE_LENGTH {
+        return Err(NoteError::InvalidData("Invalid course code".to_string()).into());
+    }
+
+    let notes = fetch_notes(&state, FeedSource::Course(course_code.clone())).await?;
+    let title = match notes.first() {
+        Some(note) => format!("{} {}: notes on CFMN", course_code, note.course_name),
+        None => format!("{}: notes on CFMN", course_code),
+    };
+    let info = FeedInfo {
+        path: ["courses".to_string(), format!("{}.atom", course_code)],
+        alternate_url: state.env_vars.frontend_url.clone(),
+        title,
+        subtitle: format!("New notes for {} on CFMN", course_code),
+    };
+    feed_response(&state, &headers, info, notes)
+}
+
+/// API handler for the feed of notes with a tag, e.g. `GET /feeds/tags/endsem.atom`
+pub async fn tag_feed(
+    State(state): State<RouterState>,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+) -> Result<(StatusCode, Response), AppError> {
+    let tag = strip_extension(&file)?.trim().to_string();
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(NoteError::InvalidData("Invalid tag".to_string()).into());
+    }
+
+    let notes = fetch_notes(&state, FeedSource::Tag(tag.clone())).await?;
+    let info = FeedInfo {
+        path: ["tags".to_string(), format!("{}.atom", tag)],
+        alternate_url: state.env_vars.frontend_url.clone(),
+        title: format!("Notes tagged {} on CFMN", tag),
+        subtitle: format!("New notes tagged {} on CFMN", tag),
+    };
+    feed_response(&state, &headers, info, notes)
+}
+
+/// API handler for the feed of a user's uploads, e.g. `GET /feeds/users/{id}.atom`. Notes they
+/// uploaded anonymously are left out.
+pub async fn user_feed(
+    State(state): State<RouterState>,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+) -> Result<(StatusCode, Response), AppError> {
+    let user_id = Uuid::parse_str(strip_extension(&file)?)
+        .map_err(|_| NoteError::NotFound("Feed not found".to_string()))?;
+    let user = find_user_by_id(&state.db_wrapper, user_id)
+        .await
+        .map_err(|err| NoteError::DatabaseError("Failed to fetch user".to_string(), err.into()))?
+        .filter(|user| user.id != GHOST_USER_ID)
+        .ok_or_else(|| NoteError::NotFound("User not found".to_string()))?;
+
+    let notes = fetch_notes(&state, FeedSource::User(user.id)).await?;
+    let info = FeedInfo {
+        path: ["users".to_string(), format!("{}.atom", user.id)],
+        alternate_url: format!(
+            "{}/profile/{}",
+            state.env_vars.frontend_url.trim_end_matches('/'),
+            user.id
+        ),
+        title: format!("Notes by {} on CFMN", user.public_name),
+        subtitle: format!("New notes uploaded by {} on CFMN", user.public_name),
+    };
+    feed_response(&state, &headers, info, notes)
+}
@@ -0,0 +1,407 @@
+use crate::api::errors::{AppError, NoteError};
+use crate::api::handlers::audit;
+use crate::api::handlers::notes::{client_ip, hash_ip};
+use crate::api::router::RouterState;
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::notes::{get_note_by_id, record_note_download};
+use crate::db::models::{NoteWithUser, User};
+use crate::pathutils::Paths;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+/// Longest a signed link to a note file can be valid for
+pub(crate) const MAX_SHARE_HOURS: i64 = 24 * 7;
+
+#[derive(Deserialize)]
+pub struct FileQuery {
+    pub expires: Option<i64>,
+    pub sig: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ShareRequest {
+    pub expires_in_hours: Option<i64>,
+}
+
+/// The part of a file requested through the `Range` header
+enum ByteRange {
+    Full,
+    /// Inclusive start and end offsets
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header for a file of `len` bytes. Only single byte ranges are supported,
+/// anything else is served as the full file as allowed by RFC 9110.
+fn parse_range(range: Option<&str>, len: u64) -> ByteRange {
+    let Some(spec) = range.and_then(|range| range.strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=start-end
+        (Ok(start), Ok(end)) if start <= end => {
+            if start >= len {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial(start, end.min(len - 1))
+            }
+        }
+        // bytes=start-
+        (Ok(start), Err(_)) if end.is_empty() => {
+            if start >= len {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial(start, len - 1)
+            }
+        }
+        // bytes=-suffix_length
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 || len == 0 {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial(len.saturating_sub(suffix), len - 1)
+            }
+        }
+        _ => ByteRange::Full,
+    }
+}
+
+/// Fetches a note, making sure the user is allowed to see it. Hidden notes are only visible to
+/// their uploader and to moderators.
+pub(crate) async fn fetch_visible_note(
+    state: &RouterState,
+    user: Option<&User>,
+    note_id: Uuid,
+) -> Result<NoteWithUser, NoteError> {
+    let note = match get_note_by_id(&state.db_wrapper, note_id, user.map(|u| u.id)).await {
+        Ok(note) => note,
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(NoteError::NotFound("Note not found".to_string()));
+        }
+        Err(err) => {
+            return Err(NoteError::DatabaseError("Failed to fetch note".to_string(), err.into()));
+        }
+    };
+
+    let can_view = !note.note_is_hidden
+        || user.is_some_and(|u| u.id == note.note_uploader_user_id || u.is_moderator());
+    if !can_view {
+        return Err(NoteError::NotFound("Note not found".to_string()));
+    }
+    Ok(note)
+}
+
+/// Replaces anything but ASCII letters, digits, `-` and `_` so the name is safe in a
+/// `Content-Disposition` header and on any file system
+pub(crate) fn safe_file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+/// Checks that a signed out request for a note file carries a valid, unexpired signature.
+/// This only protects anything because notes are not stored under the public static files root.
+fn check_file_link(
+    paths: &Paths,
+    note_id: Uuid,
+    query: &FileQuery,
+    now: DateTime<Utc>,
+) -> Result<(), NoteError> {
+    let (Some(expires), Some(sig)) = (query.expires, query.sig.as_deref()) else {
+        return Err(NoteError::Forbidden("This link is missing its signature".to_string()));
+    };
+    if !paths.verify_note_url(note_id, expires, sig) {
+        return Err(NoteError::Forbidden("This link is invalid".to_string()));
+    }
+    if expires < now.timestamp() {
+        return Err(NoteError::Forbidden("This link has expired".to_string()));
+    }
+    Ok(())
+}
+
+/// Readable file name for a note, e.g. `CS10001_2024_Autumn.pdf`
+pub(crate) fn note_file_name(course_code: &str, year: i64, semester: &str) -> String {
+    let name = format!("{}_{}_{}", course_code, year, semester);
+    format!("{}.pdf", safe_file_stem(&name))
+}
+
+/// API handler to serve the PDF of a note.
+///
+/// Signed out requests need the signed, unexpired URL handed out in `file_url` or by the share
+/// endpoint. Hidden notes are only served to their uploader and to moderators. Supports single
+/// byte `Range` requests and conditional requests through the `ETag`. Requests for the start of
+/// the file are counted as downloads.
+pub async fn serve_note_file(
+    State(state): State<RouterState>,
+    Extension(user): Extension<Option<User>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    method: Method,
+    headers: HeaderMap,
+    Path(note_id): Path<Uuid>,
+    Query(query): Query<FileQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    if user.is_none() {
+        check_file_link(&state.env_vars.paths, note_id, &query, Utc::now())?;
+    }
+
+    let note = fetch_visible_note(&state, user.as_ref(), note_id).await?;
+
+    let file_path = state.env_vars.paths.get_note_path(&format!("{}.pdf", note_id));
+    let mut file = tokio::fs::File::open(&file_path).await.map_err(|err| {
+        tracing::error!("Failed to open file for note {}: {}", note_id, err);
+        NoteError::NotFound("Note file not found".to_string())
+    })?;
+    let metadata = file
+        .metadata()
+        .await
+        .map_err(|err| NoteError::DatabaseError("Failed to read note file".to_string(), err.into()))?;
+    let len = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let etag = format!("\"{:x}-{:x}\"", len, modified);
+
+    let builder = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "private, no-cache");
+
+    let header_str = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if header_str(header::IF_NONE_MATCH)
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"))
+    {
+        let response = builder.body(Body::empty()).unwrap();
+        return Ok((StatusCode::NOT_MODIFIED, response));
+    }
+
+    // A Range is only honoured if the client's copy is still current
+    let range = match header_str(header::IF_RANGE) {
+        Some(if_range) if if_range != etag => ByteRange::Full,
+        _ => parse_range(header_str(header::RANGE), len),
+    };
+
+    let (status, start, end) = match range {
+        ByteRange::Full => (StatusCode::OK, 0, len.saturating_sub(1)),
+        ByteRange::Partial(start, end) => (StatusCode::PARTIAL_CONTENT, start, end),
+        ByteRange::Unsatisfiable => {
+            let response = builder
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty())
+                .unwrap();
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response));
+        }
+    };
+    let content_length = if len == 0 { 0 } else { end - start + 1 };
+
+    // PDF viewers fetch the file in chunks, only the request for the start counts as a download
+    if start == 0 && method != Method::HEAD {
+        let user_id = user.as_ref().map(|u| u.id);
+        let ip_hash = match user_id {
+            Some(_) => None,
+            None => {
+                let ip = client_ip(&headers, peer, state.env_vars.trust_proxy_headers);
+                Some(hash_ip(&state.env_vars.signing_secret, ip))
+            }
+        };
+        let dedup_since = Utc::now() - state.env_vars.download_dedup_window;
+
+        if let Err(err) =
+            record_note_download(&state.db_wrapper, note_id, user_id, ip_hash, dedup_since).await
+        {
+            tracing::error!("Failed to record download of note {}: {:?}", note_id, err);
+        }
+    }
+
+    file.seek(SeekFrom::Start(start))
+        .await
+        .map_err(|err| NoteError::DatabaseError("Failed to read note file".to_string(), err.into()))?;
+    let body = Body::from_stream(ReaderStream::new(file.take(content_length)));
+    let file_name = note_file_name(&note.note_course_code, note.note_year, &note.note_semester);
+
+    let mut builder = builder
+        .header(header::CONTENT_TYPE, "application/pdf")
+        .header(header::CONTENT_LENGTH, content_length)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"{}\"", file_name),
+        );
+    if status == StatusCode::PARTIAL_CONTENT {
+        builder = builder.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len));
+    }
+
+    Ok((status, builder.body(body).unwrap()))
+}
+
+/// API handler to create a signed link to a note's file that can be shared for a limited time
+pub async fn share_note_file(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(note_id): Path<Uuid>,
+    Json(payload): Json<ShareRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let hours = payload.expires_in_hours.unwrap_or(24);
+    if !(1..=MAX_SHARE_HOURS).contains(&hours) {
+        return Err(NoteError::InvalidData(format!(
+            "Links can be shared for between 1 and {} hours",
+            MAX_SHARE_HOURS
+        ))
+        .into());
+    }
+
+    fetch_visible_note(&state, Some(&user), note_id).await?;
+
+    let expires_at = Utc::now() + Duration::hours(hours);
+    let url = state
+        .env_vars
+        .paths
+        .get_signed_note_url(note_id, expires_at)
+        .map_err(|err| NoteError::InvalidData(format!("Failed to create link: {}", err)))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "note.share", "note", note_id)
+            .after(&serde_json::json!({ "expires_at": expires_at })),
+    )
+    .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({ "url": url, "expires_at": expires_at })).into_response(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_paths() -> Paths {
+        Paths::default().with_note_url_signing(b"test key", Duration::hours(1))
+    }
+
+    /// Splits a signed note URL into the query the file route receives
+    fn query_of(url: &str) -> FileQuery {
+        let url = url::Url::parse(url).unwrap();
+        let param = |name: &str| {
+            url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned())
+        };
+        FileQuery {
+            expires: param("expires").map(|expires| expires.parse().unwrap()),
+            sig: param("sig"),
+        }
+    }
+
+    fn status(result: Result<(), NoteError>) -> StatusCode {
+        match result {
+            Ok(()) => StatusCode::OK,
+            Err(err) => AppError::from(err).into_response().status(),
+        }
+    }
+
+    fn range(header: &str, len: u64) -> Option<(u64, u64)> {
+        match parse_range(Some(header), len) {
+            ByteRange::Partial(start, end) => Some((start, end)),
+            ByteRange::Full => None,
+            ByteRange::Unsatisfiable => Some((u64::MAX, u64::MAX)),
+        }
+    }
+
+    #[test]
+    fn parses_single_byte_ranges() {
+        assert_eq!(range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(range("bytes=500-", 1000), Some((500, 999)));
+        assert_eq!(range("bytes=-100", 1000), Some((900, 999)));
+        // Ends past the file and suffixes longer than it are clamped
+        assert_eq!(range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(range("bytes=-5000", 1000), Some((0, 999)));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        let unsatisfiable = Some((u64::MAX, u64::MAX));
+        assert_eq!(range("bytes=1000-1100", 1000), unsatisfiable);
+        assert_eq!(range("bytes=1000-", 1000), unsatisfiable);
+        assert_eq!(range("bytes=-0", 1000), unsatisfiable);
+        assert_eq!(range("bytes=-10", 0), unsatisfiable);
+    }
+
+    #[test]
+    fn unsupported_ranges_serve_the_full_file() {
+        assert!(matches!(parse_range(None, 1000), ByteRange::Full));
+        assert_eq!(range("bytes=0-10,20-30", 1000), None);
+        assert_eq!(range("items=0-10", 1000), None);
+        assert_eq!(range("bytes=10-5", 1000), None);
+        assert_eq!(range("bytes=abc", 1000), None);
+        assert_eq!(range("bytes=-", 1000), None);
+    }
+
+    #[test]
+    fn signed_links_are_accepted_until_they_expire() {
+        let paths = signing_paths();
+        let note_id = Uuid::new_v4();
+        let now = Utc::now();
+        let query = query_of(&paths.get_signed_note_url(note_id, now + Duration::minutes(5)).unwrap());
+
+        assert_eq!(status(check_file_link(&paths, note_id, &query, now)), StatusCode::OK);
+        assert_eq!(
+            status(check_file_link(&paths, note_id, &query, now + Duration::minutes(6))),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    fn unsigned_links_are_forbidden() {
+        let paths = signing_paths();
+        let unsigned = FileQuery { expires: None, sig: None };
+        let no_signature = FileQuery { expires: Some(i64::MAX), sig: None };
+
+        assert_eq!(
+            status(check_file_link(&paths, Uuid::new_v4(), &unsigned, Utc::now())),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(check_file_link(&paths, Uuid::new_v4(), &no_signature, Utc::now())),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    fn tampered_links_are_forbidden() {
+        let paths = signing_paths();
+        let note_id = Uuid::new_v4();
+        let now = Utc::now();
+        let query = query_of(&paths.get_signed_note_url(note_id, now + Duration::minutes(5)).unwrap());
+
+        // Another note, a pushed back expiry and a different key all break the signature
+        assert!(check_file_link(&paths, Uuid::new_v4(), &query, now).is_err());
+        let extended = FileQuery { expires: query.expires.map(|e| e + 3600), sig: query.sig.clone() };
+        assert!(check_file_link(&paths, note_id, &extended, now).is_err());
+        let other_key = Paths::default().with_note_url_signing(b"other key", Duration::hours(1));
+        assert!(check_file_link(&other_key, note_id, &query, now).is_err());
+        let garbage = FileQuery { expires: query.expires, sig: Some("not hex".to_string()) };
+        assert!(check_file_link(&paths, note_id, &garbage, now).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::api::errors::{AppError, UserError};
+use crate::api::models::{PublicUser, ResponseFeedPage, ResponseFollow, ResponseNote};
+use crate::api::router::RouterState;
+use crate::db::handlers::follows::{
+    add_follow, get_feed, get_follows, remove_follow, FeedCursor, FollowKind,
+};
+use crate::db::handlers::users::{find_user_by_id, GHOST_USER_ID};
+use crate::db::models::User;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use uuid::Uuid;
+
+const MAX_FEED_PAGE_SIZE: i64 = 50;
+const MAX_COURSE_CODE_LENGTH: usize = 50;
+const MAX_PROFESSOR_NAME_LENGTH: usize = 100;
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    /// The `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Normalises what is being followed: course codes are upper cased, professor names trimmed and
+/// users must exist. Returns the target and the followed user, if any.
+async fn follow_target(
+    state: &RouterState,
+    user: &User,
+    kind: FollowKind,
+    target: &str,
+) -> Result<(String, Option<User>), UserError> {
+    let target = target.split_whitespace().collect::<Vec<_>>().join(" ");
+    match kind {
+        FollowKind::Course => {
+            if target.is_empty() || target.chars().count() > MAX_COURSE_CODE_LENGTH {
+                return Err(UserError::InvalidData("Invalid course code".to_string()));
+            }
+            Ok((target.to_uppercase(), None))
+        }
+        FollowKind::Professor => {
+            if target.is_empty() || target.chars().count() > MAX_PROFESSOR_NAME_LENGTH {
+                return Err(UserError::InvalidData("Invalid professor name".to_string()));
+            }
+            Ok((target, None))
+        }
+        FollowKind::User => {
+            let user_id = Uuid::parse_str(&target)
+                .map_err(|_| UserError::InvalidData("Invalid user ID".to_string()))?;
+            if user_id == user.id {
+                return Err(UserError::InvalidData("You cannot follow yourself".to_string()));
+            }
+            let followed = find_user_by_id(&state.db_wrapper, user_id)
+                .await
+                .map_err(|err| UserError::Unknown("Failed to fetch user".to_string(), err.into()))?
+                .filter(|followed| followed.id != GHOST_USER_ID)
+                .ok_or_else(|| UserError::NotFound("User not found".to_string()))?;
+            Ok((user_id.to_string(), Some(followed)))
+        }
+    }
+}
+
+/// API handler to list what the signed in user follows
+pub async fn list_follows(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+) -> Result<(StatusCode, Response), AppError> {
+    let follows = get_follows(&state.db_wrapper, user.id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch follows".to_string(), err.into()))?;
+
+    let follows: Vec<ResponseFollow> = follows.into_iter().map(ResponseFollow::from).collect();
+    Ok((StatusCode::OK, Json(follows).into_response()))
+}
+
+/// API handler to follow a course, professor or user, e.g. `PUT /follows/course/CS10001`.
+/// Following something twice is not an error.
+pub async fn follow(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Path((kind, target)): Path<(FollowKind, String)>,
+) -> Result<(StatusCode, Response), AppError> {
+    let (target, followed) = follow_target(&state, &user, kind, &target).await?;
+
+    let follow = add_follow(
+        &state.db_wrapper,
+        user.id,
+        kind,
+        &target,
+        followed.as_ref().map(|followed| followed.id),
+    )
+    .await
+    .map_err(|err| UserError::Unknown("Failed to follow".to_string(), err.into()))?;
+
+    let response = ResponseFollow {
+        kind: follow.kind,
+        target: follow.target,
+        user: followed.as_ref().map(PublicUser::from),
+        created_at: follow.created_at,
+    };
+    Ok((StatusCode::OK, Json(response).into_response()))
+}
+
+/// API handler to unfollow a course, professor or user
+pub async fn unfollow(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Path((kind, target)): Path<(FollowKind, String)>,
+) -> Result<(StatusCode, Response), AppError> {
+    let target = target.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let removed = remove_follow(&state.db_wrapper, user.id, kind, &target)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to unfollow".to_string(), err.into()))?;
+    if !removed {
+        return Err(UserError::NotFound(format!("Not following this {}", kind.as_str())).into());
+    }
+
+    Ok((StatusCode::NO_CONTENT, ().into_response()))
+}
+
+/// API handler to get a page of new notes from everything the signed in user follows, ranked by
+/// recency and quality
+pub async fn get_feed_handler(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Query(query): Query<FeedQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_FEED_PAGE_SIZE);
+
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|cursor| {
+            FeedCursor::decode(cursor).ok_or_else(|| UserError::InvalidData("Invalid cursor".to_string()))
+        })
+        .transpose()?;
+
+    let notes = get_feed(&state.db_wrapper, user.id, limit, cursor)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch feed".to_string(), err.into()))?;
+
+    let next_cursor = if notes.len() as i64 == limit {
+        notes.last().map(|note| FeedCursor::after(note).encode())
+    } else {
+        None
+    };
+    let notes = notes
+        .into_iter()
+        .map(|note| {
+            let file_url = state.env_vars.paths.get_note_url(note.note_id).unwrap();
+            let preview_image_url = state
+                .env_vars
+                .paths
+                .get_preview_url(&format!("{}.jpg", note.note_id))
+                .unwrap();
+            ResponseNote::from_note_with_user(note, file_url, preview_image_url, Some(&user))
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(ResponseFeedPage { notes, next_cursor }).into_response()))
+}
@@ -3,4 +3,18 @@ pub mod misc;
 pub mod notes;
 pub mod votes;
 pub mod auth;
-pub mod users;
\ No newline at end of file
+pub mod users;
+pub mod comments;
+pub mod reports;
+pub mod admin;
+pub mod audit;
+pub mod vote_rings;
+pub mod files;
+pub mod bulk;
+pub mod course_packs;
+pub mod account;
+pub mod notifications;
+pub mod email;
+pub mod follows;
+pub mod webhooks;
+pub mod feeds;
@@ -1,23 +1,43 @@
 use crate::api::errors::{AppError, NoteError};
-use crate::api::models::{CreateNote, ResponseNote, ResponseUser};
+use crate::api::handlers::audit;
+use crate::api::handlers::files::fetch_visible_note;
+use crate::api::models::{CreateNote, PublicUser, ResponseNote};
 use crate::api::router::RouterState;
 use crate::db::handlers::notes::{
     create_note, delete_note, get_note_by_id, get_notes, get_notes_by_user_id,
-    increment_note_downloads, search_notes_by_query, update_note, update_note_preview_status,
+    record_note_download, search_notes_by_query, update_note, update_note_preview_status,
 };
+use crate::db::handlers::audit::NewAuditEvent;
 use crate::db::models::User;
+use crate::events::{self, Event};
 use axum::body::Bytes;
-use axum::extract::{multipart::Multipart, Path, Query, State};
-use axum::http::StatusCode;
+use axum::extract::{multipart::Multipart, ConnectInfo, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
 use chrono::Utc;
+use hmac::{Hmac, Mac};
 use serde::Deserialize;
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use tower_http::request_id::RequestId;
 use uuid::Uuid;
 
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteSort {
+    /// Newest first
+    #[default]
+    Recent,
+    /// Highest Wilson score first
+    Top,
+}
+
 #[derive(Deserialize)]
 pub struct NoteQuery {
     pub num: Option<usize>,
+    #[serde(default)]
+    pub sort: NoteSort,
 }
 
 /// API handler to list all notes.
@@ -30,6 +50,7 @@ pub async fn list_notes(
         &state.db_wrapper,
         query.num.unwrap_or(10),
         user.as_ref().map(|u| u.id),
+        query.sort,
     )
         .await
     {
@@ -40,7 +61,7 @@ pub async fn list_notes(
                     let file_url = state
                         .env_vars
                         .paths
-                        .get_note_url(&format!("{}.pdf", note.note_id))
+                        .get_note_url(note.note_id)
                         .unwrap();
                     let preview_image_url =
                         state
@@ -48,7 +69,7 @@ pub async fn list_notes(
                             .paths
                             .get_preview_url(&format!("{}.jpg", note.note_id))
                             .unwrap();
-                    ResponseNote::from_note_with_user(note, file_url, preview_image_url)
+                    ResponseNote::from_note_with_user(note, file_url, preview_image_url, user.as_ref())
                 })
                 .collect();
             Ok((StatusCode::OK, Json(response_notes).into_response()))
@@ -67,10 +88,19 @@ pub async fn note_by_id(
     tracing::debug!("Fetching note with ID: {}", note_id);
     match get_note_by_id(&state.db_wrapper, note_id, user.as_ref().map(|u| u.id)).await {
         Ok(note) => {
+            // Hidden notes are only visible to their uploader and to moderators
+            let can_view = !note.note_is_hidden
+                || user.as_ref().is_some_and(|u| {
+                    u.id == note.note_uploader_user_id || u.is_moderator()
+                });
+            if !can_view {
+                return Err(NoteError::NotFound("Note not found".to_string()).into());
+            }
+
             let file_url = state
                 .env_vars
                 .paths
-                .get_note_url(&format!("{}.pdf", note.note_id))
+                .get_note_url(note.note_id)
                 .unwrap();
             let preview_image_url =
 
@@ -81,7 +111,7 @@ pub async fn note_by_id(
                     .unwrap();
 
             let response_note =
-                ResponseNote::from_note_with_user(note, file_url, preview_image_url);
+                ResponseNote::from_note_with_user(note, file_url, preview_image_url, user.as_ref());
             Ok((StatusCode::OK, Json(response_note).into_response()))
         }
         Err(err) => {
@@ -114,7 +144,7 @@ pub async fn search_notes(
                     let file_url = state
                         .env_vars
                         .paths
-                        .get_note_url(&format!("{}.pdf", note.note_id))
+                        .get_note_url(note.note_id)
                         .unwrap();
                     let preview_image_url =
 
@@ -125,7 +155,7 @@ pub async fn search_notes(
                             .unwrap();
 
 
-                    ResponseNote::from_note_with_user(note, file_url, preview_image_url)
+                    ResponseNote::from_note_with_user(note, file_url, preview_image_url, user.as_ref())
                 })
                 .collect();
             Ok((StatusCode::OK, Json(response_notes).into_response()))
@@ -143,7 +173,7 @@ async fn generate_preview_image(
     preview_path: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let output = Command::new("pdftoppm")
-        .args(&[
+        .args([
             "-singlefile",
             "-jpeg",
             "-scale-to-x",
@@ -166,10 +196,20 @@ async fn generate_preview_image(
     Ok(())
 }
 
+/// Parses a checkbox style form field
+fn parse_flag(data: &str) -> Result<bool, NoteError> {
+    match data.trim() {
+        "true" | "on" | "1" => Ok(true),
+        "false" | "off" | "0" | "" => Ok(false),
+        _ => Err(NoteError::InvalidData(format!("Invalid value for a flag: {}", data))),
+    }
+}
+
 // Integration into your upload_note function
 pub async fn upload_note(
     State(state): State<RouterState>,
     Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
     mut multipart: Multipart,
 ) -> Result<(StatusCode, Response), AppError> {
     let mut course_name = String::new();
@@ -178,6 +218,7 @@ pub async fn upload_note(
     let mut professor_names: Option<Vec<String>> = None;
     let mut tags: Vec<String> = Vec::new();
     let mut file_data: Option<Bytes> = None;
+    let mut is_anonymous = false;
 
     // New fields with sensible defaults (override from form if present)
     let mut year: usize = 2025;
@@ -231,11 +272,7 @@ pub async fn upload_note(
         match name.as_str() {
             "course_name" => course_name = data,
             "course_code" => course_code = data,
-            "description" => {
-                if !data.trim().is_empty() {
-                    description = Some(data);
-                }
-            }
+            "description" if !data.trim().is_empty() => description = Some(data),
             "professor_names" => {
                 let names: Vec<String> = data
                     .split(',')
@@ -268,6 +305,8 @@ pub async fn upload_note(
                 }
             }
 
+            "anonymous" => is_anonymous = parse_flag(&data)?,
+
             _ => (),
         }
     }
@@ -303,6 +342,7 @@ pub async fn upload_note(
         timestamp: Utc::now(),
         note_year: year,
         note_semester: semester,
+        is_anonymous,
     };
 
     let (mut tx, note) = create_note(&state.db_wrapper, new_note)
@@ -313,7 +353,7 @@ pub async fn upload_note(
     let file_url = state
         .env_vars
         .paths
-        .get_note_url(&format!("{}.pdf", note.id))
+        .get_note_url(note.id)
         .unwrap();
 
     let preview_image_url = state
@@ -331,21 +371,19 @@ pub async fn upload_note(
         tags: note.tags,
         is_public: note.is_public,
         has_preview_image: false,
+        is_hidden: false,
+        is_anonymous: note.is_anonymous,
         preview_image_url: preview_image_url.clone(),
         file_url,
         year: note.note_year,
         semester: note.note_semester,
         upvotes: 0,
         downvotes: 0,
+        comment_count: 0,
+        score: 0.0,
         downloads: 0,
         user_vote: None,
-        uploader_user: ResponseUser {
-            id: user.id,
-            google_id: user.google_id.clone(),
-            email: user.email.clone(),
-            full_name: user.full_name.clone(),
-            created_at: user.created_at,
-        },
+        uploader_user: PublicUser::from(&user),
         created_at: note.created_at,
     };
 
@@ -379,6 +417,19 @@ pub async fn upload_note(
             note_with_user.has_preview_image = true;
         }
         if tx.commit().await.is_ok() {
+            audit::record(
+                &state,
+                &request_id,
+                NewAuditEvent::new(Some(user.id), "note.upload", "note", note_with_user.id)
+                    .after(&note_with_user),
+            )
+            .await;
+            events::publish(
+                &state.db_wrapper,
+                &state.env_vars.paths,
+                Event::NoteCreated { note_id: note_with_user.id },
+            )
+            .await;
             Ok((StatusCode::CREATED, Json(note_with_user).into_response()))
         } else {
             let _ = tokio::fs::remove_file(file_path).await;
@@ -395,17 +446,61 @@ pub async fn upload_note(
     }
 }
 
+/// Works out the IP of the client, looking at the headers set by the reverse proxy if it is trusted.
+pub(crate) fn client_ip(headers: &HeaderMap, peer: SocketAddr, trust_proxy_headers: bool) -> IpAddr {
+    if trust_proxy_headers {
+        let header_ip = |name: &str, pick_last: bool| {
+            let value = headers.get(name)?.to_str().ok()?;
+            // The proxy appends the address it saw, anything before it is client supplied
+            let entry = if pick_last { value.rsplit(',').next() } else { Some(value) };
+            entry?.trim().parse::<IpAddr>().ok()
+        };
+
+        if let Some(ip) = header_ip("x-real-ip", false).or_else(|| header_ip("x-forwarded-for", true)) {
+            return ip;
+        }
+    }
+    peer.ip()
+}
+
+/// Hashes an IP with the signing secret so that download events do not store raw addresses.
+pub(crate) fn hash_ip(secret: &str, ip: IpAddr) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(ip.to_string().as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// API handler to count a download of a note. Repeat downloads by the same user (or IP, when
+/// signed out) within the dedup window are only counted once. Downloads of notes the user cannot
+/// see are not counted.
 pub async fn download_note(
     State(state): State<RouterState>,
+    Extension(user): Extension<Option<User>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Path(note_id): Path<Uuid>,
 ) -> Result<(StatusCode, Response), AppError> {
-    increment_note_downloads(&state.db_wrapper, note_id)
+    fetch_visible_note(&state, user.as_ref(), note_id).await?;
+
+    let user_id = user.as_ref().map(|u| u.id);
+    let ip_hash = match user_id {
+        Some(_) => None,
+        None => {
+            let ip = client_ip(&headers, peer, state.env_vars.trust_proxy_headers);
+            Some(hash_ip(&state.env_vars.signing_secret, ip))
+        }
+    };
+    let dedup_since = Utc::now() - state.env_vars.download_dedup_window;
+
+    let counted = record_note_download(&state.db_wrapper, note_id, user_id, ip_hash, dedup_since)
         .await
         .map_err(|err| {
-            NoteError::DatabaseError("Failed to increment note downloads".to_string(), err.into())
-        })?;
+            NoteError::DatabaseError("Failed to record note download".to_string(), err.into())
+        })?
+        .ok_or(NoteError::NotFound("Note not found".to_string()))?;
 
-    Ok((StatusCode::OK, Json("OK").into_response()))
+    Ok((StatusCode::OK, Json(serde_json::json!({ "counted": counted })).into_response()))
 }
 
 /// Get all notes uploaded by a specific user
@@ -422,14 +517,14 @@ pub async fn get_user_notes(
                     let file_url = state
                         .env_vars
                         .paths
-                        .get_note_url(&format!("{}.pdf", note.note_id))
+                        .get_note_url(note.note_id)
                         .unwrap();
                     let preview_image_url = state
                         .env_vars
                         .paths
                         .get_preview_url(&format!("{}.jpg", note.note_id))
                         .unwrap();
-                    ResponseNote::from_note_with_user(note, file_url, preview_image_url)
+                    ResponseNote::from_note_with_user(note, file_url, preview_image_url, user.as_ref())
                 })
                 .collect();
             Ok((StatusCode::OK, Json(response_notes).into_response()))
@@ -442,21 +537,22 @@ pub async fn get_user_notes(
     }
 }
 
-/// Update an existing note (owner only)
+/// Update an existing note (owner, moderator or admin)
 pub async fn update_note_handler(
     State(state): State<RouterState>,
     Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
     Path(note_id): Path<Uuid>,
     mut multipart: Multipart,
 ) -> Result<(StatusCode, Response), AppError> {
-    // First, verify the user owns this note
+    // First, verify the user owns this note or is allowed to moderate it
     let existing_note = get_note_by_id(&state.db_wrapper, note_id, Some(user.id))
         .await
         .map_err(|err| {
             NoteError::DatabaseError("Failed to fetch note".to_string(), err.into())
         })?;
 
-    if existing_note.note_uploader_user_id != user.id {
+    if existing_note.note_uploader_user_id != user.id && !user.is_moderator() {
         return Err(NoteError::InvalidData("You can only edit your own notes".to_string()).into());
     }
 
@@ -466,6 +562,7 @@ pub async fn update_note_handler(
     let mut professor_names: Option<Vec<String>> = None;
     let mut tags: Vec<String> = Vec::new();
     let mut file_data: Option<Bytes> = None;
+    let mut is_anonymous: Option<bool> = None;
     let mut year: usize = 2025;
     let mut semester: String = "Autumn".to_string();
 
@@ -514,11 +611,7 @@ pub async fn update_note_handler(
         match name.as_str() {
             "course_name" => course_name = data,
             "course_code" => course_code = data,
-            "description" => {
-                if !data.trim().is_empty() {
-                    description = Some(data);
-                }
-            }
+            "description" if !data.trim().is_empty() => description = Some(data),
             "professor_names" => {
                 let names: Vec<String> = data
                     .split(',')
@@ -548,6 +641,7 @@ pub async fn update_note_handler(
                     semester = s.to_string();
                 }
             }
+            "anonymous" => is_anonymous = Some(parse_flag(&data)?),
             _ => (),
         }
     }
@@ -577,12 +671,23 @@ pub async fn update_note_handler(
         tags,
         year,
         semester,
+        is_anonymous,
+        user.id,
     )
     .await
     .map_err(|err| {
         NoteError::DatabaseError("Failed to update note".to_string(), err.into())
     })?;
 
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "note.update", "note", note_id)
+            .before(&existing_note)
+            .after(&updated_note),
+    )
+    .await;
+
     // If a new file was provided, replace the old one
     if let Some(file_bytes) = file_data {
         let file_path = state
@@ -625,7 +730,7 @@ pub async fn update_note_handler(
     let file_url = state
         .env_vars
         .paths
-        .get_note_url(&format!("{}.pdf", note_id))
+        .get_note_url(note_id)
         .unwrap();
     let preview_image_url = state
         .env_vars
@@ -633,25 +738,31 @@ pub async fn update_note_handler(
         .get_preview_url(&format!("{}.jpg", note_id))
         .unwrap();
 
-    let response_note = ResponseNote::from_note_with_user(note_with_user, file_url, preview_image_url);
+    let response_note = ResponseNote::from_note_with_user(
+        note_with_user,
+        file_url,
+        preview_image_url,
+        Some(&user),
+    );
 
     Ok((StatusCode::OK, Json(response_note).into_response()))
 }
 
-/// Delete a note (owner only)
+/// Delete a note (owner, moderator or admin)
 pub async fn delete_note_handler(
     State(state): State<RouterState>,
     Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
     Path(note_id): Path<Uuid>,
 ) -> Result<(StatusCode, Response), AppError> {
-    // First, verify the user owns this note
+    // First, verify the user owns this note or is allowed to moderate it
     let existing_note = get_note_by_id(&state.db_wrapper, note_id, Some(user.id))
         .await
         .map_err(|err| {
             NoteError::DatabaseError("Failed to fetch note".to_string(), err.into())
         })?;
 
-    if existing_note.note_uploader_user_id != user.id {
+    if existing_note.note_uploader_user_id != user.id && !user.is_moderator() {
         return Err(NoteError::InvalidData("You can only delete your own notes".to_string()).into());
     }
 
@@ -675,5 +786,22 @@ pub async fn delete_note_handler(
     let _ = tokio::fs::remove_file(file_path).await;
     let _ = tokio::fs::remove_file(preview_path).await;
 
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "note.delete", "note", note_id).before(&existing_note),
+    )
+    .await;
+    events::publish(
+        &state.db_wrapper,
+        &state.env_vars.paths,
+        Event::NoteDeleted {
+            note_id,
+            course_code: existing_note.note_course_code,
+            course_name: existing_note.note_course_name,
+        },
+    )
+    .await;
+
     Ok((StatusCode::OK, Json("Note deleted successfully").into_response()))
 }
@@ -0,0 +1,89 @@
+use crate::api::errors::{AppError, UserError};
+use crate::api::models::{ResponseNotification, ResponseNotificationPage};
+use crate::api::router::RouterState;
+use crate::db::handlers::notifications::{
+    count_notifications, get_notifications, mark_notifications_read,
+};
+use crate::db::models::User;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use uuid::Uuid;
+
+const MAX_NOTIFICATIONS_PER_PAGE: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct NotificationQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    #[serde(default)]
+    pub unread_only: bool,
+}
+
+#[derive(Deserialize)]
+pub struct MarkReadRequest {
+    /// Marks every notification as read if left out
+    pub ids: Option<Vec<Uuid>>,
+}
+
+/// API handler to list the signed in user's notifications, most recent first
+pub async fn list_notifications(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Query(query): Query<NotificationQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    let per_page = query.per_page.unwrap_or(20).clamp(1, MAX_NOTIFICATIONS_PER_PAGE);
+    let page = query.page.unwrap_or(1).clamp(1, i64::MAX / per_page);
+
+    let notifications = get_notifications(
+        &state.db_wrapper,
+        user.id,
+        query.unread_only,
+        per_page,
+        (page - 1) * per_page,
+    )
+    .await
+    .map_err(|err| UserError::Unknown("Failed to fetch notifications".to_string(), err.into()))?;
+    let total = count_notifications(&state.db_wrapper, user.id, query.unread_only)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to count notifications".to_string(), err.into()))?;
+    let unread = count_notifications(&state.db_wrapper, user.id, true)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to count notifications".to_string(), err.into()))?;
+
+    let response = ResponseNotificationPage {
+        notifications: notifications.into_iter().map(ResponseNotification::from).collect(),
+        page,
+        per_page,
+        total,
+        unread,
+    };
+    Ok((StatusCode::OK, Json(response).into_response()))
+}
+
+/// API handler to count the signed in user's unread notifications
+pub async fn unread_count(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+) -> Result<(StatusCode, Response), AppError> {
+    let unread = count_notifications(&state.db_wrapper, user.id, true)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to count notifications".to_string(), err.into()))?;
+
+    Ok((StatusCode::OK, Json(serde_json::json!({ "unread": unread })).into_response()))
+}
+
+/// API handler to mark some or all of the signed in user's notifications as read
+pub async fn mark_read(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<MarkReadRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let read = mark_notifications_read(&state.db_wrapper, user.id, payload.ids.as_deref())
+        .await
+        .map_err(|err| UserError::Unknown("Failed to update notifications".to_string(), err.into()))?;
+
+    Ok((StatusCode::OK, Json(serde_json::json!({ "read": read })).into_response()))
+}
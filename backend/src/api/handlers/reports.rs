@@ -0,0 +1,301 @@
+use crate::api::errors::{AppError, ModerationError};
+use crate::api::handlers::audit;
+use crate::api::handlers::files::fetch_visible_note;
+use crate::api::models::{ResponseModerationItem, ResponseReport};
+use crate::api::router::RouterState;
+use crate::events::{self, Event};
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::reports::{assign_reports, close_reports, create_report, get_reports_by_status};
+use crate::db::handlers::users::find_user_by_id;
+use crate::db::models::User;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+const MAX_REPORT_DETAILS_LENGTH: usize = 2000;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    WrongContent,
+    Spam,
+    Copyright,
+    Inappropriate,
+    Duplicate,
+    Other,
+}
+
+impl ReportReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportReason::WrongContent => "wrong_content",
+            ReportReason::Spam => "spam",
+            ReportReason::Copyright => "copyright",
+            ReportReason::Inappropriate => "inappropriate",
+            ReportReason::Duplicate => "duplicate",
+            ReportReason::Other => "other",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    pub reason: ReportReason,
+    pub details: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct QueueQuery {
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AssignRequest {
+    /// Defaults to the moderator making the request
+    pub moderator_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct CloseRequest {
+    pub resolution_note: Option<String>,
+}
+
+/// API handler to report a note. Only notes the reporter can see can be reported, and never
+/// their own.
+pub async fn report_note(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(note_id): Path<Uuid>,
+    Json(payload): Json<ReportRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let details = payload
+        .details
+        .map(|details| details.trim().to_string())
+        .filter(|details| !details.is_empty());
+
+    let note = fetch_visible_note(&state, Some(&user), note_id).await?;
+    if note.note_uploader_user_id == user.id {
+        return Err(ModerationError::InvalidData("You cannot report your own note".to_string()).into());
+    }
+
+    if let ReportReason::Other = payload.reason {
+        if details.is_none() {
+            return Err(ModerationError::InvalidData(
+                "Please describe the problem when reporting for another reason".to_string(),
+            )
+            .into());
+        }
+    }
+    if details.as_ref().is_some_and(|d| d.chars().count() > MAX_REPORT_DETAILS_LENGTH) {
+        return Err(ModerationError::InvalidData(format!(
+            "Details are too long. Only up to {} characters are allowed.",
+            MAX_REPORT_DETAILS_LENGTH
+        ))
+        .into());
+    }
+
+    let (report, hidden) = create_report(
+        &state.db_wrapper,
+        note_id,
+        user.id,
+        payload.reason.as_str(),
+        details,
+        state.env_vars.report_hide_threshold,
+    )
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::Database(err) if err.constraint() == Some("note_reports_note_id_fkey") => {
+            ModerationError::NotFound("Note not found".to_string())
+        }
+        sqlx::Error::Database(err) if err.constraint() == Some("note_reports_open_unique") => {
+            ModerationError::Conflict("You have already reported this note".to_string())
+        }
+        err => ModerationError::DatabaseError("Failed to report note".to_string(), err.into()),
+    })?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "report.create", "note", note_id).after(&report),
+    )
+    .await;
+    events::publish(
+        &state.db_wrapper,
+        &state.env_vars.paths,
+        Event::NoteReported {
+            note_id,
+            report_id: report.id,
+            reason: report.reason.clone(),
+            auto_hidden: hidden,
+        },
+    )
+    .await;
+    if hidden {
+        // Hidden by the system rather than by the reporter, so there is no actor
+        audit::record(
+            &state,
+            &request_id,
+            NewAuditEvent::new(None, "note.auto_hide", "note", note_id),
+        )
+        .await;
+        events::publish(
+            &state.db_wrapper,
+            &state.env_vars.paths,
+            Event::NoteAutoHidden { note_id },
+        )
+        .await;
+    }
+
+    Ok((StatusCode::CREATED, Json(ResponseReport::from(report)).into_response()))
+}
+
+/// API handler to list the moderation queue, reports grouped by note
+pub async fn get_moderation_queue(
+    State(state): State<RouterState>,
+    Query(query): Query<QueueQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    let status = query.status.unwrap_or_else(|| "open".to_string());
+    if !["open", "resolved", "dismissed"].contains(&status.as_str()) {
+        return Err(ModerationError::InvalidData(format!(
+            "Incorrect status: {}. Available options are: open, resolved and dismissed",
+            status
+        ))
+        .into());
+    }
+
+    let reports = get_reports_by_status(&state.db_wrapper, &status)
+        .await
+        .map_err(|err| {
+            ModerationError::DatabaseError("Failed to fetch reports".to_string(), err.into())
+        })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ResponseModerationItem::group_reports(reports)).into_response(),
+    ))
+}
+
+/// API handler to assign the open reports on a note to a moderator
+pub async fn assign_queue_item(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(note_id): Path<Uuid>,
+    Json(payload): Json<AssignRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let moderator_id = payload.moderator_id.unwrap_or(user.id);
+    if moderator_id != user.id {
+        let assignee = find_user_by_id(&state.db_wrapper, moderator_id)
+            .await
+            .map_err(|err| ModerationError::DatabaseError("Failed to fetch moderator".to_string(), err.into()))?;
+        if !assignee.is_some_and(|assignee| assignee.is_moderator() && !assignee.is_banned()) {
+            return Err(ModerationError::NotFound("Moderator not found".to_string()).into());
+        }
+    }
+
+    let assigned = assign_reports(&state.db_wrapper, note_id, moderator_id)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::Database(err) if err.constraint() == Some("note_reports_assigned_to_fkey") => {
+                ModerationError::NotFound("Moderator not found".to_string())
+            }
+            err => ModerationError::DatabaseError("Failed to assign reports".to_string(), err.into()),
+        })?;
+
+    if assigned == 0 {
+        return Err(ModerationError::NotFound("No open reports for this note".to_string()).into());
+    }
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "report.assign", "note", note_id)
+            .after(&serde_json::json!({ "assigned_to": moderator_id, "reports": assigned })),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(serde_json::json!({ "assigned": assigned })).into_response()))
+}
+
+async fn close_queue_item(
+    state: &RouterState,
+    user: &User,
+    request_id: &RequestId,
+    note_id: Uuid,
+    payload: CloseRequest,
+    status: &str,
+) -> Result<(StatusCode, Response), AppError> {
+    // Upheld reports hide the note, dismissed ones restore it if the reports hid it
+    let hide_note = status == "resolved";
+    let resolution_note = payload.resolution_note;
+
+    let (reporter_ids, is_hidden) = close_reports(
+        &state.db_wrapper,
+        note_id,
+        user.id,
+        status,
+        resolution_note.clone(),
+        hide_note,
+    )
+    .await
+    .map_err(|err| {
+        ModerationError::DatabaseError("Failed to close reports".to_string(), err.into())
+    })?;
+
+    if reporter_ids.is_empty() {
+        return Err(ModerationError::NotFound("No open reports for this note".to_string()).into());
+    }
+    let closed = reporter_ids.len();
+
+    let action = if hide_note { "report.resolve" } else { "report.dismiss" };
+    audit::record(
+        state,
+        request_id,
+        NewAuditEvent::new(Some(user.id), action, "note", note_id).after(&serde_json::json!({
+            "reports": closed,
+            "resolution_note": resolution_note,
+            "is_hidden": is_hidden,
+        })),
+    )
+    .await;
+    events::publish(
+        &state.db_wrapper,
+        &state.env_vars.paths,
+        Event::ReportsClosed {
+            note_id,
+            reporter_ids,
+            upheld: hide_note,
+        },
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(serde_json::json!({ "status": status, "closed": closed })).into_response()))
+}
+
+/// API handler to uphold the open reports on a note. The note is hidden.
+pub async fn resolve_queue_item(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(note_id): Path<Uuid>,
+    Json(payload): Json<CloseRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    close_queue_item(&state, &user, &request_id, note_id, payload, "resolved").await
+}
+
+/// API handler to dismiss the open reports on a note. The note is made visible again if it was
+/// hidden by reaching the report threshold, but not if an earlier report against it was upheld.
+pub async fn dismiss_queue_item(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(note_id): Path<Uuid>,
+    Json(payload): Json<CloseRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    close_queue_item(&state, &user, &request_id, note_id, payload, "dismissed").await
+}
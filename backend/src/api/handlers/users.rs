@@ -1,16 +1,161 @@
 use crate::api::errors::{AppError, UserError};
+use crate::api::handlers::audit;
+use crate::api::models::{
+    PublicUser, ResponseBadge, ResponseLeaderboardEntry, ResponseUserProfile, ResponseUserStats,
+};
 use crate::api::router::RouterState;
-use crate::db::handlers::users::{get_leaderboard, get_user_leaderboard_position};
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::badges::get_badges_for_users;
+use crate::db::handlers::leaderboard::{
+    get_leaderboard, get_user_leaderboard_position, LeaderboardEntry, LeaderboardWindow,
+};
+use crate::db::handlers::users::{
+    find_user_by_id, get_user_contributions, update_user_profile, UserProfile,
+};
+use crate::db::models::User;
 use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use axum::Json;
-use serde::Deserialize;
+use axum::{Extension, Json};
+use chrono::{Datelike, Utc};
+use serde::{Deserialize, Deserializer};
+use tower_http::request_id::RequestId;
 use uuid::Uuid;
 
+const MAX_DISPLAY_NAME_LENGTH: usize = 100;
+const MAX_BIO_LENGTH: usize = 1000;
+const MAX_DEPARTMENT_LENGTH: usize = 100;
+/// The year the institute was founded
+const EARLIEST_GRADUATION_YEAR: i32 = 1951;
+
 #[derive(Deserialize)]
 pub struct LeaderboardQuery {
     pub limit: Option<i64>,
+    #[serde(default)]
+    pub window: LeaderboardWindow,
+}
+
+#[derive(Deserialize)]
+pub struct LeaderboardPositionQuery {
+    #[serde(default)]
+    pub window: LeaderboardWindow,
+}
+
+/// Changes to a user's profile. Fields that are left out are not changed, fields set to `null`
+/// are cleared.
+#[derive(Deserialize)]
+pub struct UpdateProfileRequest {
+    #[serde(default, deserialize_with = "present")]
+    pub display_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub bio: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub department: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub graduation_year: Option<Option<i32>>,
+    pub show_initials_only: Option<bool>,
+}
+
+/// Tells a field set to `null` (`Some(None)`) apart from a missing one (`None`)
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Trims a text field, treating blank text as cleared
+fn text_field(
+    value: Option<String>,
+    field: &str,
+    max_length: usize,
+) -> Result<Option<String>, UserError> {
+    let Some(value) = value.map(|value| value.trim().to_string()) else {
+        return Ok(None);
+    };
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if value.chars().count() > max_length {
+        return Err(UserError::InvalidData(format!(
+            "The {} can be at most {} characters long",
+            field, max_length
+        )));
+    }
+    Ok(Some(value))
+}
+
+/// Display names are made of letters, digits and single spaces along with `.`, `'` and `-`, and
+/// have at least one letter. Runs of whitespace are collapsed into a single space.
+fn display_name_field(value: Option<String>) -> Result<Option<String>, UserError> {
+    let Some(name) = text_field(value, "display name", MAX_DISPLAY_NAME_LENGTH)? else {
+        return Ok(None);
+    };
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let allowed = |c: char| c.is_alphanumeric() || matches!(c, ' ' | '.' | '\'' | '-');
+    if !name.chars().all(allowed) {
+        return Err(UserError::InvalidData(
+            "The display name can only contain letters, digits, spaces, periods, apostrophes and hyphens"
+                .to_string(),
+        ));
+    }
+    if !name.chars().any(char::is_alphabetic) {
+        return Err(UserError::InvalidData(
+            "The display name must contain at least one letter".to_string(),
+        ));
+    }
+    Ok(Some(name))
+}
+
+impl UpdateProfileRequest {
+    /// Applies the changes on top of the current profile
+    fn apply(self, mut profile: UserProfile) -> Result<UserProfile, UserError> {
+        if let Some(display_name) = self.display_name {
+            profile.display_name = display_name_field(display_name)?;
+        }
+        if let Some(bio) = self.bio {
+            profile.bio = text_field(bio, "bio", MAX_BIO_LENGTH)?;
+        }
+        if let Some(department) = self.department {
+            profile.department = text_field(department, "department", MAX_DEPARTMENT_LENGTH)?;
+        }
+        if let Some(graduation_year) = self.graduation_year {
+            let latest = Utc::now().year() + 10;
+            if let Some(year) = graduation_year.filter(|year| !(EARLIEST_GRADUATION_YEAR..=latest).contains(year)) {
+                return Err(UserError::InvalidData(format!(
+                    "Invalid graduation year {}",
+                    year
+                )));
+            }
+            profile.graduation_year = graduation_year;
+        }
+        if let Some(show_initials_only) = self.show_initials_only {
+            profile.show_initials_only = show_initials_only;
+        }
+        Ok(profile)
+    }
+}
+
+/// Attaches the badges of each user to their leaderboard entries
+async fn with_badges(
+    state: &RouterState,
+    entries: Vec<LeaderboardEntry>,
+) -> Result<Vec<ResponseLeaderboardEntry>, UserError> {
+    let user_ids: Vec<Uuid> = entries.iter().map(|entry| entry.id).collect();
+    let badges = get_badges_for_users(&state.db_wrapper, &user_ids)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch badges".to_string(), err.into()))?;
+    let mut badges = ResponseBadge::group_by_user(badges);
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| ResponseLeaderboardEntry {
+            badges: badges.remove(&entry.id).unwrap_or_default(),
+            entry,
+        })
+        .collect())
 }
 
 /// API handler to get the leaderboard
@@ -20,8 +165,11 @@ pub async fn get_leaderboard_handler(
 ) -> Result<(StatusCode, Response), AppError> {
     let limit = query.limit.unwrap_or(20);
 
-    match get_leaderboard(&state.db_wrapper, limit).await {
-        Ok(leaderboard) => Ok((StatusCode::OK, Json(leaderboard).into_response())),
+    match get_leaderboard(&state.db_wrapper, query.window, limit).await {
+        Ok(leaderboard) => {
+            let leaderboard = with_badges(&state, leaderboard).await?;
+            Ok((StatusCode::OK, Json(leaderboard).into_response()))
+        }
         Err(err) => {
             tracing::error!("Failed to fetch leaderboard: {:?}", err);
             Err(UserError::Unknown(
@@ -37,9 +185,13 @@ pub async fn get_leaderboard_handler(
 pub async fn get_user_position_handler(
     State(state): State<RouterState>,
     Path(user_id): Path<Uuid>,
+    Query(query): Query<LeaderboardPositionQuery>,
 ) -> Result<(StatusCode, Response), AppError> {
-    match get_user_leaderboard_position(&state.db_wrapper, user_id).await {
-        Ok(Some(position)) => Ok((StatusCode::OK, Json(position).into_response())),
+    match get_user_leaderboard_position(&state.db_wrapper, user_id, query.window).await {
+        Ok(Some(position)) => {
+            let position = with_badges(&state, vec![position]).await?.pop();
+            Ok((StatusCode::OK, Json(position).into_response()))
+        }
         Ok(None) => Ok((
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({"error": "User not found"})).into_response(),
@@ -54,3 +206,79 @@ pub async fn get_user_position_handler(
         }
     }
 }
+
+/// API handler to get a user's public profile and contribution stats
+pub async fn get_user_profile_handler(
+    State(state): State<RouterState>,
+    Extension(viewer): Extension<Option<User>>,
+    Path(user_id): Path<Uuid>,
+) -> Result<(StatusCode, Response), AppError> {
+    let user = find_user_by_id(&state.db_wrapper, user_id)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch user".to_string(), err.into()))?
+        .ok_or_else(|| UserError::NotFound("User not found".to_string()))?;
+
+    let position = get_user_leaderboard_position(&state.db_wrapper, user.id, LeaderboardWindow::All)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch user stats".to_string(), err.into()))?
+        .ok_or_else(|| UserError::NotFound("User not found".to_string()))?;
+    let is_own_profile = viewer.is_some_and(|viewer| viewer.id == user.id);
+    let contributions = get_user_contributions(&state.db_wrapper, user.id, is_own_profile)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch user stats".to_string(), err.into()))?;
+    let badges = get_badges_for_users(&state.db_wrapper, &[user.id])
+        .await
+        .map_err(|err| UserError::Unknown("Failed to fetch badges".to_string(), err.into()))?;
+
+    let profile = ResponseUserProfile {
+        user: PublicUser::from(&user),
+        bio: user.bio,
+        department: user.department,
+        graduation_year: user.graduation_year,
+        member_since: user.created_at,
+        stats: ResponseUserStats {
+            reputation: position.reputation,
+            rank: position.rank,
+            // Counted over the notes the viewer can see, the leaderboard totals include hidden
+            // and anonymous notes
+            total_notes: contributions.total_notes,
+            total_upvotes: contributions.total_upvotes,
+            total_downvotes: contributions.total_downvotes,
+            total_downloads: contributions.total_downloads,
+            courses_covered: contributions.courses_covered,
+            total_comments: contributions.total_comments,
+        },
+        badges: ResponseBadge::group_by_user(badges)
+            .remove(&user.id)
+            .unwrap_or_default(),
+    };
+
+    Ok((StatusCode::OK, Json(profile).into_response()))
+}
+
+/// API handler to edit the signed in user's profile
+pub async fn update_profile_handler(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Json(payload): Json<UpdateProfileRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let existing_profile = UserProfile::from(&user);
+    let profile = payload.apply(existing_profile.clone())?;
+
+    let user = update_user_profile(&state.db_wrapper, user.id, &profile)
+        .await
+        .map_err(|err| UserError::Unknown("Failed to update profile".to_string(), err.into()))?
+        .ok_or(UserError::NotFound("User not found".to_string()))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "user.profile", "user", user.id)
+            .before(&existing_profile)
+            .after(&profile),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(user).into_response()))
+}
@@ -0,0 +1,86 @@
+use crate::api::errors::{AppError, ModerationError};
+use crate::api::handlers::audit;
+use crate::api::router::RouterState;
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::vote_rings::{get_vote_rings_by_status, review_vote_ring};
+use crate::db::models::User;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct VoteRingQuery {
+    pub status: Option<String>,
+}
+
+/// API handler to list the vote rings flagged by the detection job
+pub async fn list_vote_rings(
+    State(state): State<RouterState>,
+    Query(query): Query<VoteRingQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    let status = query.status.unwrap_or_else(|| "open".to_string());
+    if !["open", "confirmed", "cleared"].contains(&status.as_str()) {
+        return Err(ModerationError::InvalidData(format!(
+            "Incorrect status: {}. Available options are: open, confirmed and cleared",
+            status
+        ))
+        .into());
+    }
+
+    let rings = get_vote_rings_by_status(&state.db_wrapper, &status)
+        .await
+        .map_err(|err| {
+            ModerationError::DatabaseError("Failed to fetch vote rings".to_string(), err.into())
+        })?;
+
+    Ok((StatusCode::OK, Json(rings).into_response()))
+}
+
+async fn review(
+    state: &RouterState,
+    user: &User,
+    request_id: &RequestId,
+    ring_id: Uuid,
+    status: &str,
+) -> Result<(StatusCode, Response), AppError> {
+    let ring = review_vote_ring(&state.db_wrapper, ring_id, status, user.id)
+        .await
+        .map_err(|err| {
+            ModerationError::DatabaseError("Failed to update vote ring".to_string(), err.into())
+        })?
+        .ok_or(ModerationError::NotFound("Vote ring not found".to_string()))?;
+
+    let action = if status == "confirmed" { "vote_ring.confirm" } else { "vote_ring.clear" };
+    audit::record(
+        state,
+        request_id,
+        NewAuditEvent::new(Some(user.id), action, "vote_ring", ring_id).after(&ring),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(ring).into_response()))
+}
+
+/// API handler to confirm a vote ring. Votes between its members stop counting towards reputation.
+pub async fn confirm_vote_ring(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(ring_id): Path<Uuid>,
+) -> Result<(StatusCode, Response), AppError> {
+    review(&state, &user, &request_id, ring_id, "confirmed").await
+}
+
+/// API handler to clear a flagged vote ring as a false positive. Any excluded votes are restored.
+pub async fn clear_vote_ring(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(ring_id): Path<Uuid>,
+) -> Result<(StatusCode, Response), AppError> {
+    review(&state, &user, &request_id, ring_id, "cleared").await
+}
@@ -4,41 +4,95 @@ use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use serde::Deserialize;
 use crate::api::errors::{AppError, NoteError};
+use crate::api::handlers::audit;
 use crate::api::router::RouterState;
+use crate::events::{self, Event};
 use crate::db::models::User;
-use crate::db::handlers::votes::vote;
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::votes::{vote, VoteError};
+use tower_http::request_id::RequestId;
 
-#[derive(Deserialize)]
 pub enum VoteType {
-    Upvote(bool),
+    Upvote,
+    Downvote(DownvoteReason),
     Remove
 }
 
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DownvoteReason {
+    Outdated,
+    WrongCourse,
+    Illegible,
+    Incomplete,
+    Other,
+}
+
+impl DownvoteReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownvoteReason::Outdated => "outdated",
+            DownvoteReason::WrongCourse => "wrong_course",
+            DownvoteReason::Illegible => "illegible",
+            DownvoteReason::Incomplete => "incomplete",
+            DownvoteReason::Other => "other",
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct VoteRequest {
     vote_type: String,
+    /// Required for downvotes
+    reason: Option<DownvoteReason>,
 }
 
 pub async fn add_vote (
     State(state): State<RouterState>,
     Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
     Path(note_id): Path<uuid::Uuid>,
     Query(query): Query<VoteRequest>,
 ) -> Result<(StatusCode, Response), AppError> {
     let vote_type = match query.vote_type.as_str() {
-        "upvote" => VoteType::Upvote(true),
-        // Remove
-        // "downvote" => VoteType::Upvote(false),
+        "upvote" => VoteType::Upvote,
+        "downvote" => match query.reason {
+            Some(reason) => VoteType::Downvote(reason),
+            None => {
+                return Err(NoteError::BadVote("Please pick a reason for the downvote. Available options are: outdated, wrong_course, illegible, incomplete and other".to_string()).into());
+            }
+        },
         "remove" => VoteType::Remove,
         _ => {
-            return Err(NoteError::BadVote(format!("Incorrect vote type: {}. Available options are: upvote and remove", query.vote_type)).into());
+            return Err(NoteError::BadVote(format!("Incorrect vote type: {}. Available options are: upvote, downvote and remove", query.vote_type)).into());
         }
     };
-    let v = vote(&state.db_wrapper, user.id, note_id, vote_type)
+    let outcome = vote(&state.db_wrapper, user.id, note_id, vote_type)
         .await
-        .map_err(|e| {
-            tracing::error!("Failed to add vote: {}", e);
-            NoteError::DatabaseError("Failed to add vote".to_string(), e.into())
+        .map_err(|e| match e {
+            VoteError::NoteNotFound => NoteError::NotFound("Note not found".to_string()),
+            VoteError::SelfVote => NoteError::BadVote("You cannot vote on your own note".to_string()),
+            VoteError::Database(e) => {
+                tracing::error!("Failed to add vote: {}", e);
+                NoteError::DatabaseError("Failed to add vote".to_string(), e.into())
+            }
         })?;
+    let v = outcome.vote;
+    let action = if v.is_some() { "vote.cast" } else { "vote.remove" };
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), action, "note", note_id).after(&v),
+    )
+    .await;
+    // Changing a downvote's reason or upvoting again does not notify the uploader
+    if outcome.upvoted {
+        events::publish(
+            &state.db_wrapper,
+            &state.env_vars.paths,
+            Event::NoteUpvoted { note_id },
+        )
+        .await;
+    }
     Ok((StatusCode::OK, Json(v).into_response()))
 }
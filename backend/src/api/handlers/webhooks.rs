@@ -0,0 +1,275 @@
+use crate::api::errors::{AppError, ModerationError};
+use crate::api::handlers::audit;
+use crate::api::models::{ResponseNewWebhook, ResponseWebhookDelivery, ResponseWebhookDeliveryPage};
+use crate::api::router::RouterState;
+use crate::db::handlers::audit::NewAuditEvent;
+use crate::db::handlers::webhooks::{
+    count_deliveries, create_webhook, delete_webhook, get_attempts, get_deliveries, get_webhook,
+    get_webhooks, retry_delivery, update_webhook, WebhookSettings,
+};
+use crate::db::models::User;
+use crate::webhooks::{event_types, generate_secret};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+const MAX_DELIVERIES_PER_PAGE: i64 = 100;
+const MAX_WEBHOOK_URL_LENGTH: usize = 2000;
+const MAX_WEBHOOK_DESCRIPTION_LENGTH: usize = 500;
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// e.g. `["note.created", "note.reported"]`
+    pub event_types: Vec<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub event_types: Option<Vec<String>>,
+    /// An empty description clears it
+    pub description: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct DeliveryQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    /// `pending`, `delivered` or `failed`
+    pub status: Option<String>,
+}
+
+/// Checks and normalises the settings of a webhook before they are saved
+fn validate_settings(mut settings: WebhookSettings) -> Result<WebhookSettings, ModerationError> {
+    settings.url = settings.url.trim().to_string();
+    let url = reqwest::Url::parse(&settings.url)
+        .map_err(|_| ModerationError::InvalidData("Invalid webhook URL".to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(ModerationError::InvalidData("Webhook URLs must be http or https".to_string()));
+    }
+    if settings.url.len() > MAX_WEBHOOK_URL_LENGTH {
+        return Err(ModerationError::InvalidData("Webhook URL is too long".to_string()));
+    }
+
+    settings.event_types.sort();
+    settings.event_types.dedup();
+    if settings.event_types.is_empty() {
+        return Err(ModerationError::InvalidData("Subscribe to at least one event type".to_string()));
+    }
+    if let Some(unknown) = settings
+        .event_types
+        .iter()
+        .find(|event_type| !event_types::ALL.contains(&event_type.as_str()))
+    {
+        return Err(ModerationError::InvalidData(format!(
+            "Unknown event type {}. Webhooks can subscribe to {}.",
+            unknown,
+            event_types::ALL.join(", ")
+        )));
+    }
+
+    settings.description = settings
+        .description
+        .map(|description| description.trim().to_string())
+        .filter(|description| !description.is_empty());
+    if settings
+        .description
+        .as_ref()
+        .is_some_and(|description| description.chars().count() > MAX_WEBHOOK_DESCRIPTION_LENGTH)
+    {
+        return Err(ModerationError::InvalidData(format!(
+            "Description is too long. Only up to {} characters are allowed.",
+            MAX_WEBHOOK_DESCRIPTION_LENGTH
+        )));
+    }
+
+    Ok(settings)
+}
+
+/// API handler to list every webhook
+pub async fn list_webhooks(State(state): State<RouterState>) -> Result<(StatusCode, Response), AppError> {
+    let webhooks = get_webhooks(&state.db_wrapper)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to fetch webhooks".to_string(), err.into()))?;
+
+    Ok((StatusCode::OK, Json(webhooks).into_response()))
+}
+
+/// API handler to register a webhook. The response holds the secret its payloads are signed with,
+/// which is not shown again.
+pub async fn create_webhook_handler(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let settings = validate_settings(WebhookSettings {
+        url: payload.url,
+        event_types: payload.event_types,
+        description: payload.description,
+        is_active: true,
+    })?;
+    let secret = generate_secret();
+
+    let webhook = create_webhook(&state.db_wrapper, &settings, &secret, user.id)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to create webhook".to_string(), err.into()))?;
+
+    // The secret is left out of the audit log
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "webhook.create", "webhook", webhook.id).after(&webhook),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(ResponseNewWebhook { webhook, secret }).into_response()))
+}
+
+/// API handler to change a webhook, or pause it with `"is_active": false`. Events raised while a
+/// webhook is paused are not queued for it.
+pub async fn update_webhook_handler(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(webhook_id): Path<Uuid>,
+    Json(payload): Json<UpdateWebhookRequest>,
+) -> Result<(StatusCode, Response), AppError> {
+    let existing = get_webhook(&state.db_wrapper, webhook_id)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to fetch webhook".to_string(), err.into()))?
+        .ok_or_else(|| ModerationError::NotFound("Webhook not found".to_string()))?;
+
+    let before = WebhookSettings::from(&existing);
+    let settings = validate_settings(WebhookSettings {
+        url: payload.url.unwrap_or_else(|| before.url.clone()),
+        event_types: payload.event_types.unwrap_or_else(|| before.event_types.clone()),
+        description: payload.description.or_else(|| before.description.clone()),
+        is_active: payload.is_active.unwrap_or(before.is_active),
+    })?;
+
+    let webhook = update_webhook(&state.db_wrapper, webhook_id, &settings)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to update webhook".to_string(), err.into()))?
+        .ok_or_else(|| ModerationError::NotFound("Webhook not found".to_string()))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "webhook.update", "webhook", webhook_id)
+            .before(&before)
+            .after(&settings),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(webhook).into_response()))
+}
+
+/// API handler to delete a webhook along with its delivery log
+pub async fn delete_webhook_handler(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path(webhook_id): Path<Uuid>,
+) -> Result<(StatusCode, Response), AppError> {
+    let existing = get_webhook(&state.db_wrapper, webhook_id)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to fetch webhook".to_string(), err.into()))?
+        .ok_or_else(|| ModerationError::NotFound("Webhook not found".to_string()))?;
+
+    let deleted = delete_webhook(&state.db_wrapper, webhook_id)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to delete webhook".to_string(), err.into()))?;
+    if !deleted {
+        return Err(ModerationError::NotFound("Webhook not found".to_string()).into());
+    }
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "webhook.delete", "webhook", webhook_id).before(&existing),
+    )
+    .await;
+
+    Ok((StatusCode::NO_CONTENT, ().into_response()))
+}
+
+/// API handler to page through the delivery log of a webhook, most recent first, with every
+/// attempt at each delivery
+pub async fn list_deliveries(
+    State(state): State<RouterState>,
+    Path(webhook_id): Path<Uuid>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<(StatusCode, Response), AppError> {
+    let per_page = query.per_page.unwrap_or(20).clamp(1, MAX_DELIVERIES_PER_PAGE);
+    let page = query.page.unwrap_or(1).clamp(1, i64::MAX / per_page);
+    let status = query.status.as_deref();
+    if status.is_some_and(|status| !matches!(status, "pending" | "delivered" | "failed")) {
+        return Err(ModerationError::InvalidData("Invalid delivery status".to_string()).into());
+    }
+
+    get_webhook(&state.db_wrapper, webhook_id)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to fetch webhook".to_string(), err.into()))?
+        .ok_or_else(|| ModerationError::NotFound("Webhook not found".to_string()))?;
+
+    let deliveries = get_deliveries(&state.db_wrapper, webhook_id, status, per_page, (page - 1) * per_page)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to fetch deliveries".to_string(), err.into()))?;
+    let total = count_deliveries(&state.db_wrapper, webhook_id, status)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to count deliveries".to_string(), err.into()))?;
+
+    let delivery_ids: Vec<Uuid> = deliveries.iter().map(|delivery| delivery.id).collect();
+    let attempts = get_attempts(&state.db_wrapper, &delivery_ids)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to fetch attempts".to_string(), err.into()))?;
+    let mut attempts_by_delivery: HashMap<Uuid, Vec<_>> = HashMap::new();
+    for attempt in attempts {
+        attempts_by_delivery.entry(attempt.delivery_id).or_default().push(attempt);
+    }
+
+    let deliveries = deliveries
+        .into_iter()
+        .map(|delivery| ResponseWebhookDelivery {
+            attempt_log: attempts_by_delivery.remove(&delivery.id).unwrap_or_default(),
+            delivery,
+        })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(ResponseWebhookDeliveryPage { deliveries, page, per_page, total }).into_response(),
+    ))
+}
+
+/// API handler to send a pending or failed delivery again right away
+pub async fn retry_delivery_handler(
+    State(state): State<RouterState>,
+    Extension(user): Extension<User>,
+    Extension(request_id): Extension<RequestId>,
+    Path((webhook_id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<(StatusCode, Response), AppError> {
+    let delivery = retry_delivery(&state.db_wrapper, webhook_id, delivery_id)
+        .await
+        .map_err(|err| ModerationError::DatabaseError("Failed to retry delivery".to_string(), err.into()))?
+        .ok_or_else(|| ModerationError::NotFound("No undelivered delivery found".to_string()))?;
+
+    audit::record(
+        &state,
+        &request_id,
+        NewAuditEvent::new(Some(user.id), "webhook.retry", "webhook", webhook_id)
+            .after(&serde_json::json!({ "delivery_id": delivery_id })),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(delivery).into_response()))
+}
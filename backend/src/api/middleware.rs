@@ -1,7 +1,7 @@
 use crate::api::errors::{AppError, AuthError};
 use crate::api::router::RouterState;
 use crate::db;
-use crate::db::models::User;
+use crate::db::models::{Role, User};
 use axum::body::Body;
 use axum::extract::{Request, State};
 use axum::http::StatusCode;
@@ -10,6 +10,9 @@ use axum::response::Response;
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
 
+/// Route of the signed in user's account, relative to `/api`
+const ACCOUNT_PATH: &str = "/users/me";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppClaims {
     pub(crate) google_id: String,
@@ -74,6 +77,23 @@ pub(crate) async fn verify_token_middleware(
                 ));
             }
         };
+
+        // Suspended and banned users keep read access but cannot change anything, apart from
+        // deleting their account. Exporting it is a read and is allowed already.
+        let is_write = !matches!(*request.method(), axum::http::Method::GET | axum::http::Method::HEAD);
+        let is_account_deletion = request.method() == axum::http::Method::DELETE
+            && request.uri().path() == ACCOUNT_PATH;
+        if is_write && !is_account_deletion && user.is_suspended() {
+            let message = match user.suspended_until {
+                Some(until) if !user.is_banned() => format!(
+                    "Your account is suspended until {}",
+                    until.format("%Y-%m-%d %H:%M UTC")
+                ),
+                _ => "Your account has been banned".to_string(),
+            };
+            return Ok(create_cors_error_response(StatusCode::FORBIDDEN, &message));
+        }
+
         request.extensions_mut().insert(user);
         tracing::debug!("Valid token found for user");
     } else {
@@ -137,4 +157,43 @@ pub(crate) async fn optional_auth_middleware(
     let response = next.run(request).await;
 
     Ok(response)
-}
\ No newline at end of file
+}
+
+// Rejects users below the given role, must run after `verify_token_middleware`
+async fn require_role(
+    role: Role,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, AppError> {
+    if request.method() == axum::http::Method::OPTIONS {
+        return Ok(next.run(request).await);
+    }
+
+    match request.extensions().get::<User>() {
+        Some(user) if user.role >= role => Ok(next.run(request).await),
+        Some(_) => Ok(create_cors_error_response(
+            StatusCode::FORBIDDEN,
+            &format!("Access restricted to {}s", role.as_str())
+        )),
+        None => Ok(create_cors_error_response(
+            StatusCode::UNAUTHORIZED,
+            "Missing authorization header"
+        )),
+    }
+}
+
+// Moderator-only middleware (admins are allowed as well)
+pub(crate) async fn require_moderator_middleware(
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, AppError> {
+    require_role(Role::Moderator, request, next).await
+}
+
+// Admin-only middleware
+pub(crate) async fn require_admin_middleware(
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, AppError> {
+    require_role(Role::Admin, request, next).await
+}
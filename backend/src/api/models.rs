@@ -1,7 +1,16 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::db::models::NoteWithUser;
+use std::collections::HashMap;
+use crate::badges::find_badge;
+use crate::events::kinds;
+use crate::db::handlers::follows::FollowWithUser;
+use crate::db::handlers::leaderboard::LeaderboardEntry;
+use crate::db::handlers::users::DEFAULT_PICTURE;
+use crate::db::models::{
+    Comment, CommentWithUser, NoteReport, NoteWithUser, Notification, ReportWithNote, User,
+    UserBadge, Webhook, WebhookAttempt, WebhookDelivery,
+};
 
 
 
@@ -17,15 +26,38 @@ pub struct CreateNote {
     pub timestamp: DateTime<Utc>,
     pub note_year: usize,
     pub note_semester: String,
+    pub is_anonymous: bool,
 }
 
+/// What anyone can see about a user. Everything else, the email and Google ID in particular, is
+/// only returned to the user themselves by `/auth/me`.
 #[derive(Serialize, Deserialize, Debug)]
-pub struct ResponseUser {
-    pub id: Uuid,
-    pub google_id: String,
-    pub email: String,
-    pub full_name: String,
-    pub created_at: DateTime<Utc>,
+pub struct PublicUser {
+    /// `None` in place of the uploader of an anonymous note
+    pub id: Option<Uuid>,
+    pub display_name: String,
+    pub picture: String,
+}
+
+impl From<&User> for PublicUser {
+    fn from(user: &User) -> Self {
+        Self {
+            id: Some(user.id),
+            display_name: user.public_name.clone(),
+            picture: user.public_picture.clone(),
+        }
+    }
+}
+
+impl PublicUser {
+    /// Shown in place of the uploader of an anonymous note
+    pub fn anonymous() -> Self {
+        Self {
+            id: None,
+            display_name: "Anonymous".to_string(),
+            picture: DEFAULT_PICTURE.to_string(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -38,12 +70,18 @@ pub struct ResponseNote {
     pub tags: Vec<String>,
     pub is_public: bool,
     pub has_preview_image: bool,
+    pub is_hidden: bool,
+    /// The uploader is only shown to themselves and to moderators
+    pub is_anonymous: bool,
     pub preview_image_url: String,
     pub file_url: String,
-    pub uploader_user: ResponseUser,
+    pub uploader_user: PublicUser,
     pub created_at: DateTime<Utc>,
     pub upvotes: usize,
     pub downvotes: usize,
+    pub comment_count: usize,
+    /// Lower bound of the Wilson score interval over the note's votes, used for "top" ordering
+    pub score: f64,
     pub user_vote: Option<bool>, // If currently authenticated user has voted on this note
     pub downloads: usize,
     pub year: i64,
@@ -51,11 +89,26 @@ pub struct ResponseNote {
 }
 
 impl ResponseNote {
+    /// Builds the response as seen by `viewer`, hiding the uploader of anonymous notes from
+    /// everyone but the uploader and moderators
     pub fn from_note_with_user(
         note: NoteWithUser,
         file_url: String,
         preview_image_url: String,
+        viewer: Option<&User>,
     ) -> Self {
+        let show_uploader = !note.note_is_anonymous
+            || viewer.is_some_and(|viewer| viewer.id == note.user_id || viewer.is_moderator());
+        let uploader_user = if show_uploader {
+            PublicUser {
+                id: Some(note.user_id),
+                display_name: note.user_display_name,
+                picture: note.user_picture,
+            }
+        } else {
+            PublicUser::anonymous()
+        };
+
         Self {
             id: note.note_id,
             course_name: note.note_course_name,
@@ -65,22 +118,478 @@ impl ResponseNote {
             tags: note.note_tags,
             is_public: note.note_is_public,
             has_preview_image: note.note_has_preview_image,
+            is_hidden: note.note_is_hidden,
+            is_anonymous: note.note_is_anonymous,
             preview_image_url,
             file_url,
             year: note.note_year,
             semester: note.note_semester,
             upvotes: note.note_upvote_count as usize,
             downvotes: note.note_downvote_count as usize,
+            comment_count: note.note_comment_count as usize,
+            score: note.note_wilson_score,
             downloads: note.note_downloads as usize,
             user_vote: note.note_user_upvote,
-            uploader_user: ResponseUser {
-                id: note.user_id,
-                google_id: note.user_google_id,
-                email: note.user_email,
-                full_name: note.user_full_name,
-                created_at: note.user_created_at,
-            },
+            uploader_user,
             created_at: note.note_created_at,
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseComment {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub author: PublicUser,
+    /// `None` once the comment has been deleted
+    pub body: Option<String>,
+    pub is_deleted: bool,
+    pub is_edited: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub replies: Vec<ResponseComment>,
+}
+
+impl ResponseComment {
+    pub fn from_comment_with_user(comment: CommentWithUser) -> Self {
+        let is_deleted = comment.comment_deleted_at.is_some();
+        Self {
+            id: comment.comment_id,
+            note_id: comment.comment_note_id,
+            parent_id: comment.comment_parent_id,
+            author: PublicUser {
+                id: Some(comment.user_id),
+                display_name: comment.user_display_name,
+                picture: comment.user_picture,
+            },
+            body: if is_deleted { None } else { Some(comment.comment_body) },
+            is_deleted,
+            is_edited: comment.comment_updated_at.is_some(),
+            created_at: comment.comment_created_at,
+            updated_at: comment.comment_updated_at,
+            replies: Vec::new(),
+        }
+    }
+
+    pub fn from_comment(comment: Comment, author: &User) -> Self {
+        Self::from_comment_with_user(CommentWithUser {
+            comment_id: comment.id,
+            comment_note_id: comment.note_id,
+            comment_parent_id: comment.parent_id,
+            comment_body: comment.body,
+            comment_created_at: comment.created_at,
+            comment_updated_at: comment.updated_at,
+            comment_deleted_at: comment.deleted_at,
+            user_id: author.id,
+            user_display_name: author.public_name.clone(),
+            user_picture: author.public_picture.clone(),
+        })
+    }
+
+    /// Nests a flat list of comments (ordered by creation time) into threads.
+    pub fn build_threads(comments: Vec<CommentWithUser>) -> Vec<Self> {
+        let mut children: HashMap<Option<Uuid>, Vec<Self>> = HashMap::new();
+        for comment in comments {
+            let comment = Self::from_comment_with_user(comment);
+            children.entry(comment.parent_id).or_default().push(comment);
+        }
+
+        fn attach(comment: &mut ResponseComment, children: &mut HashMap<Option<Uuid>, Vec<ResponseComment>>) {
+            comment.replies = children.remove(&Some(comment.id)).unwrap_or_default();
+            for reply in comment.replies.iter_mut() {
+                attach(reply, children);
+            }
+        }
+
+        // Top-level comments are shown newest first, replies oldest first
+        let mut roots = children.remove(&None).unwrap_or_default();
+        roots.reverse();
+        for root in roots.iter_mut() {
+            attach(root, &mut children);
+        }
+        roots
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseCommentPage {
+    pub comments: Vec<ResponseComment>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseReport {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub reporter_id: Uuid,
+    pub reporter_name: Option<String>,
+    pub reason: String,
+    pub details: Option<String>,
+    pub status: String,
+    pub assigned_to: Option<Uuid>,
+    pub resolved_by: Option<Uuid>,
+    pub resolution_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl From<NoteReport> for ResponseReport {
+    fn from(report: NoteReport) -> Self {
+        Self {
+            id: report.id,
+            note_id: report.note_id,
+            reporter_id: report.reporter_id,
+            reporter_name: None,
+            reason: report.reason,
+            details: report.details,
+            status: report.status,
+            assigned_to: report.assigned_to,
+            resolved_by: report.resolved_by,
+            resolution_note: report.resolution_note,
+            created_at: report.created_at,
+            resolved_at: report.resolved_at,
+        }
+    }
+}
+
+/// All reports filed against a single note, as shown in the moderation queue
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseModerationItem {
+    pub note_id: Uuid,
+    pub course_name: String,
+    pub course_code: String,
+    pub uploader_user_id: Uuid,
+    pub is_hidden: bool,
+    pub report_count: usize,
+    pub reason_counts: HashMap<String, usize>,
+    pub assigned_to: Option<Uuid>,
+    pub first_reported_at: DateTime<Utc>,
+    pub reports: Vec<ResponseReport>,
+}
+
+impl ResponseModerationItem {
+    /// Groups reports (ordered oldest first) by the note they were filed against.
+    pub fn group_reports(reports: Vec<ReportWithNote>) -> Vec<Self> {
+        let mut items: Vec<Self> = Vec::new();
+        let mut index: HashMap<Uuid, usize> = HashMap::new();
+
+        for report in reports {
+            let position = *index.entry(report.note_id).or_insert_with(|| {
+                items.push(Self {
+                    note_id: report.note_id,
+                    course_name: report.note_course_name.clone(),
+                    course_code: report.note_course_code.clone(),
+                    uploader_user_id: report.note_uploader_user_id,
+                    is_hidden: report.note_is_hidden,
+                    report_count: 0,
+                    reason_counts: HashMap::new(),
+                    assigned_to: None,
+                    first_reported_at: report.report_created_at,
+                    reports: Vec::new(),
+                });
+                items.len() - 1
+            });
+
+            let item = &mut items[position];
+            item.report_count += 1;
+            *item.reason_counts.entry(report.report_reason.clone()).or_default() += 1;
+            if report.report_assigned_to.is_some() {
+                item.assigned_to = report.report_assigned_to;
+            }
+            item.reports.push(ResponseReport {
+                id: report.report_id,
+                note_id: report.note_id,
+                reporter_id: report.reporter_id,
+                reporter_name: Some(report.reporter_full_name),
+                reason: report.report_reason,
+                details: report.report_details,
+                status: report.report_status,
+                assigned_to: report.report_assigned_to,
+                resolved_by: report.report_resolved_by,
+                resolution_note: report.report_resolution_note,
+                created_at: report.report_created_at,
+                resolved_at: report.report_resolved_at,
+            });
+        }
+
+        items
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseBadge {
+    pub badge: String,
+    pub name: String,
+    pub description: String,
+    /// Set for badges that can be earned more than once, e.g. the semester
+    pub context: Option<String>,
+    pub awarded_at: DateTime<Utc>,
+}
+
+impl ResponseBadge {
+    /// Groups badges by user, dropping badges that are no longer defined
+    pub fn group_by_user(badges: Vec<UserBadge>) -> HashMap<Uuid, Vec<Self>> {
+        let mut grouped: HashMap<Uuid, Vec<Self>> = HashMap::new();
+        for badge in badges {
+            let Some(definition) = find_badge(&badge.badge) else {
+                continue;
+            };
+            grouped.entry(badge.user_id).or_default().push(Self {
+                badge: badge.badge,
+                name: definition.name.to_string(),
+                description: definition.description.to_string(),
+                context: Some(badge.context).filter(|context| !context.is_empty()),
+                awarded_at: badge.awarded_at,
+            });
+        }
+        grouped
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseLeaderboardEntry {
+    #[serde(flatten)]
+    pub entry: LeaderboardEntry,
+    pub badges: Vec<ResponseBadge>,
+}
+
+/// The signed in user, as returned by `/auth/me`
+#[derive(Serialize, Debug)]
+pub struct ResponseCurrentUser {
+    #[serde(flatten)]
+    pub user: User,
+    pub badges: Vec<ResponseBadge>,
+}
+
+/// A user's contribution stats, all time
+#[derive(Serialize, Debug)]
+pub struct ResponseUserStats {
+    pub reputation: f64,
+    pub rank: i64,
+    pub total_notes: i64,
+    pub total_upvotes: i64,
+    pub total_downvotes: i64,
+    pub total_downloads: i64,
+    pub courses_covered: i64,
+    pub total_comments: i64,
+}
+
+/// A user's public profile, as returned by `/users/{user_id}`
+#[derive(Serialize, Debug)]
+pub struct ResponseUserProfile {
+    #[serde(flatten)]
+    pub user: PublicUser,
+    pub bio: Option<String>,
+    pub department: Option<String>,
+    pub graduation_year: Option<i32>,
+    pub member_since: DateTime<Utc>,
+    pub stats: ResponseUserStats,
+    pub badges: Vec<ResponseBadge>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseNotification {
+    pub id: Uuid,
+    pub kind: String,
+    pub note_id: Uuid,
+    pub course_code: String,
+    pub count: i32,
+    pub message: String,
+    pub is_read: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Notification> for ResponseNotification {
+    fn from(notification: Notification) -> Self {
+        let plural = |singular: &str, plural: &str| {
+            format!(
+                "{} new {}",
+                notification.count,
+                if notification.count == 1 { singular } else { plural }
+            )
+        };
+        let course = &notification.course_code;
+        let message = match notification.kind.as_str() {
+            kinds::NOTE_UPVOTED => format!("{} on your {} notes", plural("upvote", "upvotes"), course),
+            kinds::NOTE_COMMENTED => {
+                format!("{} on your {} notes", plural("comment", "comments"), course)
+            }
+            kinds::COMMENT_REPLIED => {
+                format!("{} to your comment on {} notes", plural("reply", "replies"), course)
+            }
+            kinds::NOTE_HIDDEN => format!("Your {} notes were hidden after being reported", course),
+            kinds::REPORT_UPHELD => {
+                format!("Your report on {} notes was upheld and the notes were hidden", course)
+            }
+            kinds::REPORT_DISMISSED => {
+                format!("Your report on {} notes was reviewed and dismissed", course)
+            }
+            _ => format!("Activity on {} notes", course),
+        };
+
+        Self {
+            id: notification.id,
+            kind: notification.kind,
+            note_id: notification.note_id,
+            course_code: notification.course_code,
+            count: notification.count,
+            message,
+            is_read: notification.read_at.is_some(),
+            created_at: notification.created_at,
+            updated_at: notification.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseNotificationPage {
+    pub notifications: Vec<ResponseNotification>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub unread: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseFollow {
+    pub kind: String,
+    pub target: String,
+    /// The followed user, when following a user
+    pub user: Option<PublicUser>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<FollowWithUser> for ResponseFollow {
+    fn from(follow: FollowWithUser) -> Self {
+        let user = match (follow.followed_user_id, follow.followed_user_name, follow.followed_user_picture) {
+            (Some(id), Some(display_name), Some(picture)) => Some(PublicUser {
+                id: Some(id),
+                display_name,
+                picture,
+            }),
+            _ => None,
+        };
+        Self {
+            kind: follow.kind,
+            target: follow.target,
+            user,
+            created_at: follow.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseFeedPage {
+    pub notes: Vec<ResponseNote>,
+    /// Pass as `cursor` to get the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseNewWebhook {
+    pub webhook: Webhook,
+    /// Payloads are signed with this, it is not shown again
+    pub secret: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseWebhookDelivery {
+    #[serde(flatten)]
+    pub delivery: WebhookDelivery,
+    pub attempt_log: Vec<WebhookAttempt>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseWebhookDeliveryPage {
+    pub deliveries: Vec<ResponseWebhookDelivery>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    /// A comment posted `minutes` after the first one
+    fn comment(id: Uuid, parent_id: Option<Uuid>, minutes: i64) -> CommentWithUser {
+        CommentWithUser {
+            comment_id: id,
+            comment_note_id: Uuid::nil(),
+            comment_parent_id: parent_id,
+            comment_body: format!("comment {}", minutes),
+            comment_created_at: DateTime::<Utc>::UNIX_EPOCH + Duration::minutes(minutes),
+            comment_updated_at: None,
+            comment_deleted_at: None,
+            user_id: Uuid::new_v4(),
+            user_display_name: "Student".to_string(),
+            user_picture: String::new(),
+        }
+    }
+
+    fn ids(comments: &[ResponseComment]) -> Vec<Uuid> {
+        comments.iter().map(|comment| comment.id).collect()
+    }
+
+    #[test]
+    fn nests_replies_under_their_parents() {
+        let (first, second, reply, nested) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let threads = ResponseComment::build_threads(vec![
+            comment(first, None, 0),
+            comment(second, None, 1),
+            comment(reply, Some(first), 2),
+            comment(nested, Some(reply), 3),
+        ]);
+
+        assert_eq!(ids(&threads), [second, first]);
+        assert!(threads[0].replies.is_empty());
+        assert_eq!(ids(&threads[1].replies), [reply]);
+        assert_eq!(ids(&threads[1].replies[0].replies), [nested]);
+    }
+
+    #[test]
+    fn top_level_comments_are_newest_first_and_replies_oldest_first() {
+        let root = Uuid::new_v4();
+        let (older, newer) = (Uuid::new_v4(), Uuid::new_v4());
+        let (early, late) = (Uuid::new_v4(), Uuid::new_v4());
+        let threads = ResponseComment::build_threads(vec![
+            comment(older, None, 0),
+            comment(root, None, 1),
+            comment(early, Some(root), 2),
+            comment(newer, None, 3),
+            comment(late, Some(root), 4),
+        ]);
+
+        assert_eq!(ids(&threads), [newer, root, older]);
+        assert_eq!(ids(&threads[1].replies), [early, late]);
+    }
+
+    #[test]
+    fn deleted_comments_keep_their_replies() {
+        let (parent, reply) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut deleted = comment(parent, None, 0);
+        deleted.comment_deleted_at = Some(Utc::now());
+        let threads = ResponseComment::build_threads(vec![deleted, comment(reply, Some(parent), 1)]);
+
+        assert!(threads[0].is_deleted);
+        assert_eq!(threads[0].body, None);
+        assert_eq!(ids(&threads[0].replies), [reply]);
+    }
+
+    #[test]
+    fn replies_to_missing_comments_are_dropped() {
+        let root = Uuid::new_v4();
+        let threads = ResponseComment::build_threads(vec![
+            comment(root, None, 0),
+            comment(Uuid::new_v4(), Some(Uuid::new_v4()), 1),
+        ]);
+
+        assert_eq!(ids(&threads), [root]);
+        assert!(threads[0].replies.is_empty());
+    }
+}
@@ -4,9 +4,9 @@ use super::handlers;
 use crate::api::middleware;
 use crate::db::DBPoolWrapper;
 use crate::env::EnvVars;
-use axum::middleware::from_fn_with_state;
+use axum::middleware::{from_fn, from_fn_with_state};
 use axum::{
-    routing::{delete, get, post, put},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use tower_http::services::ServeDir;
@@ -29,7 +29,70 @@ pub fn create_router(db_wrapper: DBPoolWrapper, env_vars: EnvVars) -> Router {
         .route("/notes/{note_id}", put(handlers::notes::update_note_handler))
         .route("/notes/{note_id}", delete(handlers::notes::delete_note_handler))
         .route("/notes/{note_id}/vote", post(handlers::votes::add_vote))
+        .route("/notes/{note_id}/comments", post(handlers::comments::add_comment))
+        .route("/notes/{note_id}/comments/{comment_id}", put(handlers::comments::update_comment_handler))
+        .route("/notes/{note_id}/comments/{comment_id}", delete(handlers::comments::delete_comment_handler))
+        .route("/notes/{note_id}/report", post(handlers::reports::report_note))
+        .route("/notes/{note_id}/share", post(handlers::files::share_note_file))
+        .route("/notes/bulk-download", post(handlers::bulk::bulk_download))
+        .route("/courses/{course_code}/download.zip", get(handlers::bulk::download_course_zip))
+        .route("/notes/course-pack", post(handlers::course_packs::build_course_pack))
+        .route("/courses/{course_code}/pack.pdf", get(handlers::course_packs::download_course_pack))
         .route("/auth/me", get(handlers::auth::get_current_user))
+        .route("/users/me", patch(handlers::users::update_profile_handler))
+        .route("/users/me", delete(handlers::account::delete_account))
+        .route("/users/me/export", get(handlers::account::export_account))
+        .route("/users/me/email-preferences", get(handlers::email::get_preferences))
+        .route("/users/me/email-preferences", patch(handlers::email::update_preferences))
+        .route("/follows", get(handlers::follows::list_follows))
+        .route("/follows/{kind}/{target}", put(handlers::follows::follow))
+        .route("/follows/{kind}/{target}", delete(handlers::follows::unfollow))
+        .route("/feed", get(handlers::follows::get_feed_handler))
+        .route("/notifications", get(handlers::notifications::list_notifications))
+        .route("/notifications/unread-count", get(handlers::notifications::unread_count))
+        .route("/notifications/read", post(handlers::notifications::mark_read))
+        .route_layer(from_fn_with_state(
+            state.clone(),
+            middleware::verify_token_middleware,
+        ));
+
+    // Moderator routes (require authentication as a moderator)
+    let moderator_router = Router::new()
+        .route("/moderation/queue", get(handlers::reports::get_moderation_queue))
+        .route("/moderation/queue/{note_id}/assign", post(handlers::reports::assign_queue_item))
+        .route("/moderation/queue/{note_id}/resolve", post(handlers::reports::resolve_queue_item))
+        .route("/moderation/queue/{note_id}/dismiss", post(handlers::reports::dismiss_queue_item))
+        .route("/moderation/vote-rings", get(handlers::vote_rings::list_vote_rings))
+        .route("/moderation/vote-rings/{ring_id}/confirm", post(handlers::vote_rings::confirm_vote_ring))
+        .route("/moderation/vote-rings/{ring_id}/clear", post(handlers::vote_rings::clear_vote_ring))
+        .route_layer(from_fn(middleware::require_moderator_middleware))
+        .route_layer(from_fn_with_state(
+            state.clone(),
+            middleware::verify_token_middleware,
+        ));
+
+    // Admin routes (require authentication as an admin)
+    let admin_router = Router::new()
+        .route("/admin/users/{user_id}/role", put(handlers::admin::set_role_handler))
+        .route("/admin/users/{user_id}/suspend", post(handlers::admin::suspend_user_handler))
+        .route("/admin/users/{user_id}/ban", post(handlers::admin::ban_user_handler))
+        .route("/admin/users/{user_id}/reinstate", post(handlers::admin::reinstate_user_handler))
+        .route("/admin/audit", get(handlers::audit::list_audit_events))
+        .route("/admin/reputation/preview", post(handlers::admin::preview_reputation_handler))
+        .route(
+            "/admin/webhooks",
+            get(handlers::webhooks::list_webhooks).post(handlers::webhooks::create_webhook_handler),
+        )
+        .route(
+            "/admin/webhooks/{webhook_id}",
+            patch(handlers::webhooks::update_webhook_handler).delete(handlers::webhooks::delete_webhook_handler),
+        )
+        .route("/admin/webhooks/{webhook_id}/deliveries", get(handlers::webhooks::list_deliveries))
+        .route(
+            "/admin/webhooks/{webhook_id}/deliveries/{delivery_id}/retry",
+            post(handlers::webhooks::retry_delivery_handler),
+        )
+        .route_layer(from_fn(middleware::require_admin_middleware))
         .route_layer(from_fn_with_state(
             state.clone(),
             middleware::verify_token_middleware,
@@ -40,7 +103,11 @@ pub fn create_router(db_wrapper: DBPoolWrapper, env_vars: EnvVars) -> Router {
         .route("/notes", get(handlers::notes::list_notes))
         .route("/notes/search", get(handlers::notes::search_notes))
         .route("/notes/{note_id}", get(handlers::notes::note_by_id))
+        .route("/notes/{note_id}/download", get(handlers::notes::download_note))
+        .route("/notes/{note_id}/file", get(handlers::files::serve_note_file))
+        .route("/notes/{note_id}/comments", get(handlers::comments::list_comments))
         .route("/users/{user_id}/notes", get(handlers::notes::get_user_notes))
+        .route("/users/{user_id}", get(handlers::users::get_user_profile_handler))
         .route_layer(from_fn_with_state(
             state.clone(),
             middleware::optional_auth_middleware,
@@ -50,22 +117,30 @@ pub fn create_router(db_wrapper: DBPoolWrapper, env_vars: EnvVars) -> Router {
     let public_router = Router::new()
         .route("/", get(handlers::misc::index))
         .route("/auth/google", post(handlers::auth::google_auth_callback))
-        .route("/notes/{note_id}/download", get(handlers::notes::download_note))
+        .route("/email/unsubscribe", get(handlers::email::unsubscribe_page).post(handlers::email::unsubscribe))
         .route("/users/leaderboard", get(handlers::users::get_leaderboard_handler))
         .route("/users/{user_id}/leaderboard-position", get(handlers::users::get_user_position_handler));
 
+    // Atom feeds, served outside `/api` so that feed readers get a stable URL. The last segment
+    // is e.g. `CS10001.atom`.
+    let feeds_router = Router::new()
+        .route("/courses/{file}", get(handlers::feeds::course_feed))
+        .route("/tags/{file}", get(handlers::feeds::tag_feed))
+        .route("/users/{file}", get(handlers::feeds::user_feed));
+
     // Merge all routers (CORS is handled by CorsLayer in main.rs)
     let api_router = Router::new()
         .merge(public_router)
         .merge(protected_router)
+        .merge(moderator_router)
+        .merge(admin_router)
         .merge(optional_user_router);
 
     // ... rest of your code remains the same
-    let notes_path = state.env_vars.paths.get_notes_dir().to_path_buf();
     let images_path = state.env_vars.paths.get_previews_dir().to_path_buf();
     Router::new()
         .nest("/api", api_router)
-        .nest_service("/notes/uploaded", ServeDir::new(notes_path))
+        .nest("/feeds", feeds_router)
         .nest_service("/previews/uploaded", ServeDir::new(images_path))
         .with_state(state)
 }
@@ -0,0 +1,71 @@
+//! Achievement badges.
+//!
+//! Every badge is declared below along with the rule that earns it. The badge job evaluates each
+//! rule periodically and awards the badge to every user who meets it. Badges are never taken
+//! back. Hidden notes, excluded votes and banned users do not count towards any badge.
+
+/// What a user has to achieve to earn a badge
+#[derive(Debug, Clone, Copy)]
+pub enum BadgeRule {
+    /// Uploaded at least this many notes
+    NotesUploaded(i64),
+    /// Has a single note with at least this many downloads
+    NoteDownloads(i64),
+    /// Uploaded notes for at least this many different courses
+    CoursesCovered(i64),
+    /// Received at least this many upvotes across all notes
+    UpvotesReceived(i64),
+    /// Ranked in the top N of a semester's leaderboard, awarded once the semester is over.
+    /// Earned once per semester.
+    SemesterTop(i64),
+}
+
+pub struct BadgeDefinition {
+    pub slug: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub rule: BadgeRule,
+}
+
+pub const BADGES: &[BadgeDefinition] = &[
+    BadgeDefinition {
+        slug: "first_upload",
+        name: "First Upload",
+        description: "Uploaded their first note",
+        rule: BadgeRule::NotesUploaded(1),
+    },
+    BadgeDefinition {
+        slug: "prolific_uploader",
+        name: "Prolific Uploader",
+        description: "Uploaded 25 notes",
+        rule: BadgeRule::NotesUploaded(25),
+    },
+    BadgeDefinition {
+        slug: "popular_note",
+        name: "Popular Note",
+        description: "A note was downloaded 100 times",
+        rule: BadgeRule::NoteDownloads(100),
+    },
+    BadgeDefinition {
+        slug: "course_explorer",
+        name: "Course Explorer",
+        description: "Uploaded notes for 5 different courses",
+        rule: BadgeRule::CoursesCovered(5),
+    },
+    BadgeDefinition {
+        slug: "well_liked",
+        name: "Well Liked",
+        description: "Received 50 upvotes",
+        rule: BadgeRule::UpvotesReceived(50),
+    },
+    BadgeDefinition {
+        slug: "semester_top_10",
+        name: "Top 10",
+        description: "Ranked in the top 10 of a semester's leaderboard",
+        rule: BadgeRule::SemesterTop(10),
+    },
+];
+
+pub fn find_badge(slug: &str) -> Option<&'static BadgeDefinition> {
+    BADGES.iter().find(|badge| badge.slug == slug)
+}
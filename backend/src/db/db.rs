@@ -1,10 +1,13 @@
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-use std::env;
+use std::sync::Arc;
 use crate::env::EnvVars;
+use crate::scoring::ReputationScorer;
 
 #[derive(Clone)]
 pub struct DBPoolWrapper {
     pool: Pool<Postgres>,
+    /// Scores reputation whenever leaderboard stats are refreshed
+    scorer: Arc<dyn ReputationScorer>,
 }
 
 impl DBPoolWrapper {
@@ -28,10 +31,15 @@ impl DBPoolWrapper {
             .expect("Error running migrations");
         Self {
             pool: connection_pool,
+            scorer: Arc::from(env_vars.reputation.scorer()),
         }
     }
 
     pub fn pool(&self) -> &Pool<Postgres> {
         &self.pool
     }
+
+    pub fn scorer(&self) -> &dyn ReputationScorer {
+        self.scorer.as_ref()
+    }
 }
@@ -0,0 +1,117 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::models::AuditEvent;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// An entry to be appended to the audit log
+pub struct NewAuditEvent {
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<Uuid>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub request_id: Option<String>,
+}
+
+impl NewAuditEvent {
+    /// `actor_id` is `None` for actions taken by the system itself
+    pub fn new(actor_id: Option<Uuid>, action: &str, target_type: &str, target_id: Uuid) -> Self {
+        Self {
+            actor_id,
+            action: action.to_string(),
+            target_type: target_type.to_string(),
+            target_id: Some(target_id),
+            before: None,
+            after: None,
+            request_id: None,
+        }
+    }
+
+    pub fn before(mut self, before: &impl Serialize) -> Self {
+        self.before = serde_json::to_value(before).ok();
+        self
+    }
+
+    pub fn after(mut self, after: &impl Serialize) -> Self {
+        self.after = serde_json::to_value(after).ok();
+        self
+    }
+
+    pub fn request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+}
+
+/// Filters for the audit log. Every filter is optional.
+pub struct AuditFilter {
+    pub actor_id: Option<Uuid>,
+    /// Matches the action exactly, or every action under it (`note` matches `note.update`)
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<Uuid>,
+    pub request_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+pub async fn insert_audit_event(
+    db_wrapper: &DBPoolWrapper,
+    event: NewAuditEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (actor_id, action, target_type, target_id, before, after, request_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        event.actor_id,
+        event.action,
+        event.target_type,
+        event.target_id,
+        event.before,
+        event.after,
+        event.request_id,
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(())
+}
+
+/// Fetches audit log entries matching the filter, newest first
+pub async fn get_audit_events(
+    db_wrapper: &DBPoolWrapper,
+    filter: AuditFilter,
+) -> Result<Vec<AuditEvent>, sqlx::Error> {
+    let events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT *
+        FROM audit_events
+        WHERE ($1::uuid IS NULL OR actor_id = $1)
+          AND ($2::text IS NULL OR action = $2 OR action LIKE $2 || '.%')
+          AND ($3::text IS NULL OR target_type = $3)
+          AND ($4::uuid IS NULL OR target_id = $4)
+          AND ($5::text IS NULL OR request_id = $5)
+          AND ($6::timestamptz IS NULL OR created_at >= $6)
+          AND ($7::timestamptz IS NULL OR created_at < $7)
+        ORDER BY created_at DESC
+        LIMIT $8
+        "#,
+        filter.actor_id,
+        filter.action,
+        filter.target_type,
+        filter.target_id,
+        filter.request_id,
+        filter.since,
+        filter.until,
+        filter.limit,
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(events)
+}
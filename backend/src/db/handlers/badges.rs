@@ -0,0 +1,219 @@
+use crate::badges::BadgeRule;
+use crate::db::db::DBPoolWrapper;
+use crate::db::handlers::leaderboard::Semester;
+use crate::db::handlers::users::GHOST_USER_ID;
+use crate::db::models::UserBadge;
+use crate::scoring::ReputationStats;
+use uuid::Uuid;
+
+/// Users who currently meet a badge rule. Semester rankings are those of `last_semester`, which
+/// should have ended. Hidden notes, excluded votes and banned users are left out.
+pub async fn find_badge_candidates(
+    db_wrapper: &DBPoolWrapper,
+    rule: BadgeRule,
+    last_semester: Semester,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let pool = db_wrapper.pool();
+    let user_ids = match rule {
+        BadgeRule::NotesUploaded(count) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT n.uploader_user_id
+                FROM notes n
+                JOIN users u ON u.id = n.uploader_user_id
+                WHERE NOT n.is_hidden AND NOT u.notes_hidden AND u.banned_at IS NULL
+                GROUP BY n.uploader_user_id
+                HAVING COUNT(*) >= $1
+                "#,
+                count
+            )
+                .fetch_all(pool)
+                .await?
+        }
+        BadgeRule::NoteDownloads(count) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT DISTINCT n.uploader_user_id
+                FROM notes n
+                JOIN users u ON u.id = n.uploader_user_id
+                WHERE n.downloads >= $1::BIGINT
+                  AND NOT n.is_hidden AND NOT u.notes_hidden AND u.banned_at IS NULL
+                "#,
+                count
+            )
+                .fetch_all(pool)
+                .await?
+        }
+        BadgeRule::CoursesCovered(count) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT n.uploader_user_id
+                FROM notes n
+                JOIN users u ON u.id = n.uploader_user_id
+                WHERE NOT n.is_hidden AND NOT u.notes_hidden AND u.banned_at IS NULL
+                GROUP BY n.uploader_user_id
+                HAVING COUNT(DISTINCT UPPER(n.course_code)) >= $1
+                "#,
+                count
+            )
+                .fetch_all(pool)
+                .await?
+        }
+        BadgeRule::UpvotesReceived(count) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT n.uploader_user_id
+                FROM votes v
+                JOIN notes n ON n.id = v.note_id
+                JOIN users u ON u.id = n.uploader_user_id
+                WHERE v.is_upvote AND NOT v.excluded
+                  AND NOT n.is_hidden AND NOT u.notes_hidden AND u.banned_at IS NULL
+                GROUP BY n.uploader_user_id
+                HAVING COUNT(*) >= $1
+                "#,
+                count
+            )
+                .fetch_all(pool)
+                .await?
+        }
+        BadgeRule::SemesterTop(top) => semester_top(db_wrapper, last_semester, top).await?,
+    };
+
+    Ok(user_ids)
+}
+
+/// Users ranked in the `top` of a semester, scored with the configured reputation formula and
+/// counted the same way as the leaderboard's semester window
+async fn semester_top(
+    db_wrapper: &DBPoolWrapper,
+    semester: Semester,
+    top: i64,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let Some((since, until)) = semester.bounds() else {
+        return Ok(Vec::new());
+    };
+
+    let stats = sqlx::query!(
+        r#"
+        SELECT
+            n.uploader_user_id as user_id,
+            COUNT(*) FILTER (
+                WHERE n.created_at >= $1 OR votes.upvotes + votes.downvotes + downloads.count > 0
+            ) as "total_notes!",
+            SUM(votes.upvotes)::BIGINT as "total_upvotes!",
+            SUM(votes.downvotes)::BIGINT as "total_downvotes!",
+            SUM(downloads.count)::BIGINT as "total_downloads!"
+        FROM notes n
+        JOIN users u ON u.id = n.uploader_user_id
+        CROSS JOIN LATERAL (
+            SELECT
+                COUNT(*) FILTER (WHERE v.is_upvote) as upvotes,
+                COUNT(*) FILTER (WHERE NOT v.is_upvote) as downvotes
+            FROM votes v
+            WHERE v.note_id = n.id AND NOT v.excluded AND v.created_at >= $1 AND v.created_at < $2
+        ) votes
+        CROSS JOIN LATERAL (
+            SELECT COUNT(*) as count
+            FROM download_events d
+            WHERE d.note_id = n.id AND d.created_at >= $1 AND d.created_at < $2
+        ) downloads
+        WHERE n.created_at < $2
+          AND NOT n.is_hidden AND NOT u.notes_hidden AND u.banned_at IS NULL AND u.id <> $3
+        GROUP BY n.uploader_user_id
+        "#,
+        since,
+        until,
+        GHOST_USER_ID
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    let scorer = db_wrapper.scorer();
+    let mut ranked: Vec<(Uuid, f64, i64)> = stats
+        .into_iter()
+        .map(|row| {
+            let reputation = scorer.score(&ReputationStats {
+                notes: row.total_notes,
+                upvotes: row.total_upvotes,
+                downvotes: row.total_downvotes,
+                downloads: row.total_downloads,
+            });
+            (row.user_id, reputation, row.total_notes)
+        })
+        .filter(|(_, reputation, _)| *reputation > 0.0)
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.2.cmp(&a.2)));
+
+    // Ranked like the leaderboard, with ties sharing a rank
+    let mut user_ids = Vec::new();
+    let mut rank = 0;
+    for (index, (user_id, reputation, notes)) in ranked.iter().enumerate() {
+        if index == 0 || (*reputation, *notes) != (ranked[index - 1].1, ranked[index - 1].2) {
+            rank = index as i64 + 1;
+        }
+        if rank > top {
+            break;
+        }
+        user_ids.push(*user_id);
+    }
+
+    Ok(user_ids)
+}
+
+/// Whether a badge has been awarded to anyone in a context, e.g. for a semester
+pub async fn is_badge_awarded(
+    db_wrapper: &DBPoolWrapper,
+    badge: &str,
+    context: &str,
+) -> Result<bool, sqlx::Error> {
+    let awarded = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM user_badges WHERE badge = $1 AND context = $2) as "awarded!""#,
+        badge,
+        context
+    )
+        .fetch_one(db_wrapper.pool())
+        .await?;
+
+    Ok(awarded)
+}
+
+/// Awards a badge to the given users, skipping those who already have it in this context.
+/// Returns the ids of the users who were newly awarded the badge.
+pub async fn award_badge(
+    db_wrapper: &DBPoolWrapper,
+    badge: &str,
+    context: &str,
+    user_ids: &[Uuid],
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let awarded = sqlx::query_scalar!(
+        r#"
+        INSERT INTO user_badges (user_id, badge, context)
+        SELECT user_id, $2, $3 FROM UNNEST($1::UUID[]) as user_id
+        ON CONFLICT ON CONSTRAINT user_badges_unique DO NOTHING
+        RETURNING user_id
+        "#,
+        user_ids,
+        badge,
+        context
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(awarded)
+}
+
+/// Badges of the given users, oldest first
+pub async fn get_badges_for_users(
+    db_wrapper: &DBPoolWrapper,
+    user_ids: &[Uuid],
+) -> Result<Vec<UserBadge>, sqlx::Error> {
+    let badges = sqlx::query_as!(
+        UserBadge,
+        "SELECT * FROM user_badges WHERE user_id = ANY ($1) ORDER BY awarded_at, badge",
+        user_ids
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(badges)
+}
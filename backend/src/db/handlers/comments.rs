@@ -0,0 +1,172 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::models::{Comment, CommentWithUser};
+use uuid::Uuid;
+
+/// Inserts a new comment (or a reply, if `parent_id` is set) on a note.
+pub async fn create_comment(
+    db_wrapper: &DBPoolWrapper,
+    note_id: Uuid,
+    user_id: Uuid,
+    parent_id: Option<Uuid>,
+    body: &str,
+) -> Result<Comment, sqlx::Error> {
+    let comment = sqlx::query_as!(
+        Comment,
+        r#"
+        INSERT INTO comments (note_id, user_id, parent_id, body)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+        note_id,
+        user_id,
+        parent_id,
+        body,
+    )
+        .fetch_one(db_wrapper.pool())
+        .await?;
+
+    Ok(comment)
+}
+
+pub async fn get_comment_by_id(
+    db_wrapper: &DBPoolWrapper,
+    comment_id: Uuid,
+) -> Result<Option<Comment>, sqlx::Error> {
+    let comment = sqlx::query_as!(
+        Comment,
+        "SELECT * FROM comments WHERE id = $1",
+        comment_id
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(comment)
+}
+
+/// Every comment a user has written, including deleted ones, oldest first
+pub async fn get_comments_by_user(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<Vec<Comment>, sqlx::Error> {
+    let comments = sqlx::query_as!(
+        Comment,
+        "SELECT * FROM comments WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(comments)
+}
+
+/// Fetches a page of top-level comments on a note along with every reply below them.
+///
+/// Top-level comments are ordered newest first, the returned rows are in creation order so
+/// that replies can be attached to their parents in a single pass.
+pub async fn get_comment_threads(
+    db_wrapper: &DBPoolWrapper,
+    note_id: Uuid,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<CommentWithUser>, sqlx::Error> {
+    let comments = sqlx::query_as!(
+        CommentWithUser,
+        r#"
+        WITH RECURSIVE roots AS (
+            SELECT id
+            FROM comments
+            WHERE note_id = $1 AND parent_id IS NULL
+            ORDER BY created_at DESC
+            LIMIT $2 OFFSET $3
+        ),
+        thread AS (
+            SELECT c.*
+            FROM comments c
+            JOIN roots r ON c.id = r.id
+            UNION ALL
+            SELECT c.*
+            FROM comments c
+            JOIN thread t ON c.parent_id = t.id
+        )
+        SELECT
+            c.id as "comment_id!",
+            c.note_id as "comment_note_id!",
+            c.parent_id as "comment_parent_id?",
+            c.body as "comment_body!",
+            c.created_at as "comment_created_at!",
+            c.updated_at as "comment_updated_at?",
+            c.deleted_at as "comment_deleted_at?",
+            u.id as "user_id!",
+            u.public_name as "user_display_name!",
+            u.public_picture as "user_picture!"
+        FROM
+            thread c
+        JOIN
+            users u ON c.user_id = u.id
+        ORDER BY
+            c.created_at ASC
+        "#,
+        note_id,
+        limit,
+        offset
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(comments)
+}
+
+/// Counts the top-level comments on a note, used to paginate threads.
+pub async fn count_root_comments(
+    db_wrapper: &DBPoolWrapper,
+    note_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM comments WHERE note_id = $1 AND parent_id IS NULL"#,
+        note_id
+    )
+        .fetch_one(db_wrapper.pool())
+        .await?;
+
+    Ok(count)
+}
+
+pub async fn update_comment(
+    db_wrapper: &DBPoolWrapper,
+    comment_id: Uuid,
+    body: &str,
+) -> Result<Comment, sqlx::Error> {
+    let comment = sqlx::query_as!(
+        Comment,
+        r#"
+        UPDATE comments
+        SET body = $2,
+            updated_at = NOW()
+        WHERE id = $1 AND deleted_at IS NULL
+        RETURNING *
+        "#,
+        comment_id,
+        body,
+    )
+        .fetch_one(db_wrapper.pool())
+        .await?;
+
+    Ok(comment)
+}
+
+/// Soft deletes a comment. The row is kept so that its replies stay attached to the thread.
+pub async fn delete_comment(
+    db_wrapper: &DBPoolWrapper,
+    comment_id: Uuid,
+    deleted_by: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE comments SET deleted_at = NOW(), deleted_by = $2 WHERE id = $1 AND deleted_at IS NULL",
+        comment_id,
+        deleted_by
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::handlers::users::GHOST_USER_ID;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailPreferences {
+    pub weekly_digest: bool,
+    /// Include new notes in the courses the user takes part in
+    pub course_notes: bool,
+    /// Include votes, comments and downloads on the user's own notes
+    pub upload_activity: bool,
+}
+
+impl Default for EmailPreferences {
+    fn default() -> Self {
+        Self {
+            weekly_digest: true,
+            course_notes: true,
+            upload_activity: true,
+        }
+    }
+}
+
+/// A user who is due a digest, with what it should cover
+pub struct DigestRecipient {
+    pub user_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub course_notes: bool,
+    pub upload_activity: bool,
+    /// Start of the period the digest covers
+    pub since: DateTime<Utc>,
+}
+
+/// A note uploaded to one of the recipient's courses
+pub struct DigestNote {
+    pub course_code: String,
+    pub course_name: String,
+    pub description: Option<String>,
+    pub note_year: i64,
+    pub note_semester: String,
+    pub uploader_name: String,
+}
+
+/// What happened on one of the recipient's notes
+pub struct UploadActivity {
+    pub course_code: String,
+    pub course_name: String,
+    pub note_year: i64,
+    pub note_semester: String,
+    pub upvotes: i64,
+    pub comments: i64,
+    pub downloads: i64,
+}
+
+pub async fn get_email_preferences(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<EmailPreferences, sqlx::Error> {
+    let preferences = sqlx::query_as!(
+        EmailPreferences,
+        r#"
+        SELECT weekly_digest, course_notes, upload_activity
+        FROM email_preferences
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(preferences.unwrap_or_default())
+}
+
+pub async fn update_email_preferences(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    preferences: &EmailPreferences,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_preferences (user_id, weekly_digest, course_notes, upload_activity)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id) DO UPDATE
+        SET weekly_digest = EXCLUDED.weekly_digest,
+            course_notes = EXCLUDED.course_notes,
+            upload_activity = EXCLUDED.upload_activity,
+            updated_at = NOW()
+        "#,
+        user_id,
+        preferences.weekly_digest,
+        preferences.course_notes,
+        preferences.upload_activity
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(())
+}
+
+/// Users whose last digest, or account creation if they never had one, is older than
+/// `due_before`. Users who opted out, banned users and the ghost user are left out, as are the
+/// users in `skip`.
+pub async fn get_digest_recipients(
+    db_wrapper: &DBPoolWrapper,
+    due_before: DateTime<Utc>,
+    skip: &[Uuid],
+    limit: i64,
+) -> Result<Vec<DigestRecipient>, sqlx::Error> {
+    let recipients = sqlx::query_as!(
+        DigestRecipient,
+        r#"
+        SELECT
+            u.id as user_id,
+            u.email,
+            COALESCE(u.display_name, u.full_name) as "name!",
+            COALESCE(p.course_notes, TRUE) as "course_notes!",
+            COALESCE(p.upload_activity, TRUE) as "upload_activity!",
+            COALESCE(p.last_digest_at, $1) as "since!"
+        FROM users u
+        LEFT JOIN email_preferences p ON p.user_id = u.id
+        WHERE COALESCE(p.weekly_digest, TRUE)
+          AND COALESCE(p.last_digest_at, u.created_at) <= $1
+          AND u.banned_at IS NULL
+          AND u.id <> $2
+          AND NOT (u.id = ANY ($3))
+        ORDER BY p.last_digest_at NULLS FIRST
+        LIMIT $4
+        "#,
+        due_before,
+        GHOST_USER_ID,
+        skip,
+        limit
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(recipients)
+}
+
+/// Visible notes uploaded by others since `since` in the courses a user follows or has uploaded
+/// to, voted on, commented on or downloaded from, most recent first
+pub async fn get_new_course_notes(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    since: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<DigestNote>, sqlx::Error> {
+    let notes = sqlx::query_as!(
+        DigestNote,
+        r#"
+        WITH courses AS (
+            SELECT UPPER(course_code) as course_code FROM notes WHERE uploader_user_id = $1
+            UNION
+            SELECT UPPER(n.course_code) FROM votes v JOIN notes n ON n.id = v.note_id WHERE v.user_id = $1
+            UNION
+            SELECT UPPER(n.course_code) FROM comments c JOIN notes n ON n.id = c.note_id WHERE c.user_id = $1
+            UNION
+            SELECT UPPER(n.course_code) FROM download_events d JOIN notes n ON n.id = d.note_id WHERE d.user_id = $1
+            UNION
+            SELECT target FROM follows WHERE user_id = $1 AND kind = 'course'
+        )
+        SELECT
+            n.course_code,
+            n.course_name,
+            n.description,
+            n.note_year,
+            n.note_semester,
+            CASE WHEN n.is_anonymous THEN 'Anonymous' ELSE u.public_name END as "uploader_name!"
+        FROM notes n
+        JOIN users u ON u.id = n.uploader_user_id
+        WHERE UPPER(n.course_code) IN (SELECT course_code FROM courses)
+          AND n.created_at >= $2
+          AND n.uploader_user_id <> $1
+          AND NOT n.is_hidden AND NOT u.notes_hidden
+        ORDER BY n.created_at DESC
+        LIMIT $3
+        "#,
+        user_id,
+        since,
+        limit
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(notes)
+}
+
+/// Upvotes, comments by others and downloads on each of a user's notes since `since`, for the
+/// notes that had any, busiest first
+pub async fn get_upload_activity(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    since: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<UploadActivity>, sqlx::Error> {
+    let activity = sqlx::query_as!(
+        UploadActivity,
+        r#"
+        SELECT
+            n.course_code,
+            n.course_name,
+            n.note_year,
+            n.note_semester,
+            counts.upvotes as "upvotes!",
+            counts.comments as "comments!",
+            counts.downloads as "downloads!"
+        FROM notes n
+        CROSS JOIN LATERAL (
+            SELECT
+                (SELECT COUNT(*) FROM votes v
+                 WHERE v.note_id = n.id AND v.is_upvote AND NOT v.excluded AND v.created_at >= $2) as upvotes,
+                (SELECT COUNT(*) FROM comments c
+                 WHERE c.note_id = n.id AND c.user_id <> $1 AND c.deleted_at IS NULL AND c.created_at >= $2) as comments,
+                (SELECT COUNT(*) FROM download_events d
+                 WHERE d.note_id = n.id AND d.created_at >= $2) as downloads
+        ) counts
+        WHERE n.uploader_user_id = $1
+          AND counts.upvotes + counts.comments + counts.downloads > 0
+        ORDER BY counts.upvotes + counts.comments + counts.downloads DESC
+        LIMIT $3
+        "#,
+        user_id,
+        since,
+        limit
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(activity)
+}
+
+/// Records that a user's digest covering everything up to `sent_at` was handled, whether or not
+/// there was anything to send
+pub async fn mark_digest_sent(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    sent_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_preferences (user_id, last_digest_at)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+        SET last_digest_at = EXCLUDED.last_digest_at
+        "#,
+        user_id,
+        sent_at
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(())
+}
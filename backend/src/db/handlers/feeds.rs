@@ -0,0 +1,76 @@
+use crate::db::db::DBPoolWrapper;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// What a public Atom feed lists the notes of
+pub enum FeedSource {
+    Course(String),
+    Tag(String),
+    User(Uuid),
+}
+
+/// A note as listed in a public feed. The uploader of an anonymous note is left out.
+pub struct FeedNote {
+    pub id: Uuid,
+    pub course_code: String,
+    pub course_name: String,
+    pub description: Option<String>,
+    pub professor_names: Option<Vec<String>>,
+    pub tags: Vec<String>,
+    pub note_year: i64,
+    pub note_semester: String,
+    pub has_preview_image: bool,
+    pub uploader_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// When the note was last edited, or uploaded if it never was
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The most recent visible notes of a course, with a tag or by a user, newest first. Course
+/// codes and tags are matched case insensitively. Anonymous notes are left out of user feeds.
+pub async fn get_feed_notes(
+    db_wrapper: &DBPoolWrapper,
+    source: &FeedSource,
+    limit: i64,
+) -> Result<Vec<FeedNote>, sqlx::Error> {
+    let (course_code, tag, user_id) = match source {
+        FeedSource::Course(course_code) => (Some(course_code.as_str()), None, None),
+        FeedSource::Tag(tag) => (None, Some(tag.as_str()), None),
+        FeedSource::User(user_id) => (None, None, Some(*user_id)),
+    };
+
+    let notes = sqlx::query_as!(
+        FeedNote,
+        r#"
+        SELECT
+            n.id,
+            n.course_code,
+            n.course_name,
+            n.description,
+            n.professor_names,
+            n.tags,
+            n.note_year,
+            n.note_semester,
+            n.has_preview_image,
+            CASE WHEN n.is_anonymous THEN NULL ELSE u.public_name END as "uploader_name?",
+            n.created_at,
+            COALESCE(n.updated_at, n.created_at) as "updated_at!"
+        FROM notes n
+        JOIN users u ON u.id = n.uploader_user_id
+        WHERE NOT n.is_hidden AND NOT u.notes_hidden
+          AND ($1::TEXT IS NULL OR UPPER(n.course_code) = UPPER($1))
+          AND ($2::TEXT IS NULL OR EXISTS (SELECT 1 FROM UNNEST(n.tags) as tag WHERE LOWER(tag) = LOWER($2)))
+          AND ($3::UUID IS NULL OR (n.uploader_user_id = $3 AND NOT n.is_anonymous))
+        ORDER BY n.created_at DESC
+        LIMIT $4
+        "#,
+        course_code,
+        tag,
+        user_id,
+        limit
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(notes)
+}
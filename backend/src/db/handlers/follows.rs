@@ -0,0 +1,255 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::models::{Follow, NoteWithUser};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How many days newer a note with a perfect Wilson score ranks in the feed. Notes are ranked by
+/// `days since the epoch + FEED_QUALITY_BOOST_DAYS * wilson_score`, which does not change with
+/// time.
+const FEED_QUALITY_BOOST_DAYS: f64 = 3.0;
+
+/// Where a page of the feed ends: the rank of its last note, kept as the upload time and Wilson
+/// score it is computed from, and the note's ID to break ties. The next page starts right after
+/// this position, whatever has happened to the note since.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeedCursor {
+    pub created_at: DateTime<Utc>,
+    pub wilson_score: f64,
+    pub note_id: Uuid,
+}
+
+impl FeedCursor {
+    pub fn after(note: &NoteWithUser) -> Self {
+        Self {
+            created_at: note.note_created_at,
+            wilson_score: note.note_wilson_score,
+            note_id: note.note_id,
+        }
+    }
+
+    /// The cursor as an opaque string for clients to send back
+    pub fn encode(&self) -> String {
+        let mut bytes = Vec::with_capacity(32);
+        bytes.extend_from_slice(&self.created_at.timestamp_micros().to_be_bytes());
+        bytes.extend_from_slice(&self.wilson_score.to_bits().to_be_bytes());
+        bytes.extend_from_slice(self.note_id.as_bytes());
+        hex::encode(bytes)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let bytes: [u8; 32] = hex::decode(cursor).ok()?.try_into().ok()?;
+        let (micros, rest) = bytes.split_at(8);
+        let (score, id) = rest.split_at(8);
+        Some(Self {
+            created_at: DateTime::from_timestamp_micros(i64::from_be_bytes(micros.try_into().ok()?))?,
+            wilson_score: f64::from_bits(u64::from_be_bytes(score.try_into().ok()?)),
+            note_id: Uuid::from_slice(id).ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FollowKind {
+    Course,
+    Professor,
+    User,
+}
+
+impl FollowKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FollowKind::Course => "course",
+            FollowKind::Professor => "professor",
+            FollowKind::User => "user",
+        }
+    }
+}
+
+/// Follows `target`, doing nothing if it is already followed. `target` must already be
+/// normalised, see `crate::api::handlers::follows`.
+pub async fn add_follow(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    kind: FollowKind,
+    target: &str,
+    followed_user_id: Option<Uuid>,
+) -> Result<Follow, sqlx::Error> {
+    let mut tx = db_wrapper.pool().begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO follows (user_id, kind, target, followed_user_id)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id, kind, LOWER(target)) DO NOTHING
+        "#,
+        user_id,
+        kind.as_str(),
+        target,
+        followed_user_id
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    let follow = sqlx::query_as!(
+        Follow,
+        "SELECT * FROM follows WHERE user_id = $1 AND kind = $2 AND LOWER(target) = LOWER($3)",
+        user_id,
+        kind.as_str(),
+        target
+    )
+        .fetch_one(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(follow)
+}
+
+/// Unfollows `target`, returning whether it was followed
+pub async fn remove_follow(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    kind: FollowKind,
+    target: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM follows WHERE user_id = $1 AND kind = $2 AND LOWER(target) = LOWER($3)",
+        user_id,
+        kind.as_str(),
+        target
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// A follow along with the followed user's public name and picture, if a user is followed
+pub struct FollowWithUser {
+    pub kind: String,
+    pub target: String,
+    pub created_at: DateTime<Utc>,
+    pub followed_user_id: Option<Uuid>,
+    pub followed_user_name: Option<String>,
+    pub followed_user_picture: Option<String>,
+}
+
+/// Everything a user follows, most recently followed first
+pub async fn get_follows(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<Vec<FollowWithUser>, sqlx::Error> {
+    let follows = sqlx::query_as!(
+        FollowWithUser,
+        r#"
+        SELECT
+            f.kind,
+            f.target,
+            f.created_at,
+            f.followed_user_id,
+            u.public_name as "followed_user_name?",
+            u.public_picture as "followed_user_picture?"
+        FROM follows f
+        LEFT JOIN users u ON u.id = f.followed_user_id
+        WHERE f.user_id = $1
+        ORDER BY f.created_at DESC
+        "#,
+        user_id
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(follows)
+}
+
+/// A page of a user's feed: visible notes by others in the courses, by the professors or
+/// uploaded by the users they follow, best ranked first. Anonymous notes only show up through
+/// their course or professors. The page starts after the cursor `after`.
+pub async fn get_feed(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    limit: i64,
+    after: Option<FeedCursor>,
+) -> Result<Vec<NoteWithUser>, sqlx::Error> {
+    let notes = sqlx::query_as!(
+        NoteWithUser,
+        r#"
+        WITH followed AS (
+            SELECT kind, target, followed_user_id FROM follows WHERE user_id = $1
+        )
+        SELECT
+            n.id as "note_id!",
+            n.course_name as "note_course_name!",
+            n.course_code as "note_course_code!",
+            n.description as "note_description",
+            n.professor_names as "note_professor_names",
+            n.tags as "note_tags!",
+            n.is_public as "note_is_public!",
+            n.has_preview_image as "note_has_preview_image!",
+            (n.is_hidden OR u.notes_hidden) as "note_is_hidden!",
+            n.is_anonymous as "note_is_anonymous!",
+            n.uploader_user_id as "note_uploader_user_id!",
+            n.created_at as "note_created_at!",
+            n.downloads as "note_downloads!",
+            n.note_year as "note_year!",
+            n.note_semester as "note_semester!",
+            COALESCE(upvote_counts.count, 0) as "note_upvote_count!",
+            COALESCE(downvote_counts.count, 0) as "note_downvote_count!",
+            COALESCE(comment_counts.count, 0) as "note_comment_count!",
+            n.wilson_score as "note_wilson_score!",
+            user_vote.is_upvote as "note_user_upvote?",
+            u.id as "user_id!",
+            u.public_name as "user_display_name!",
+            u.public_picture as "user_picture!"
+        FROM
+            notes n
+        JOIN
+            users u ON n.uploader_user_id = u.id
+        CROSS JOIN LATERAL
+            (SELECT EXTRACT(EPOCH FROM n.created_at)::FLOAT8 / 86400 + $3 * n.wilson_score as rank) r
+        LEFT JOIN
+            (SELECT note_id, COUNT(*) as count
+             FROM votes
+             WHERE is_upvote = true
+             GROUP BY note_id) upvote_counts ON n.id = upvote_counts.note_id
+        LEFT JOIN
+            (SELECT note_id, COUNT(*) as count
+             FROM votes
+             WHERE is_upvote = false
+             GROUP BY note_id) downvote_counts ON n.id = downvote_counts.note_id
+        LEFT JOIN
+            (SELECT note_id, COUNT(*) as count
+             FROM comments
+             WHERE deleted_at IS NULL
+             GROUP BY note_id) comment_counts ON n.id = comment_counts.note_id
+        LEFT JOIN
+            votes user_vote ON n.id = user_vote.note_id AND user_vote.user_id = $1
+        WHERE NOT n.is_hidden AND NOT u.notes_hidden
+          AND n.uploader_user_id <> $1
+          AND (
+            UPPER(n.course_code) IN (SELECT target FROM followed WHERE kind = 'course')
+            OR EXISTS (
+                SELECT 1
+                FROM followed f, UNNEST(n.professor_names) as professor
+                WHERE f.kind = 'professor' AND LOWER(TRIM(professor)) = LOWER(f.target)
+            )
+            OR (NOT n.is_anonymous AND n.uploader_user_id IN (SELECT followed_user_id FROM followed WHERE kind = 'user'))
+          )
+          AND ($4::TIMESTAMPTZ IS NULL
+               OR (r.rank, n.id) < (EXTRACT(EPOCH FROM $4::TIMESTAMPTZ)::FLOAT8 / 86400 + $3 * $5::FLOAT8, $6))
+        ORDER BY r.rank DESC, n.id DESC
+        LIMIT $2
+        "#,
+        user_id,
+        limit,
+        FEED_QUALITY_BOOST_DAYS,
+        after.map(|cursor| cursor.created_at),
+        after.map(|cursor| cursor.wilson_score),
+        after.map(|cursor| cursor.note_id)
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(notes)
+}
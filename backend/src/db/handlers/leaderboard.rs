@@ -0,0 +1,374 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::handlers::users::GHOST_USER_ID;
+use crate::scoring::{ReputationScorer, ReputationStats};
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// The period a leaderboard covers. Windows follow the calendar in the server's time zone, see
+/// `Semester` for when semesters start.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardWindow {
+    Week,
+    Month,
+    Semester,
+    #[default]
+    All,
+}
+
+impl LeaderboardWindow {
+    pub const WINDOWED: [LeaderboardWindow; 3] = [
+        LeaderboardWindow::Week,
+        LeaderboardWindow::Month,
+        LeaderboardWindow::Semester,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeaderboardWindow::Week => "week",
+            LeaderboardWindow::Month => "month",
+            LeaderboardWindow::Semester => "semester",
+            LeaderboardWindow::All => "all",
+        }
+    }
+
+    /// Start of the window containing `now`, `None` for all time
+    pub fn since(&self, now: DateTime<Local>) -> Option<DateTime<Utc>> {
+        let today = now.date_naive();
+        let start = match self {
+            LeaderboardWindow::All => return None,
+            LeaderboardWindow::Week => {
+                today - Days::new(today.weekday().num_days_from_monday() as u64)
+            }
+            LeaderboardWindow::Month => today.with_day(1)?,
+            LeaderboardWindow::Semester => Semester::containing(today).first_day()?,
+        };
+        local_midnight(start)
+    }
+}
+
+/// An academic semester. Spring runs from January to June and Autumn from July to December.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Semester {
+    pub year: i32,
+    pub autumn: bool,
+}
+
+impl Semester {
+    pub fn containing(date: NaiveDate) -> Self {
+        Self {
+            year: date.year(),
+            autumn: date.month() >= 7,
+        }
+    }
+
+    /// The semester before this one
+    pub fn previous(self) -> Self {
+        if self.autumn {
+            Self { year: self.year, autumn: false }
+        } else {
+            Self { year: self.year - 1, autumn: true }
+        }
+    }
+
+    /// The semester after this one
+    pub fn next(self) -> Self {
+        if self.autumn {
+            Self { year: self.year + 1, autumn: false }
+        } else {
+            Self { year: self.year, autumn: true }
+        }
+    }
+
+    pub fn first_day(self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year, if self.autumn { 7 } else { 1 }, 1)
+    }
+
+    /// Start and end of the semester in the server's time zone, the end being the start of the
+    /// next semester
+    pub fn bounds(self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        Some((local_midnight(self.first_day()?)?, local_midnight(self.next().first_day()?)?))
+    }
+
+    /// e.g. `Autumn 2026`
+    pub fn label(self) -> String {
+        format!("{} {}", if self.autumn { "Autumn" } else { "Spring" }, self.year)
+    }
+}
+
+/// The start of a day in the server's time zone
+fn local_midnight(date: NaiveDate) -> Option<DateTime<Utc>> {
+    date.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|start| start.with_timezone(&Utc))
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardEntry {
+    pub id: Uuid,
+    pub display_name: String,
+    pub picture: String,
+    pub reputation: f64,
+    pub total_notes: i64,
+    pub total_upvotes: i64,
+    pub total_downvotes: i64,
+    pub total_downloads: i64,
+    pub rank: i64,
+}
+
+/// A user's counts in one leaderboard window
+struct PeriodStats {
+    period: String,
+    user_id: Uuid,
+    total_notes: i64,
+    total_upvotes: i64,
+    total_downvotes: i64,
+    total_downloads: i64,
+}
+
+/// Recomputes the leaderboard stats of the given users in every window, or of every user if
+/// `user_ids` is `None`, scoring reputation with `scorer`. The ghost user holding the notes of
+/// deleted accounts is never ranked.
+///
+/// Within a window, a user's notes are those that were uploaded or got a vote or download in it,
+/// and only the votes and downloads made in the window are counted. Votes on older notes then
+/// count towards their own notes instead of being averaged over none.
+///
+/// Called after anything that changes a user's notes, the votes on them or their downloads,
+/// within the same transaction where there is one. A scheduled job also refreshes everyone to
+/// pick up new users, roll the windows over and catch anything missed.
+pub async fn refresh_leaderboard_stats(
+    conn: &mut PgConnection,
+    scorer: &dyn ReputationScorer,
+    user_ids: Option<&[Uuid]>,
+) -> Result<u64, sqlx::Error> {
+    let now = Local::now();
+    let [week, month, semester] = LeaderboardWindow::WINDOWED.map(|window| window.since(now));
+
+    let stats = sqlx::query_as!(
+        PeriodStats,
+        r#"
+        WITH periods (period, since) AS (
+            VALUES
+                ('all', NULL::TIMESTAMPTZ),
+                ('week', $2::TIMESTAMPTZ),
+                ('month', $3::TIMESTAMPTZ),
+                ('semester', $4::TIMESTAMPTZ)
+        ),
+        note_stats AS (
+            SELECT
+                p.period,
+                n.uploader_user_id as user_id,
+                COUNT(*) FILTER (
+                    WHERE p.since IS NULL
+                       OR n.created_at >= p.since
+                       OR votes.upvotes + votes.downvotes + downloads.count > 0
+                ) as total_notes,
+                SUM(votes.upvotes) as total_upvotes,
+                SUM(votes.downvotes) as total_downvotes,
+                SUM(downloads.count) as total_downloads
+            FROM periods p
+            CROSS JOIN notes n
+            CROSS JOIN LATERAL (
+                SELECT
+                    COUNT(*) FILTER (WHERE v.is_upvote) as upvotes,
+                    COUNT(*) FILTER (WHERE NOT v.is_upvote) as downvotes
+                FROM votes v
+                WHERE v.note_id = n.id
+                  AND NOT v.excluded
+                  AND (p.since IS NULL OR v.created_at >= p.since)
+            ) votes
+            CROSS JOIN LATERAL (
+                SELECT CASE
+                    WHEN p.since IS NULL THEN n.downloads
+                    ELSE (SELECT COUNT(*) FROM download_events d WHERE d.note_id = n.id AND d.created_at >= p.since)
+                END as count
+            ) downloads
+            WHERE $1::UUID[] IS NULL OR n.uploader_user_id = ANY ($1)
+            GROUP BY p.period, n.uploader_user_id
+        )
+        SELECT
+            p.period as "period!",
+            u.id as "user_id!",
+            COALESCE(s.total_notes, 0) as "total_notes!",
+            COALESCE(s.total_upvotes, 0)::BIGINT as "total_upvotes!",
+            COALESCE(s.total_downvotes, 0)::BIGINT as "total_downvotes!",
+            COALESCE(s.total_downloads, 0)::BIGINT as "total_downloads!"
+        FROM periods p
+        CROSS JOIN users u
+        LEFT JOIN note_stats s ON s.user_id = u.id AND s.period = p.period
+        WHERE ($1::UUID[] IS NULL OR u.id = ANY ($1)) AND u.id <> $5
+        "#,
+        user_ids,
+        week,
+        month,
+        semester,
+        GHOST_USER_ID
+    )
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut periods = Vec::with_capacity(stats.len());
+    let mut ids = Vec::with_capacity(stats.len());
+    let mut notes = Vec::with_capacity(stats.len());
+    let mut upvotes = Vec::with_capacity(stats.len());
+    let mut downvotes = Vec::with_capacity(stats.len());
+    let mut downloads = Vec::with_capacity(stats.len());
+    let mut reputations = Vec::with_capacity(stats.len());
+    for row in stats {
+        reputations.push(scorer.score(&ReputationStats {
+            notes: row.total_notes,
+            upvotes: row.total_upvotes,
+            downvotes: row.total_downvotes,
+            downloads: row.total_downloads,
+        }));
+        periods.push(row.period);
+        ids.push(row.user_id);
+        notes.push(row.total_notes);
+        upvotes.push(row.total_upvotes);
+        downvotes.push(row.total_downvotes);
+        downloads.push(row.total_downloads);
+    }
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO leaderboard_stats
+            (period, user_id, total_notes, total_upvotes, total_downvotes, total_downloads, reputation)
+        SELECT * FROM UNNEST($1::TEXT[], $2::UUID[], $3::BIGINT[], $4::BIGINT[], $5::BIGINT[], $6::BIGINT[], $7::FLOAT8[])
+        ON CONFLICT (period, user_id) DO UPDATE
+        SET total_notes = EXCLUDED.total_notes,
+            total_upvotes = EXCLUDED.total_upvotes,
+            total_downvotes = EXCLUDED.total_downvotes,
+            total_downloads = EXCLUDED.total_downloads,
+            reputation = EXCLUDED.reputation,
+            updated_at = NOW()
+        "#,
+        &periods,
+        &ids,
+        &notes,
+        &upvotes,
+        &downvotes,
+        &downloads,
+        &reputations
+    )
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Every user's stats in a window, ordered by their current rank
+pub async fn get_leaderboard_stats(
+    db_wrapper: &DBPoolWrapper,
+    window: LeaderboardWindow,
+) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+    let stats = sqlx::query_as!(
+        LeaderboardEntry,
+        r#"
+        SELECT
+            u.id,
+            u.public_name as display_name,
+            u.public_picture as picture,
+            s.reputation,
+            s.total_notes,
+            s.total_upvotes,
+            s.total_downvotes,
+            s.total_downloads,
+            RANK() OVER (ORDER BY s.reputation DESC, s.total_notes DESC) as "rank!"
+        FROM leaderboard_stats s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.period = $1
+        ORDER BY s.reputation DESC, s.total_notes DESC
+        "#,
+        window.as_str()
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(stats)
+}
+
+pub async fn get_leaderboard(
+    db_wrapper: &DBPoolWrapper,
+    window: LeaderboardWindow,
+    limit: i64,
+) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+    // The window is computed as rows are read off the index, so only `limit` rows are visited
+    let leaderboard = sqlx::query_as!(
+        LeaderboardEntry,
+        r#"
+        SELECT
+            u.id,
+            u.public_name as display_name,
+            u.public_picture as picture,
+            s.reputation,
+            s.total_notes,
+            s.total_upvotes,
+            s.total_downvotes,
+            s.total_downloads,
+            RANK() OVER (ORDER BY s.reputation DESC, s.total_notes DESC) as "rank!"
+        FROM leaderboard_stats s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.period = $1
+        ORDER BY s.reputation DESC, s.total_notes DESC
+        LIMIT $2
+        "#,
+        window.as_str(),
+        limit
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(leaderboard)
+}
+
+pub async fn get_user_leaderboard_position(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    window: LeaderboardWindow,
+) -> Result<Option<LeaderboardEntry>, sqlx::Error> {
+    // Users who have not been picked up by a refresh yet have no stats and rank as zero
+    let entry = sqlx::query_as!(
+        LeaderboardEntry,
+        r#"
+        WITH user_stats AS (
+            SELECT
+                u.id,
+                u.public_name as display_name,
+                u.public_picture as picture,
+                COALESCE(s.reputation, 0) as reputation,
+                COALESCE(s.total_notes, 0) as total_notes,
+                COALESCE(s.total_upvotes, 0) as total_upvotes,
+                COALESCE(s.total_downvotes, 0) as total_downvotes,
+                COALESCE(s.total_downloads, 0) as total_downloads
+            FROM users u
+            LEFT JOIN leaderboard_stats s ON s.user_id = u.id AND s.period = $2
+            WHERE u.id = $1
+        )
+        SELECT
+            us.id,
+            us.display_name,
+            us.picture,
+            us.reputation as "reputation!",
+            us.total_notes as "total_notes!",
+            us.total_upvotes as "total_upvotes!",
+            us.total_downvotes as "total_downvotes!",
+            us.total_downloads as "total_downloads!",
+            (
+                SELECT COUNT(*) + 1
+                FROM leaderboard_stats o
+                WHERE o.period = $2 AND (o.reputation, o.total_notes) > (us.reputation, us.total_notes)
+            ) as "rank!"
+        FROM user_stats us
+        "#,
+        user_id,
+        window.as_str()
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(entry)
+}
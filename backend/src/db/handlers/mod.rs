@@ -1,3 +1,14 @@
+pub mod audit;
+pub mod badges;
+pub mod comments;
+pub mod email;
+pub mod feeds;
+pub mod follows;
+pub mod leaderboard;
 pub mod notes;
+pub mod notifications;
+pub mod reports;
 pub mod users;
+pub mod vote_rings;
 pub mod votes;
+pub mod webhooks;
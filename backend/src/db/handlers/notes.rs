@@ -1,12 +1,14 @@
 use crate::db::db::DBPoolWrapper;
-use crate::db::models::{Note, NoteWithUser};
+use crate::db::handlers::leaderboard::refresh_leaderboard_stats;
+use crate::db::models::{DownloadEvent, DownloadableNote, Note, NoteWithUser};
+use chrono::{DateTime, Utc};
 use sqlx::Postgres;
 use uuid::Uuid;
 
+use crate::api::handlers::notes::NoteSort;
 use crate::api::models::CreateNote;
 
-/// Inserts a new note record into the database.
-
+/// Updates the preview image flag of a note within an open transaction.
 pub async fn update_note_preview_status(
     tx: &mut sqlx::Transaction<'_, Postgres>,
     note_id: Uuid,
@@ -34,9 +36,9 @@ pub async fn create_note(
     let note = sqlx::query_as!(
         Note,
         r#"
-        INSERT INTO notes (course_name, course_code, description, professor_names, tags, has_preview_image, uploader_user_id, note_year, note_semester)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-        RETURNING id, course_name, course_code, description, professor_names, tags, is_public, has_preview_image, uploader_user_id, created_at, downloads, note_year, note_semester
+        INSERT INTO notes (course_name, course_code, description, professor_names, tags, has_preview_image, uploader_user_id, note_year, note_semester, is_anonymous)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING id, course_name, course_code, description, professor_names, tags, is_public, has_preview_image, uploader_user_id, created_at, downloads, note_year, note_semester, is_anonymous
         "#,
         new_note.course_name,
         new_note.course_code,
@@ -47,10 +49,13 @@ pub async fn create_note(
         new_note.uploader_user_id,
         new_note.note_year as i64,
         new_note.note_semester,
+        new_note.is_anonymous,
     )
         .fetch_one(&mut *tx)  // Execute on the transaction instead of the pool
         .await?;
 
+    refresh_leaderboard_stats(&mut tx, db_wrapper.scorer(), Some(&[note.uploader_user_id])).await?;
+
     Ok((tx, note))
 }
 
@@ -58,6 +63,7 @@ pub async fn get_notes(
     db_wrapper: &DBPoolWrapper,
     num_notes: usize,
     current_user_id: Option<Uuid>,
+    sort: NoteSort,
 ) -> Result<Vec<NoteWithUser>, sqlx::Error> {
     let notes = sqlx::query_as!(
         NoteWithUser,
@@ -71,6 +77,8 @@ pub async fn get_notes(
             n.tags as "note_tags!",
             n.is_public as "note_is_public!",
             n.has_preview_image as "note_has_preview_image!",
+            (n.is_hidden OR u.notes_hidden) as "note_is_hidden!",
+            n.is_anonymous as "note_is_anonymous!",
             n.uploader_user_id as "note_uploader_user_id!",
             n.created_at as "note_created_at!",
             n.downloads as "note_downloads!",
@@ -78,12 +86,12 @@ pub async fn get_notes(
             n.note_semester as "note_semester!",
             COALESCE(upvote_counts.count, 0) as "note_upvote_count!",
             COALESCE(downvote_counts.count, 0) as "note_downvote_count!",
+            COALESCE(comment_counts.count, 0) as "note_comment_count!",
+            n.wilson_score as "note_wilson_score!",
             user_vote.is_upvote as "note_user_upvote?",
             u.id as "user_id!",
-            u.google_id as "user_google_id!",
-            u.email as "user_email!",
-            u.full_name as "user_full_name!",
-            u.created_at as "user_created_at!"
+            u.public_name as "user_display_name!",
+            u.public_picture as "user_picture!"
         FROM
             notes n
         JOIN
@@ -98,14 +106,22 @@ pub async fn get_notes(
              FROM votes
              WHERE is_upvote = false
              GROUP BY note_id) downvote_counts ON n.id = downvote_counts.note_id
+        LEFT JOIN
+            (SELECT note_id, COUNT(*) as count
+             FROM comments
+             WHERE deleted_at IS NULL
+             GROUP BY note_id) comment_counts ON n.id = comment_counts.note_id
         LEFT JOIN
             votes user_vote ON n.id = user_vote.note_id AND user_vote.user_id = $2
+        WHERE NOT n.is_hidden AND NOT u.notes_hidden
         ORDER BY
+            CASE WHEN $3 THEN n.wilson_score END DESC NULLS LAST,
             n.created_at DESC
         LIMIT $1
         "#,
         num_notes as i64,
-        current_user_id.as_ref()
+        current_user_id.as_ref(),
+        matches!(sort, NoteSort::Top)
     )
         .fetch_all(db_wrapper.pool())
         .await?;
@@ -130,6 +146,8 @@ pub async fn search_notes_by_query(
             n.tags as "note_tags!",
             n.is_public as "note_is_public!",
             n.has_preview_image as "note_has_preview_image!",
+            (n.is_hidden OR u.notes_hidden) as "note_is_hidden!",
+            n.is_anonymous as "note_is_anonymous!",
             n.uploader_user_id as "note_uploader_user_id!",
             n.created_at as "note_created_at!",
             n.downloads as "note_downloads!",
@@ -137,12 +155,12 @@ pub async fn search_notes_by_query(
             n.note_semester as "note_semester!",
             COALESCE(upvote_counts.count, 0) as "note_upvote_count!",
             COALESCE(downvote_counts.count, 0) as "note_downvote_count!",
+            COALESCE(comment_counts.count, 0) as "note_comment_count!",
+            n.wilson_score as "note_wilson_score!",
             user_vote.is_upvote as "note_user_upvote?",
             u.id as "user_id!",
-            u.google_id as "user_google_id!",
-            u.email as "user_email!",
-            u.full_name as "user_full_name!",
-            u.created_at as "user_created_at!"
+            u.public_name as "user_display_name!",
+            u.public_picture as "user_picture!"
         FROM
             notes n
         JOIN
@@ -157,10 +175,15 @@ pub async fn search_notes_by_query(
              FROM votes
              WHERE is_upvote = false
              GROUP BY note_id) downvote_counts ON n.id = downvote_counts.note_id
+        LEFT JOIN
+            (SELECT note_id, COUNT(*) as count
+             FROM comments
+             WHERE deleted_at IS NULL
+             GROUP BY note_id) comment_counts ON n.id = comment_counts.note_id
         LEFT JOIN
             votes user_vote ON n.id = user_vote.note_id AND user_vote.user_id = $2
-        WHERE course_name ILIKE $1 OR course_code ILIKE $1
-        ORDER BY COALESCE(upvote_counts.count, 0) DESC, n.created_at DESC
+        WHERE (course_name ILIKE $1 OR course_code ILIKE $1) AND NOT n.is_hidden AND NOT u.notes_hidden
+        ORDER BY n.wilson_score DESC, n.created_at DESC
         "#,
         search_term,
         current_user_id.as_ref()
@@ -187,6 +210,8 @@ pub async fn get_note_by_id(
         n.tags as "note_tags!",
         n.is_public as "note_is_public!",
         n.has_preview_image as "note_has_preview_image!",
+        (n.is_hidden OR u.notes_hidden) as "note_is_hidden!",
+        n.is_anonymous as "note_is_anonymous!",
         n.uploader_user_id as "note_uploader_user_id!",
         n.created_at as "note_created_at!",
         n.downloads as "note_downloads!",
@@ -194,12 +219,12 @@ pub async fn get_note_by_id(
         n.note_semester as "note_semester!",
         COALESCE(upvote_counts.count, 0) as "note_upvote_count!",
         COALESCE(downvote_counts.count, 0) as "note_downvote_count!",
+        COALESCE(comment_counts.count, 0) as "note_comment_count!",
+        n.wilson_score as "note_wilson_score!",
         user_vote.is_upvote as "note_user_upvote?",
         u.id as "user_id!",
-        u.google_id as "user_google_id!",
-        u.email as "user_email!",
-        u.full_name as "user_full_name!",
-        u.created_at as "user_created_at!"
+        u.public_name as "user_display_name!",
+        u.public_picture as "user_picture!"
     FROM
         notes n
     JOIN
@@ -214,6 +239,11 @@ pub async fn get_note_by_id(
          FROM votes
          WHERE is_upvote = false
          GROUP BY note_id) downvote_counts ON n.id = downvote_counts.note_id
+    LEFT JOIN
+        (SELECT note_id, COUNT(*) as count
+         FROM comments
+         WHERE deleted_at IS NULL
+         GROUP BY note_id) comment_counts ON n.id = comment_counts.note_id
     LEFT JOIN
         votes user_vote ON n.id = user_vote.note_id AND user_vote.user_id = $2
     WHERE n.id = $1
@@ -226,18 +256,76 @@ pub async fn get_note_by_id(
     Ok(note_with_user)
 }
 
-pub async fn increment_note_downloads(
+/// Records a download of a note by a signed in user or, failing that, by a hashed client IP.
+///
+/// Downloads by the same user or IP since `dedup_since` are ignored. Returns whether the download
+/// was counted, or `None` if the note does not exist.
+pub async fn record_note_download(
     db_wrapper: &DBPoolWrapper,
     note_id: Uuid,
-) -> Result<(), sqlx::Error> {
+    user_id: Option<Uuid>,
+    ip_hash: Option<String>,
+    dedup_since: DateTime<Utc>,
+) -> Result<Option<bool>, sqlx::Error> {
+    let mut tx = db_wrapper.pool().begin().await?;
+
+    // Locking the note serialises concurrent downloads so duplicates cannot slip through
+    let uploader_id = sqlx::query_scalar!(
+        "SELECT uploader_user_id FROM notes WHERE id = $1 FOR UPDATE",
+        note_id
+    )
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(uploader_id) = uploader_id else {
+        return Ok(None);
+    };
+
+    let duplicate = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM download_events
+            WHERE note_id = $1
+              AND created_at > $4
+              AND (user_id = $2 OR ($2::UUID IS NULL AND ip_hash = $3))
+        ) as "exists!"
+        "#,
+        note_id,
+        user_id,
+        ip_hash,
+        dedup_since
+    )
+        .fetch_one(&mut *tx)
+        .await?;
+    if duplicate {
+        return Ok(Some(false));
+    }
+
+    sqlx::query!(
+        "INSERT INTO download_events (note_id, user_id, ip_hash) VALUES ($1, $2, $3)",
+        note_id,
+        user_id,
+        ip_hash
+    )
+        .execute(&mut *tx)
+        .await?;
+
     sqlx::query!(
-        "UPDATE notes SET downloads = downloads + 1 WHERE id = $1",
+        r#"
+        UPDATE notes
+        SET downloads = legacy_downloads + (SELECT COUNT(*) FROM download_events WHERE note_id = $1)
+        WHERE id = $1
+        "#,
         note_id
     )
-        .execute(db_wrapper.pool())
+        .execute(&mut *tx)
         .await?;
 
-    Ok(())
+    refresh_leaderboard_stats(&mut tx, db_wrapper.scorer(), Some(&[uploader_id])).await?;
+
+    tx.commit().await?;
+
+    Ok(Some(true))
 }
 
 /// Get all notes uploaded by a specific user
@@ -258,6 +346,8 @@ pub async fn get_notes_by_user_id(
             n.tags as "note_tags!",
             n.is_public as "note_is_public!",
             n.has_preview_image as "note_has_preview_image!",
+            (n.is_hidden OR u.notes_hidden) as "note_is_hidden!",
+            n.is_anonymous as "note_is_anonymous!",
             n.uploader_user_id as "note_uploader_user_id!",
             n.created_at as "note_created_at!",
             n.downloads as "note_downloads!",
@@ -265,12 +355,12 @@ pub async fn get_notes_by_user_id(
             n.note_semester as "note_semester!",
             COALESCE(upvote_counts.count, 0) as "note_upvote_count!",
             COALESCE(downvote_counts.count, 0) as "note_downvote_count!",
+            COALESCE(comment_counts.count, 0) as "note_comment_count!",
+            n.wilson_score as "note_wilson_score!",
             user_vote.is_upvote as "note_user_upvote?",
             u.id as "user_id!",
-            u.google_id as "user_google_id!",
-            u.email as "user_email!",
-            u.full_name as "user_full_name!",
-            u.created_at as "user_created_at!"
+            u.public_name as "user_display_name!",
+            u.public_picture as "user_picture!"
         FROM
             notes n
         JOIN
@@ -285,9 +375,16 @@ pub async fn get_notes_by_user_id(
              FROM votes
              WHERE is_upvote = false
              GROUP BY note_id) downvote_counts ON n.id = downvote_counts.note_id
+        LEFT JOIN
+            (SELECT note_id, COUNT(*) as count
+             FROM comments
+             WHERE deleted_at IS NULL
+             GROUP BY note_id) comment_counts ON n.id = comment_counts.note_id
         LEFT JOIN
             votes user_vote ON n.id = user_vote.note_id AND user_vote.user_id = $2
         WHERE n.uploader_user_id = $1
+          AND ((NOT n.is_hidden AND NOT u.notes_hidden) OR n.uploader_user_id = $2)
+          AND (NOT n.is_anonymous OR n.uploader_user_id = $2)
         ORDER BY
             n.created_at DESC
         "#,
@@ -300,6 +397,7 @@ pub async fn get_notes_by_user_id(
 }
 
 /// Update an existing note
+#[allow(clippy::too_many_arguments)]
 pub async fn update_note(
     db_wrapper: &DBPoolWrapper,
     note_id: Uuid,
@@ -310,6 +408,8 @@ pub async fn update_note(
     tags: Vec<String>,
     year: usize,
     semester: String,
+    is_anonymous: Option<bool>,
+    updated_by: Uuid,
 ) -> Result<Note, sqlx::Error> {
     let note = sqlx::query_as!(
         Note,
@@ -321,9 +421,12 @@ pub async fn update_note(
             professor_names = $5,
             tags = $6,
             note_year = $7,
-            note_semester = $8
+            note_semester = $8,
+            is_anonymous = COALESCE($10, is_anonymous),
+            updated_at = NOW(),
+            updated_by = $9
         WHERE id = $1
-        RETURNING id, course_name, course_code, description, professor_names, tags, is_public, has_preview_image, uploader_user_id, created_at, downloads, note_year, note_semester
+        RETURNING id, course_name, course_code, description, professor_names, tags, is_public, has_preview_image, uploader_user_id, created_at, downloads, note_year, note_semester, is_anonymous
         "#,
         note_id,
         course_name,
@@ -333,6 +436,8 @@ pub async fn update_note(
         &tags,
         year as i64,
         semester,
+        updated_by,
+        is_anonymous,
     )
         .fetch_one(db_wrapper.pool())
         .await?;
@@ -344,11 +449,126 @@ pub async fn delete_note(
     db_wrapper: &DBPoolWrapper,
     note_id: Uuid,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        "DELETE FROM notes WHERE id = $1",
+    let mut tx = db_wrapper.pool().begin().await?;
+
+    let uploader_id = sqlx::query_scalar!(
+        "DELETE FROM notes WHERE id = $1 RETURNING uploader_user_id",
         note_id
     )
-        .execute(db_wrapper.pool())
+        .fetch_optional(&mut *tx)
         .await?;
+    if let Some(uploader_id) = uploader_id {
+        refresh_leaderboard_stats(&mut tx, db_wrapper.scorer(), Some(&[uploader_id])).await?;
+    }
+
+    tx.commit().await?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Fetches up to `limit` visible notes for a course, newest first.
+pub async fn get_downloadable_notes_by_course(
+    db_wrapper: &DBPoolWrapper,
+    course_code: &str,
+    limit: i64,
+) -> Result<Vec<DownloadableNote>, sqlx::Error> {
+    let notes = sqlx::query_as!(
+        DownloadableNote,
+        r#"
+        SELECT
+            n.id,
+            n.course_code,
+            n.course_name,
+            n.description,
+            n.professor_names,
+            n.note_year,
+            n.note_semester,
+            n.created_at,
+            CASE WHEN n.is_anonymous THEN 'Anonymous' ELSE u.public_name END as "uploader_name!"
+        FROM
+            notes n
+        JOIN
+            users u ON n.uploader_user_id = u.id
+        WHERE UPPER(n.course_code) = UPPER($1) AND NOT n.is_hidden AND NOT u.notes_hidden
+        ORDER BY
+            n.note_year DESC, n.created_at DESC
+        LIMIT $2
+        "#,
+        course_code,
+        limit
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(notes)
+}
+
+/// Fetches the visible notes among `note_ids`, in the order they were requested.
+pub async fn get_downloadable_notes_by_ids(
+    db_wrapper: &DBPoolWrapper,
+    note_ids: &[Uuid],
+) -> Result<Vec<DownloadableNote>, sqlx::Error> {
+    let notes = sqlx::query_as!(
+        DownloadableNote,
+        r#"
+        SELECT
+            n.id,
+            n.course_code,
+            n.course_name,
+            n.description,
+            n.professor_names,
+            n.note_year,
+            n.note_semester,
+            n.created_at,
+            CASE WHEN n.is_anonymous THEN 'Anonymous' ELSE u.public_name END as "uploader_name!"
+        FROM
+            notes n
+        JOIN
+            users u ON n.uploader_user_id = u.id
+        WHERE n.id = ANY($1) AND NOT n.is_hidden AND NOT u.notes_hidden
+        ORDER BY
+            array_position($1, n.id)
+        "#,
+        note_ids
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(notes)
+}
+
+/// Every note a user has uploaded, hidden ones included, oldest first
+pub async fn get_notes_by_uploader(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<Vec<Note>, sqlx::Error> {
+    let notes = sqlx::query_as!(
+        Note,
+        r#"
+        SELECT id, course_name, course_code, description, professor_names, tags, is_public, has_preview_image, uploader_user_id, created_at, downloads, note_year, note_semester, is_anonymous
+        FROM notes
+        WHERE uploader_user_id = $1
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(notes)
+}
+
+/// Every download counted for a user, oldest first
+pub async fn get_downloads_by_user(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<Vec<DownloadEvent>, sqlx::Error> {
+    let downloads = sqlx::query_as!(
+        DownloadEvent,
+        "SELECT * FROM download_events WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(downloads)
+}
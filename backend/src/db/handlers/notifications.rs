@@ -0,0 +1,144 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::handlers::users::GHOST_USER_ID;
+use crate::db::models::Notification;
+use uuid::Uuid;
+
+/// The note an event is about, as far as notifications are concerned
+pub struct NoteSubject {
+    pub uploader_user_id: Uuid,
+    pub course_code: String,
+}
+
+pub async fn get_note_subject(
+    db_wrapper: &DBPoolWrapper,
+    note_id: Uuid,
+) -> Result<Option<NoteSubject>, sqlx::Error> {
+    let subject = sqlx::query_as!(
+        NoteSubject,
+        "SELECT uploader_user_id, course_code FROM notes WHERE id = $1",
+        note_id
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(subject)
+}
+
+/// Notifies each of the users, folding the event into their unread notification of the same
+/// kind about the same subject if there is one. The ghost user is never notified.
+pub async fn notify(
+    db_wrapper: &DBPoolWrapper,
+    user_ids: &[Uuid],
+    kind: &str,
+    note_id: Uuid,
+    subject_id: Uuid,
+    course_code: &str,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO notifications (user_id, kind, note_id, subject_id, course_code)
+        SELECT user_id, $2, $3, $4, $5
+        FROM UNNEST($1::UUID[]) as user_id
+        WHERE user_id <> $6
+        ON CONFLICT (user_id, kind, subject_id) WHERE read_at IS NULL DO UPDATE
+        SET count = notifications.count + 1,
+            course_code = EXCLUDED.course_code,
+            updated_at = NOW()
+        "#,
+        user_ids,
+        kind,
+        note_id,
+        subject_id,
+        course_code,
+        GHOST_USER_ID
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// A page of a user's notifications, most recently updated first
+pub async fn get_notifications(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    unread_only: bool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Notification>, sqlx::Error> {
+    let notifications = sqlx::query_as!(
+        Notification,
+        r#"
+        SELECT *
+        FROM notifications
+        WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+        ORDER BY updated_at DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        user_id,
+        unread_only,
+        limit,
+        offset
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(notifications)
+}
+
+/// Every notification of a user, oldest first
+pub async fn get_all_notifications(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<Vec<Notification>, sqlx::Error> {
+    let notifications = sqlx::query_as!(
+        Notification,
+        "SELECT * FROM notifications WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(notifications)
+}
+
+pub async fn count_notifications(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    unread_only: bool,
+) -> Result<i64, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM notifications
+        WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+        "#,
+        user_id,
+        unread_only
+    )
+        .fetch_one(db_wrapper.pool())
+        .await?;
+
+    Ok(count)
+}
+
+/// Marks the given notifications of a user as read, or all of them if `ids` is `None`
+pub async fn mark_notifications_read(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    ids: Option<&[Uuid]>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE notifications
+        SET read_at = NOW()
+        WHERE user_id = $1 AND read_at IS NULL AND ($2::UUID[] IS NULL OR id = ANY ($2))
+        "#,
+        user_id,
+        ids
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,187 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::models::{NoteReport, ReportWithNote};
+use uuid::Uuid;
+
+/// Files a report against a note.
+///
+/// Once the number of open reports on the note reaches `hide_threshold` the note is hidden from
+/// the public listings until a moderator looks at it. Returns the report and whether this
+/// report caused the note to be hidden.
+pub async fn create_report(
+    db_wrapper: &DBPoolWrapper,
+    note_id: Uuid,
+    reporter_id: Uuid,
+    reason: &str,
+    details: Option<String>,
+    hide_threshold: i64,
+) -> Result<(NoteReport, bool), sqlx::Error> {
+    let mut tx = db_wrapper.pool().begin().await?;
+
+    let report = sqlx::query_as!(
+        NoteReport,
+        r#"
+        INSERT INTO note_reports (note_id, reporter_id, reason, details)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#,
+        note_id,
+        reporter_id,
+        reason,
+        details,
+    )
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let hidden = sqlx::query!(
+        r#"
+        UPDATE notes
+        SET is_hidden = TRUE,
+            hidden_by_reports = TRUE
+        WHERE id = $1
+          AND NOT is_hidden
+          AND (SELECT COUNT(*) FROM note_reports WHERE note_id = $1 AND status = 'open') >= $2
+        "#,
+        note_id,
+        hide_threshold
+    )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+        > 0;
+
+    tx.commit().await?;
+
+    Ok((report, hidden))
+}
+
+/// Fetches every report with the given status along with the reported note, oldest first.
+pub async fn get_reports_by_status(
+    db_wrapper: &DBPoolWrapper,
+    status: &str,
+) -> Result<Vec<ReportWithNote>, sqlx::Error> {
+    let reports = sqlx::query_as!(
+        ReportWithNote,
+        r#"
+        SELECT
+            r.id as "report_id!",
+            r.reason as "report_reason!",
+            r.details as "report_details?",
+            r.status as "report_status!",
+            r.assigned_to as "report_assigned_to?",
+            r.resolved_by as "report_resolved_by?",
+            r.resolution_note as "report_resolution_note?",
+            r.created_at as "report_created_at!",
+            r.resolved_at as "report_resolved_at?",
+            u.id as "reporter_id!",
+            u.full_name as "reporter_full_name!",
+            n.id as "note_id!",
+            n.course_name as "note_course_name!",
+            n.course_code as "note_course_code!",
+            n.uploader_user_id as "note_uploader_user_id!",
+            n.is_hidden as "note_is_hidden!"
+        FROM
+            note_reports r
+        JOIN
+            notes n ON r.note_id = n.id
+        JOIN
+            users u ON r.reporter_id = u.id
+        WHERE r.status = $1
+        ORDER BY
+            r.created_at ASC
+        "#,
+        status
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(reports)
+}
+
+/// Assigns all open reports on a note to a moderator. Returns the number of reports assigned.
+pub async fn assign_reports(
+    db_wrapper: &DBPoolWrapper,
+    note_id: Uuid,
+    moderator_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE note_reports SET assigned_to = $2 WHERE note_id = $1 AND status = 'open'",
+        note_id,
+        moderator_id
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Closes all open reports on a note as either `resolved` or `dismissed`. Upholding them hides
+/// the note for good, while dismissing them only restores the note if it was hidden by reaching
+/// the report threshold, never if a moderator hid it. Returns the users whose reports were closed
+/// and whether the note is hidden now. The note is left alone if it had no open reports.
+pub async fn close_reports(
+    db_wrapper: &DBPoolWrapper,
+    note_id: Uuid,
+    moderator_id: Uuid,
+    status: &str,
+    resolution_note: Option<String>,
+    hide_note: bool,
+) -> Result<(Vec<Uuid>, bool), sqlx::Error> {
+    let mut tx = db_wrapper.pool().begin().await?;
+
+    let reporter_ids = sqlx::query_scalar!(
+        r#"
+        UPDATE note_reports
+        SET status = $3,
+            resolved_by = $2,
+            resolution_note = $4,
+            resolved_at = NOW()
+        WHERE note_id = $1 AND status = 'open'
+        RETURNING reporter_id
+        "#,
+        note_id,
+        moderator_id,
+        status,
+        resolution_note
+    )
+        .fetch_all(&mut *tx)
+        .await?;
+
+    if reporter_ids.is_empty() {
+        tx.rollback().await?;
+        return Ok((reporter_ids, false));
+    }
+
+    let is_hidden = sqlx::query_scalar!(
+        r#"
+        UPDATE notes
+        SET is_hidden = CASE WHEN $2 THEN TRUE WHEN hidden_by_reports THEN FALSE ELSE is_hidden END,
+            hidden_by_reports = FALSE
+        WHERE id = $1
+        RETURNING is_hidden
+        "#,
+        note_id,
+        hide_note
+    )
+        .fetch_one(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok((reporter_ids, is_hidden))
+}
+
+/// Every report a user has filed, oldest first
+pub async fn get_reports_by_reporter(
+    db_wrapper: &DBPoolWrapper,
+    reporter_id: Uuid,
+) -> Result<Vec<NoteReport>, sqlx::Error> {
+    let reports = sqlx::query_as!(
+        NoteReport,
+        "SELECT * FROM note_reports WHERE reporter_id = $1 ORDER BY created_at",
+        reporter_id
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(reports)
+}
@@ -1,9 +1,28 @@
 use crate::db::db::DBPoolWrapper;
-use crate::db::models::User;
+use crate::db::handlers::leaderboard::refresh_leaderboard_stats;
+use crate::db::handlers::votes::refresh_wilson_score;
+use chrono::{DateTime, Utc};
+use crate::db::models::{Role, User};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
 use uuid::Uuid;
 
+/// Placeholder user that the notes of deleted accounts are reassigned to
+pub const GHOST_USER_ID: Uuid = Uuid::nil();
+
+/// Picture of users without one of their own, the same as the default of `users.picture`
+pub const DEFAULT_PICTURE: &str =
+    "https://lh3.googleusercontent.com/-XdUIqdMkCWA/AAAAAAAAAAI/AAAAAAAAAAA/4252rscbv5M/photo.jpg";
+
+/// What happens to a user's notes when they delete their account
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountNotes {
+    Delete,
+    /// Keep the notes up, reassigned to the ghost user
+    #[serde(alias = "anonymize")]
+    Anonymise,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct GoogleUserInfo {
     pub google_id: String,
@@ -12,16 +31,38 @@ pub struct GoogleUserInfo {
     pub picture: String,
 }
 
+/// The editable part of a user's profile
+#[derive(Debug, Clone, Serialize)]
+pub struct UserProfile {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub department: Option<String>,
+    pub graduation_year: Option<i32>,
+    pub show_initials_only: bool,
+}
+
+impl From<&User> for UserProfile {
+    fn from(user: &User) -> Self {
+        Self {
+            display_name: user.display_name.clone(),
+            bio: user.bio.clone(),
+            department: user.department.clone(),
+            graduation_year: user.graduation_year,
+            show_initials_only: user.show_initials_only,
+        }
+    }
+}
+
+/// Contributions shown on a user's public profile, on top of their leaderboard rank and
+/// reputation
 #[derive(Debug, Serialize)]
-pub struct LeaderboardEntry {
-    pub id: Uuid,
-    pub full_name: String,
-    pub picture: String,
-    pub reputation: f64,
+pub struct UserContributions {
     pub total_notes: i64,
     pub total_upvotes: i64,
+    pub total_downvotes: i64,
     pub total_downloads: i64,
-    pub rank: i64,
+    pub courses_covered: i64,
+    pub total_comments: i64,
 }
 
 pub async fn find_user_by_google_id(
@@ -39,17 +80,141 @@ pub async fn find_user_by_google_id(
     Ok(user)
 }
 
+pub async fn find_user_by_id(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        "SELECT * FROM users WHERE id = $1",
+        user_id
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(user)
+}
+
+/// Suspends a user until the given time
+pub async fn suspend_user(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    until: DateTime<Utc>,
+    reason: &str,
+    sanctioned_by: Uuid,
+) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        r#"
+        UPDATE users
+        SET suspended_until = $2,
+            sanction_reason = $3,
+            sanctioned_by = $4
+        WHERE id = $1
+        RETURNING *
+        "#,
+        user_id,
+        until,
+        reason,
+        sanctioned_by
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(user)
+}
+
+/// Bans a user permanently, optionally hiding all of their notes
+pub async fn ban_user(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    reason: &str,
+    hide_notes: bool,
+    sanctioned_by: Uuid,
+) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        r#"
+        UPDATE users
+        SET banned_at = NOW(),
+            suspended_until = NULL,
+            sanction_reason = $2,
+            sanctioned_by = $4,
+            notes_hidden = $3
+        WHERE id = $1
+        RETURNING *
+        "#,
+        user_id,
+        reason,
+        hide_notes,
+        sanctioned_by
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(user)
+}
+
+/// Lifts any suspension or ban on a user and makes their notes visible again
+pub async fn reinstate_user(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        r#"
+        UPDATE users
+        SET banned_at = NULL,
+            suspended_until = NULL,
+            sanction_reason = NULL,
+            sanctioned_by = NULL,
+            notes_hidden = FALSE
+        WHERE id = $1
+        RETURNING *
+        "#,
+        user_id
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(user)
+}
+
+/// Changes the role of a user, returning the updated user if they exist
+pub async fn set_user_role(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    role: Role,
+) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        "UPDATE users SET role = $2 WHERE id = $1 RETURNING *",
+        user_id,
+        role.as_str()
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(user)
+}
+
+/// Creates the user on their first sign in. On later sign ins, the name, email and picture are
+/// refreshed from Google. A display name set by the user is kept separately and is not touched.
 pub async fn find_or_create_user(
     db_wrapper: &DBPoolWrapper,
     user_info: GoogleUserInfo,
 ) -> Result<User, sqlx::Error> {
-    let existing_user = find_user_by_google_id(db_wrapper, &user_info.google_id).await?;
-    if let Some(user) = existing_user {
-        return Ok(user);
-    }
-    let new_user = sqlx::query_as!(
+    let user = sqlx::query_as!(
         User,
-        "INSERT INTO users (google_id, email, full_name, picture) VALUES ($1, $2, $3, $4) RETURNING *",
+        r#"
+        INSERT INTO users (google_id, email, full_name, picture)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (google_id) DO UPDATE
+        SET email = EXCLUDED.email,
+            full_name = EXCLUDED.full_name,
+            picture = EXCLUDED.picture
+        RETURNING *
+        "#,
         user_info.google_id,
         user_info.email,
         user_info.full_name,
@@ -58,151 +223,171 @@ pub async fn find_or_create_user(
         .fetch_one(db_wrapper.pool())
         .await?;
 
-    Ok(new_user)
+    Ok(user)
 }
 
-pub async fn get_leaderboard(
+/// Saves the fields of a user's profile that they can edit themselves
+pub async fn update_user_profile(
     db_wrapper: &DBPoolWrapper,
-    limit: i64,
-) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
-    let rows = sqlx::query!(
+    user_id: Uuid,
+    profile: &UserProfile,
+) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        r#"
+        UPDATE users
+        SET display_name = $2,
+            bio = $3,
+            department = $4,
+            graduation_year = $5,
+            show_initials_only = $6
+        WHERE id = $1
+        RETURNING *
+        "#,
+        user_id,
+        profile.display_name,
+        profile.bio,
+        profile.department,
+        profile.graduation_year,
+        profile.show_initials_only
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(user)
+}
+
+/// Counts a user's visible notes, the votes and downloads they got, the courses they cover and
+/// the comments the user has written. Anonymous notes are only counted with `include_anonymous`,
+/// for the user themselves, so that the profile does not give away their anonymous uploads.
+pub async fn get_user_contributions(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+    include_anonymous: bool,
+) -> Result<UserContributions, sqlx::Error> {
+    let contributions = sqlx::query_as!(
+        UserContributions,
         r#"
-        WITH user_stats AS (
-            SELECT
-                u.id,
-                u.full_name,
-                u.picture,
-                COUNT(DISTINCT n.id) as total_notes,
-                COUNT(DISTINCT CASE WHEN v.is_upvote = true THEN v.id END) as total_upvotes,
-                COALESCE(SUM(n.downloads), 0) as total_downloads
-            FROM users u
-            LEFT JOIN notes n ON u.id = n.uploader_user_id
-            LEFT JOIN votes v ON n.id = v.note_id
-            GROUP BY u.id, u.full_name, u.picture
-        ),
-        user_reputation AS (
-            SELECT
-                id,
-                full_name,
-                picture,
-                total_notes,
-                total_upvotes,
-                total_downloads,
-                CASE
-                    WHEN total_notes > 0 THEN
-                        (total_upvotes::FLOAT / total_notes::FLOAT) *
-                        (total_notes + total_upvotes + total_downloads)
-                    ELSE 0
-                END as reputation
-            FROM user_stats
-        )
         SELECT
-            id,
-            full_name,
-            picture,
-            reputation,
-            total_notes,
-            total_upvotes,
-            total_downloads,
-            RANK() OVER (ORDER BY reputation DESC, total_notes DESC) as rank
-        FROM user_reputation
-        ORDER BY rank
-        LIMIT $1
+            COUNT(n.id) as "total_notes!",
+            COALESCE(SUM(votes.upvotes), 0)::BIGINT as "total_upvotes!",
+            COALESCE(SUM(votes.downvotes), 0)::BIGINT as "total_downvotes!",
+            COALESCE(SUM(n.downloads), 0)::BIGINT as "total_downloads!",
+            COUNT(DISTINCT UPPER(n.course_code)) as "courses_covered!",
+            (
+                SELECT COUNT(*)
+                FROM comments c
+                WHERE c.user_id = $1 AND c.deleted_at IS NULL
+            ) as "total_comments!"
+        FROM notes n
+        CROSS JOIN LATERAL (
+            SELECT
+                COUNT(*) FILTER (WHERE v.is_upvote) as upvotes,
+                COUNT(*) FILTER (WHERE NOT v.is_upvote) as downvotes
+            FROM votes v
+            WHERE v.note_id = n.id AND NOT v.excluded
+        ) votes
+        WHERE n.uploader_user_id = $1 AND NOT n.is_hidden AND ($2 OR NOT n.is_anonymous)
         "#,
-        limit
+        user_id,
+        include_anonymous
     )
-        .fetch_all(db_wrapper.pool())
+        .fetch_one(db_wrapper.pool())
         .await?;
 
-    let leaderboard = rows
-        .into_iter()
-        .map(|row| LeaderboardEntry {
-            id: row.id,
-            full_name: row.full_name,
-            picture: row.picture,
-            reputation: row.reputation.unwrap_or(0.0),
-            total_notes: row.total_notes.unwrap_or(0),
-            total_upvotes: row.total_upvotes.unwrap_or(0),
-            total_downloads: row.total_downloads.unwrap_or(0),
-            rank: row.rank.unwrap_or(0),
-        })
-        .collect();
+    Ok(contributions)
+}
 
-    Ok(leaderboard)
+/// A note deleted along with its uploader's account
+pub struct DeletedNote {
+    pub id: Uuid,
+    pub course_code: String,
+    pub course_name: String,
 }
 
-pub async fn get_user_leaderboard_position(
+/// Deletes a user's account, returning the notes that were deleted along with it.
+///
+/// Comments are kept as deleted comments of the ghost user so that replies from others stay in
+/// place, and reports the user filed are handed to the ghost user so that moderators can still act
+/// on them. Votes cast by the user are removed, so the scores of the notes they voted on and the
+/// stats of those notes' uploaders are recomputed.
+pub async fn delete_user_account(
     db_wrapper: &DBPoolWrapper,
     user_id: Uuid,
-) -> Result<Option<LeaderboardEntry>, sqlx::Error> {
-    let row = sqlx::query!(
+    notes: AccountNotes,
+) -> Result<Vec<DeletedNote>, sqlx::Error> {
+    let mut tx = db_wrapper.pool().begin().await?;
+
+    let voted_notes = sqlx::query!(
         r#"
-        WITH user_stats AS (
-            SELECT
-                u.id,
-                u.full_name,
-                u.picture,
-                COUNT(DISTINCT n.id) as total_notes,
-                COUNT(DISTINCT CASE WHEN v.is_upvote = true THEN v.id END) as total_upvotes,
-                COALESCE(SUM(n.downloads), 0) as total_downloads
-            FROM users u
-            LEFT JOIN notes n ON u.id = n.uploader_user_id
-            LEFT JOIN votes v ON n.id = v.note_id
-            GROUP BY u.id, u.full_name, u.picture
-        ),
-        user_reputation AS (
-            SELECT
-                id,
-                full_name,
-                picture,
-                total_notes,
-                total_upvotes,
-                total_downloads,
-                CASE
-                    WHEN total_notes > 0 THEN
-                        (total_upvotes::FLOAT / total_notes::FLOAT) *
-                        (total_notes + total_upvotes + total_downloads)
-                    ELSE 0
-                END as reputation
-            FROM user_stats
-        ),
-        ranked_users AS (
-            SELECT
-                id,
-                full_name,
-                picture,
-                reputation,
-                total_notes,
-                total_upvotes,
-                total_downloads,
-                RANK() OVER (ORDER BY reputation DESC, total_notes DESC) as rank
-            FROM user_reputation
-        )
-        SELECT
-            id,
-            full_name,
-            picture,
-            reputation,
-            total_notes,
-            total_upvotes,
-            total_downloads,
-            rank
-        FROM ranked_users
-        WHERE id = $1
+        SELECT id, uploader_user_id
+        FROM notes
+        WHERE id IN (SELECT note_id FROM votes WHERE user_id = $1) AND uploader_user_id <> $1
+        FOR UPDATE
         "#,
         user_id
     )
-        .fetch_optional(db_wrapper.pool())
+        .fetch_all(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE comments
+        SET user_id = $2,
+            body = '',
+            deleted_at = COALESCE(deleted_at, NOW())
+        WHERE user_id = $1
+        "#,
+        user_id,
+        GHOST_USER_ID
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "UPDATE note_reports SET reporter_id = $2 WHERE reporter_id = $1",
+        user_id,
+        GHOST_USER_ID
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    let deleted_notes = match notes {
+        AccountNotes::Delete => {
+            sqlx::query_as!(
+                DeletedNote,
+                "DELETE FROM notes WHERE uploader_user_id = $1 RETURNING id, course_code, course_name",
+                user_id
+            )
+                .fetch_all(&mut *tx)
+                .await?
+        }
+        AccountNotes::Anonymise => {
+            sqlx::query!(
+                "UPDATE notes SET uploader_user_id = $2 WHERE uploader_user_id = $1",
+                user_id,
+                GHOST_USER_ID
+            )
+                .execute(&mut *tx)
+                .await?;
+            Vec::new()
+        }
+    };
+
+    sqlx::query!("DELETE FROM users WHERE id = $1", user_id)
+        .execute(&mut *tx)
         .await?;
 
-    Ok(row.map(|r| LeaderboardEntry {
-        id: r.id,
-        full_name: r.full_name,
-        picture: r.picture,
-        reputation: r.reputation.unwrap_or(0.0),
-        total_notes: r.total_notes.unwrap_or(0),
-        total_upvotes: r.total_upvotes.unwrap_or(0),
-        total_downloads: r.total_downloads.unwrap_or(0),
-        rank: r.rank.unwrap_or(0),
-    }))
-}
\ No newline at end of file
+    for note in &voted_notes {
+        refresh_wilson_score(&mut tx, note.id).await?;
+    }
+    let mut uploaders: Vec<Uuid> = voted_notes.iter().map(|note| note.uploader_user_id).collect();
+    uploaders.sort();
+    uploaders.dedup();
+    if !uploaders.is_empty() {
+        refresh_leaderboard_stats(&mut tx, db_wrapper.scorer(), Some(&uploaders)).await?;
+    }
+
+    tx.commit().await?;
+    Ok(deleted_notes)
+}
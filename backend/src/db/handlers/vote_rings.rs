@@ -0,0 +1,159 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::handlers::leaderboard::refresh_leaderboard_stats;
+use crate::db::models::VoteRing;
+use uuid::Uuid;
+
+/// Number of upvotes one user has given to notes uploaded by another
+pub struct UpvoteEdge {
+    pub voter_id: Uuid,
+    pub uploader_id: Uuid,
+    pub votes: i64,
+}
+
+/// Fetches the upvote counts between every pair of users, used to look for vote rings.
+pub async fn get_upvote_edges(db_wrapper: &DBPoolWrapper) -> Result<Vec<UpvoteEdge>, sqlx::Error> {
+    let edges = sqlx::query_as!(
+        UpvoteEdge,
+        r#"
+        SELECT
+            v.user_id as "voter_id!",
+            n.uploader_user_id as "uploader_id!",
+            COUNT(*) as "votes!"
+        FROM
+            votes v
+        JOIN
+            notes n ON v.note_id = n.id
+        WHERE v.is_upvote AND v.user_id <> n.uploader_user_id
+        GROUP BY
+            v.user_id, n.uploader_user_id
+        "#
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(edges)
+}
+
+/// Flags a cluster of accounts as a possible vote ring. `member_ids` must be sorted.
+///
+/// Clusters that were already flagged only get their vote counts refreshed, so a ring cleared
+/// by a moderator is not flagged again.
+pub async fn upsert_vote_ring(
+    db_wrapper: &DBPoolWrapper,
+    member_ids: &[Uuid],
+    mutual_votes: i64,
+    total_votes: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO vote_rings (member_ids, mutual_votes, total_votes)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (member_ids)
+        DO UPDATE SET mutual_votes = EXCLUDED.mutual_votes,
+                      total_votes = EXCLUDED.total_votes,
+                      updated_at = NOW()
+        "#,
+        member_ids,
+        mutual_votes as i32,
+        total_votes as i32
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(())
+}
+
+/// Excludes votes cast within confirmed rings from reputation, including ones cast after the
+/// ring was confirmed. Returns the number of newly excluded votes.
+pub async fn exclude_confirmed_ring_votes(db_wrapper: &DBPoolWrapper) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE votes v
+        SET excluded = TRUE
+        FROM notes n, vote_rings r
+        WHERE v.note_id = n.id
+          AND r.status = 'confirmed'
+          AND v.user_id = ANY (r.member_ids)
+          AND n.uploader_user_id = ANY (r.member_ids)
+          AND NOT v.excluded
+        "#
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn get_vote_rings_by_status(
+    db_wrapper: &DBPoolWrapper,
+    status: &str,
+) -> Result<Vec<VoteRing>, sqlx::Error> {
+    let rings = sqlx::query_as!(
+        VoteRing,
+        "SELECT * FROM vote_rings WHERE status = $1 ORDER BY detected_at DESC",
+        status
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(rings)
+}
+
+/// Marks a vote ring as `confirmed` or `cleared` and excludes or restores the votes between its
+/// members accordingly. Votes that are still covered by another confirmed ring stay excluded.
+pub async fn review_vote_ring(
+    db_wrapper: &DBPoolWrapper,
+    ring_id: Uuid,
+    status: &str,
+    reviewed_by: Uuid,
+) -> Result<Option<VoteRing>, sqlx::Error> {
+    let mut tx = db_wrapper.pool().begin().await?;
+
+    let ring = sqlx::query_as!(
+        VoteRing,
+        r#"
+        UPDATE vote_rings
+        SET status = $2,
+            reviewed_by = $3,
+            reviewed_at = NOW(),
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING *
+        "#,
+        ring_id,
+        status,
+        reviewed_by
+    )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let Some(ring) = ring else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE votes v
+        SET excluded = EXISTS (
+            SELECT 1
+            FROM vote_rings r
+            WHERE r.status = 'confirmed'
+              AND v.user_id = ANY (r.member_ids)
+              AND n.uploader_user_id = ANY (r.member_ids)
+        )
+        FROM notes n
+        WHERE v.note_id = n.id
+          AND v.user_id = ANY ($1)
+          AND n.uploader_user_id = ANY ($1)
+        "#,
+        &ring.member_ids
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    refresh_leaderboard_stats(&mut tx, db_wrapper.scorer(), Some(&ring.member_ids)).await?;
+
+    tx.commit().await?;
+
+    Ok(Some(ring))
+}
@@ -1,39 +1,111 @@
 use crate::api::handlers::votes::VoteType;
+use crate::db::handlers::leaderboard::refresh_leaderboard_stats;
 use crate::db::models::DBVote;
 use crate::db::DBPoolWrapper;
+use sqlx::Postgres;
 use uuid::Uuid;
 
+/// z-score for a 95% confidence interval
+const WILSON_Z: f64 = 1.96;
+
+/// Lower bound of the Wilson score confidence interval for the fraction of positive votes.
+///
+/// Unlike the plain ratio this favours notes with many votes over ones with only a handful,
+/// so a note with 40 upvotes and 2 downvotes ranks above one with a single upvote.
+pub fn wilson_lower_bound(upvotes: i64, downvotes: i64) -> f64 {
+    let total = (upvotes + downvotes) as f64;
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    let p = upvotes as f64 / total;
+    let z2 = WILSON_Z * WILSON_Z;
+    (p + z2 / (2.0 * total) - WILSON_Z * ((p * (1.0 - p) + z2 / (4.0 * total)) / total).sqrt())
+        / (1.0 + z2 / total)
+}
+
+#[derive(Debug)]
+pub enum VoteError {
+    NoteNotFound,
+    /// Uploaders cannot vote on their own notes
+    SelfVote,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for VoteError {
+    fn from(err: sqlx::Error) -> Self {
+        VoteError::Database(err)
+    }
+}
+
+/// Recomputes the stored Wilson score of a note from its votes within an open transaction.
+/// The note row must already be locked by the transaction.
+pub async fn refresh_wilson_score(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    note_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let counts = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE is_upvote) as "upvotes!",
+            COUNT(*) FILTER (WHERE NOT is_upvote) as "downvotes!"
+        FROM votes
+        WHERE note_id = $1
+        "#,
+        note_id
+    )
+        .fetch_one(&mut **tx)
+        .await?;
+
+    sqlx::query!(
+        "UPDATE notes SET wilson_score = $2 WHERE id = $1",
+        note_id,
+        wilson_lower_bound(counts.upvotes, counts.downvotes)
+    )
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+/// The vote a user has on a note after voting, and whether voting turned no vote or a downvote
+/// into an upvote
+pub struct VoteOutcome {
+    pub vote: Option<DBVote>,
+    pub upvoted: bool,
+}
+
 pub async fn vote(
     db_wrapper: &DBPoolWrapper,
     user_id: Uuid,
     note_id: Uuid,
     vote_type: VoteType,
-) -> Result<Option<DBVote>, sqlx::Error> {
+) -> Result<VoteOutcome, VoteError> {
     let mut tx = db_wrapper.pool().begin().await?;
-    let existing_vote = sqlx::query_as!(
-        DBVote,
-        "SELECT * FROM votes WHERE user_id = $1 AND note_id = $2",
-        user_id,
+
+    // Lock the note so that concurrent votes cannot overwrite each other's score
+    let uploader_id = sqlx::query_scalar!(
+        "SELECT uploader_user_id FROM notes WHERE id = $1 FOR UPDATE",
         note_id
     )
-    .fetch_optional(&mut *tx)
-    .await?;
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(VoteError::NoteNotFound)?;
 
-    let mut return_value: Option<DBVote> = None;
+    if uploader_id == user_id && !matches!(vote_type, VoteType::Remove) {
+        return Err(VoteError::SelfVote);
+    }
 
-    if let Some(_vote) = existing_vote {
-        if let VoteType::Upvote(is_upvote) = vote_type {
-            let new_vote = sqlx::query_as!(
-                DBVote,
-                "UPDATE votes SET is_upvote = $1 WHERE user_id = $2 AND note_id = $3 RETURNING *",
-                is_upvote,
-                user_id,
-                note_id
-            )
-            .fetch_one(&mut *tx)
-            .await?;
-            return_value = Some(new_vote);
-        } else {
+    let was_upvote = sqlx::query_scalar!(
+        "SELECT is_upvote FROM votes WHERE user_id = $1 AND note_id = $2",
+        user_id,
+        note_id
+    )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let vote = match vote_type {
+        VoteType::Remove => {
             sqlx::query!(
                 "DELETE FROM votes WHERE user_id = $1 AND note_id = $2",
                 user_id,
@@ -41,25 +113,88 @@ pub async fn vote(
             )
             .execute(&mut *tx)
             .await?;
-            return_value = None;
+            None
         }
-    } else {
-        if let VoteType::Upvote(is_upvote) = vote_type {
+        VoteType::Upvote | VoteType::Downvote(_) => {
+            let (is_upvote, downvote_reason) = match vote_type {
+                VoteType::Downvote(reason) => (false, Some(reason.as_str())),
+                _ => (true, None),
+            };
             let new_vote = sqlx::query_as!(
                 DBVote,
-                "INSERT INTO votes (user_id, note_id, is_upvote) VALUES ($1, $2, $3) RETURNING *",
+                r#"
+                INSERT INTO votes (user_id, note_id, is_upvote, downvote_reason)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (user_id, note_id)
+                DO UPDATE SET is_upvote = EXCLUDED.is_upvote, downvote_reason = EXCLUDED.downvote_reason
+                RETURNING id, user_id, note_id, is_upvote, created_at, downvote_reason
+                "#,
                 user_id,
                 note_id,
-                is_upvote
+                is_upvote,
+                downvote_reason
             )
             .fetch_one(&mut *tx)
             .await?;
-            return_value = Some(new_vote);
+            Some(new_vote)
         }
-    }
-    // No else arm since we have already deleted any
+    };
+
+    refresh_wilson_score(&mut tx, note_id).await?;
+    refresh_leaderboard_stats(&mut tx, db_wrapper.scorer(), Some(&[uploader_id])).await?;
 
     tx.commit().await?;
 
-    Ok(return_value)
+    let upvoted = vote.as_ref().is_some_and(|vote| vote.is_upvote) && was_upvote != Some(true);
+    Ok(VoteOutcome { vote, upvoted })
+}
+
+/// Every vote a user has cast, newest first
+pub async fn get_votes_by_user(
+    db_wrapper: &DBPoolWrapper,
+    user_id: Uuid,
+) -> Result<Vec<DBVote>, sqlx::Error> {
+    let votes = sqlx::query_as!(
+        DBVote,
+        r#"
+        SELECT id, user_id, note_id, is_upvote, created_at, downvote_reason
+        FROM votes
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(votes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_votes_scores_zero() {
+        assert_eq!(wilson_lower_bound(0, 0), 0.0);
+    }
+
+    #[test]
+    fn stays_below_the_plain_ratio() {
+        for (up, down) in [(1, 0), (5, 5), (40, 2), (1000, 10)] {
+            let score = wilson_lower_bound(up, down);
+            let ratio = up as f64 / (up + down) as f64;
+            assert!((0.0..ratio).contains(&score), "{} up, {} down scored {}", up, down, score);
+        }
+        assert_eq!(wilson_lower_bound(0, 10), 0.0);
+    }
+
+    #[test]
+    fn favours_notes_with_more_votes() {
+        assert!(wilson_lower_bound(40, 2) > wilson_lower_bound(1, 0));
+        assert!(wilson_lower_bound(100, 0) > wilson_lower_bound(10, 0));
+        assert!(wilson_lower_bound(10, 1) > wilson_lower_bound(10, 5));
+        // 1 up and 0 down at 95% confidence
+        assert!((wilson_lower_bound(1, 0) - 0.2065).abs() < 1e-4);
+    }
 }
@@ -0,0 +1,354 @@
+use crate::db::db::DBPoolWrapper;
+use crate::db::models::{Webhook, WebhookAttempt, WebhookDelivery};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// The parts of a webhook admins can change
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookSettings {
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub description: Option<String>,
+    pub is_active: bool,
+}
+
+impl From<&Webhook> for WebhookSettings {
+    fn from(webhook: &Webhook) -> Self {
+        Self {
+            url: webhook.url.clone(),
+            event_types: webhook.event_types.clone(),
+            description: webhook.description.clone(),
+            is_active: webhook.is_active,
+        }
+    }
+}
+
+/// A delivery claimed by the worker, along with where to send it
+pub struct DueDelivery {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// How an attempt at a delivery went
+pub struct AttemptOutcome {
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+    pub delivered: bool,
+}
+
+pub async fn create_webhook(
+    db_wrapper: &DBPoolWrapper,
+    settings: &WebhookSettings,
+    secret: &str,
+    created_by: Uuid,
+) -> Result<Webhook, sqlx::Error> {
+    let webhook = sqlx::query_as!(
+        Webhook,
+        r#"
+        INSERT INTO webhooks (url, secret, event_types, description, is_active, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, url, event_types, description, is_active, created_by, created_at, updated_at
+        "#,
+        settings.url,
+        secret,
+        &settings.event_types,
+        settings.description,
+        settings.is_active,
+        created_by
+    )
+        .fetch_one(db_wrapper.pool())
+        .await?;
+
+    Ok(webhook)
+}
+
+pub async fn get_webhooks(db_wrapper: &DBPoolWrapper) -> Result<Vec<Webhook>, sqlx::Error> {
+    let webhooks = sqlx::query_as!(
+        Webhook,
+        r#"
+        SELECT id, url, event_types, description, is_active, created_by, created_at, updated_at
+        FROM webhooks
+        ORDER BY created_at
+        "#
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(webhooks)
+}
+
+pub async fn get_webhook(
+    db_wrapper: &DBPoolWrapper,
+    webhook_id: Uuid,
+) -> Result<Option<Webhook>, sqlx::Error> {
+    let webhook = sqlx::query_as!(
+        Webhook,
+        r#"
+        SELECT id, url, event_types, description, is_active, created_by, created_at, updated_at
+        FROM webhooks
+        WHERE id = $1
+        "#,
+        webhook_id
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(webhook)
+}
+
+pub async fn update_webhook(
+    db_wrapper: &DBPoolWrapper,
+    webhook_id: Uuid,
+    settings: &WebhookSettings,
+) -> Result<Option<Webhook>, sqlx::Error> {
+    let webhook = sqlx::query_as!(
+        Webhook,
+        r#"
+        UPDATE webhooks
+        SET url = $2,
+            event_types = $3,
+            description = $4,
+            is_active = $5,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, url, event_types, description, is_active, created_by, created_at, updated_at
+        "#,
+        webhook_id,
+        settings.url,
+        &settings.event_types,
+        settings.description,
+        settings.is_active
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(webhook)
+}
+
+/// Deletes a webhook along with its delivery log, returning whether it existed
+pub async fn delete_webhook(db_wrapper: &DBPoolWrapper, webhook_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM webhooks WHERE id = $1", webhook_id)
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Queues the event for every active webhook subscribed to its type
+pub async fn enqueue_deliveries(
+    db_wrapper: &DBPoolWrapper,
+    event_id: Uuid,
+    event_type: &str,
+    payload: &serde_json::Value,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload)
+        SELECT id, $1, $2::TEXT, $3
+        FROM webhooks
+        WHERE is_active AND $2::TEXT = ANY (event_types)
+        "#,
+        event_id,
+        event_type,
+        payload
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Claims up to `limit` pending deliveries that are due, oldest first, by pushing their next
+/// attempt `lease_seconds` into the future. A delivery whose worker dies is picked up again once
+/// the lease runs out. Deliveries to inactive webhooks wait until the webhook is reactivated.
+pub async fn claim_due_deliveries(
+    db_wrapper: &DBPoolWrapper,
+    limit: i64,
+    lease_seconds: i64,
+) -> Result<Vec<DueDelivery>, sqlx::Error> {
+    let deliveries = sqlx::query_as!(
+        DueDelivery,
+        r#"
+        WITH due AS (
+            SELECT d.id
+            FROM webhook_deliveries d
+            JOIN webhooks w ON w.id = d.webhook_id
+            WHERE d.status = 'pending' AND d.next_attempt_at <= NOW() AND w.is_active
+            ORDER BY d.next_attempt_at
+            LIMIT $1
+            FOR UPDATE OF d SKIP LOCKED
+        )
+        UPDATE webhook_deliveries d
+        SET next_attempt_at = NOW() + $2 * INTERVAL '1 second'
+        FROM due, webhooks w
+        WHERE d.id = due.id AND w.id = d.webhook_id
+        RETURNING d.id, d.event_id, d.event_type, d.payload, d.attempts, w.url, w.secret
+        "#,
+        limit,
+        lease_seconds as f64
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(deliveries)
+}
+
+/// Logs an attempt at a delivery. Undelivered events are retried at `retry_at`, unless this was
+/// attempt number `max_attempts`, after which the delivery is marked as failed.
+pub async fn record_attempt(
+    db_wrapper: &DBPoolWrapper,
+    delivery_id: Uuid,
+    outcome: &AttemptOutcome,
+    retry_at: DateTime<Utc>,
+    max_attempts: i32,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db_wrapper.pool().begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO webhook_attempts (delivery_id, response_status, error, duration_ms)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        delivery_id,
+        outcome.response_status,
+        outcome.error,
+        outcome.duration_ms
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE webhook_deliveries
+        SET attempts = attempts + 1,
+            status = CASE
+                WHEN $2 THEN 'delivered'
+                WHEN attempts + 1 >= $4 THEN 'failed'
+                ELSE 'pending'
+            END,
+            next_attempt_at = $3,
+            delivered_at = CASE WHEN $2 THEN NOW() END
+        WHERE id = $1
+        "#,
+        delivery_id,
+        outcome.delivered,
+        retry_at,
+        max_attempts
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// A page of a webhook's deliveries, most recent first, optionally only those with `status`
+pub async fn get_deliveries(
+    db_wrapper: &DBPoolWrapper,
+    webhook_id: Uuid,
+    status: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    let deliveries = sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        SELECT *
+        FROM webhook_deliveries
+        WHERE webhook_id = $1 AND ($2::TEXT IS NULL OR status = $2)
+        ORDER BY created_at DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        webhook_id,
+        status,
+        limit,
+        offset
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(deliveries)
+}
+
+pub async fn count_deliveries(
+    db_wrapper: &DBPoolWrapper,
+    webhook_id: Uuid,
+    status: Option<&str>,
+) -> Result<i64, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM webhook_deliveries
+        WHERE webhook_id = $1 AND ($2::TEXT IS NULL OR status = $2)
+        "#,
+        webhook_id,
+        status
+    )
+        .fetch_one(db_wrapper.pool())
+        .await?;
+
+    Ok(count)
+}
+
+/// Every attempt at the given deliveries, in the order they were made
+pub async fn get_attempts(
+    db_wrapper: &DBPoolWrapper,
+    delivery_ids: &[Uuid],
+) -> Result<Vec<WebhookAttempt>, sqlx::Error> {
+    let attempts = sqlx::query_as!(
+        WebhookAttempt,
+        "SELECT * FROM webhook_attempts WHERE delivery_id = ANY ($1) ORDER BY attempted_at",
+        delivery_ids
+    )
+        .fetch_all(db_wrapper.pool())
+        .await?;
+
+    Ok(attempts)
+}
+
+/// Queues a delivery to be attempted again right away. A failed delivery gets one more attempt.
+pub async fn retry_delivery(
+    db_wrapper: &DBPoolWrapper,
+    webhook_id: Uuid,
+    delivery_id: Uuid,
+) -> Result<Option<WebhookDelivery>, sqlx::Error> {
+    let delivery = sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        UPDATE webhook_deliveries
+        SET status = 'pending',
+            next_attempt_at = NOW()
+        WHERE id = $1 AND webhook_id = $2 AND status <> 'delivered'
+        RETURNING *
+        "#,
+        delivery_id,
+        webhook_id
+    )
+        .fetch_optional(db_wrapper.pool())
+        .await?;
+
+    Ok(delivery)
+}
+
+/// Deletes the deliveries that were no longer pending and created before `before`, along with
+/// their attempts. Returns the number of deliveries deleted.
+pub async fn delete_old_deliveries(
+    db_wrapper: &DBPoolWrapper,
+    before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM webhook_deliveries WHERE status <> 'pending' AND created_at < $1",
+        before
+    )
+        .execute(db_wrapper.pool())
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod db;
 pub mod models;
 pub(crate) mod handlers;
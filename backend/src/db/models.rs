@@ -9,6 +9,35 @@ pub struct DBVote {
     pub note_id: Uuid,
     pub is_upvote: bool,
     pub created_at: Option<DateTime<Utc>>,
+    pub downvote_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl From<String> for Role {
+    fn from(role: String) -> Self {
+        match role.as_str() {
+            "admin" => Role::Admin,
+            "moderator" => Role::Moderator,
+            _ => Role::User,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -19,6 +48,39 @@ pub struct User {
     pub full_name: String,
     pub created_at: DateTime<Utc>,
     pub picture: String,
+    #[sqlx(try_from = "String")]
+    pub role: Role,
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub banned_at: Option<DateTime<Utc>>,
+    pub sanction_reason: Option<String>,
+    pub sanctioned_by: Option<Uuid>,
+    pub notes_hidden: bool,
+    /// Set by the user, takes precedence over the name from Google
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub department: Option<String>,
+    pub graduation_year: Option<i32>,
+    pub show_initials_only: bool,
+    /// The name shown to other users, derived from the above by the database
+    pub public_name: String,
+    /// The picture shown to other users, the default one if only initials are shown
+    pub public_picture: String,
+}
+
+impl User {
+    /// Moderators and admins can act on notes, comments and reports of other users
+    pub fn is_moderator(&self) -> bool {
+        self.role >= Role::Moderator
+    }
+
+    pub fn is_banned(&self) -> bool {
+        self.banned_at.is_some()
+    }
+
+    /// Suspended (or banned) users can still browse, but cannot change anything
+    pub fn is_suspended(&self) -> bool {
+        self.is_banned() || self.suspended_until.is_some_and(|until| until > Utc::now())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -36,6 +98,7 @@ pub struct Note {
     pub downloads: i64,
     pub note_year: i64,
     pub note_semester: String,
+    pub is_anonymous: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,26 +111,208 @@ pub struct NoteWithUser {
     pub note_tags: Vec<String>,
     pub note_is_public: bool,
     pub note_has_preview_image: bool,
+    pub note_is_hidden: bool,
+    pub note_is_anonymous: bool,
     pub note_uploader_user_id: Uuid,
     pub note_created_at: DateTime<Utc>,
     pub note_upvote_count: i64,
     pub note_downvote_count: i64,
+    pub note_comment_count: i64,
+    pub note_wilson_score: f64,
     pub note_user_upvote: Option<bool>,
     pub note_downloads: i64,
     pub note_year: i64,
     pub note_semester: String,
     pub user_id: Uuid,
-    pub user_google_id: String,
-    pub user_email: String,
-    pub user_full_name: String,
-    pub user_created_at: DateTime<Utc>,
+    pub user_display_name: String,
+    pub user_picture: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, sqlx::FromRow)]
-pub struct Vote {
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct Comment {
     pub id: Uuid,
+    pub note_id: Uuid,
     pub user_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub deleted_by: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentWithUser {
+    pub comment_id: Uuid,
+    pub comment_note_id: Uuid,
+    pub comment_parent_id: Option<Uuid>,
+    pub comment_body: String,
+    pub comment_created_at: DateTime<Utc>,
+    pub comment_updated_at: Option<DateTime<Utc>>,
+    pub comment_deleted_at: Option<DateTime<Utc>>,
+    pub user_id: Uuid,
+    pub user_display_name: String,
+    pub user_picture: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct NoteReport {
+    pub id: Uuid,
     pub note_id: Uuid,
-    pub vote_type: String,
+    pub reporter_id: Uuid,
+    pub reason: String,
+    pub details: Option<String>,
+    pub status: String,
+    pub assigned_to: Option<Uuid>,
+    pub resolved_by: Option<Uuid>,
+    pub resolution_note: Option<String>,
     pub created_at: DateTime<Utc>,
-}
\ No newline at end of file
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportWithNote {
+    pub report_id: Uuid,
+    pub report_reason: String,
+    pub report_details: Option<String>,
+    pub report_status: String,
+    pub report_assigned_to: Option<Uuid>,
+    pub report_resolved_by: Option<Uuid>,
+    pub report_resolution_note: Option<String>,
+    pub report_created_at: DateTime<Utc>,
+    pub report_resolved_at: Option<DateTime<Utc>>,
+    pub reporter_id: Uuid,
+    pub reporter_full_name: String,
+    pub note_id: Uuid,
+    pub note_course_name: String,
+    pub note_course_code: String,
+    pub note_uploader_user_id: Uuid,
+    pub note_is_hidden: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<Uuid>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub request_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct VoteRing {
+    pub id: Uuid,
+    pub member_ids: Vec<Uuid>,
+    /// Upvotes cast by members on notes uploaded by other members
+    pub mutual_votes: i32,
+    /// All upvotes cast by members
+    pub total_votes: i32,
+    pub status: String,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub detected_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A note along with what is needed to package its file into a download
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct DownloadableNote {
+    pub id: Uuid,
+    pub course_code: String,
+    pub course_name: String,
+    pub description: Option<String>,
+    pub professor_names: Option<Vec<String>>,
+    pub note_year: i64,
+    pub note_semester: String,
+    pub created_at: DateTime<Utc>,
+    pub uploader_name: String,
+}
+
+/// A counted download of a note
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct DownloadEvent {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub ip_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct UserBadge {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// Slug of the badge definition in `crate::badges`
+    pub badge: String,
+    /// Empty unless the badge can be earned more than once
+    pub context: String,
+    pub awarded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    pub note_id: Uuid,
+    pub subject_id: Uuid,
+    pub course_code: String,
+    /// Number of events grouped into this notification
+    pub count: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct Follow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// `course`, `professor` or `user`
+    pub kind: String,
+    pub target: String,
+    pub followed_user_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A webhook, without its secret, which is only read by the delivery worker
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    /// `pending`, `delivered` or `failed`
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct WebhookAttempt {
+    pub id: Uuid,
+    pub delivery_id: Uuid,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+    pub attempted_at: DateTime<Utc>,
+}
@@ -1,6 +1,15 @@
 use std::path::PathBuf;
 use clap::Parser;
+use crate::mailer::{MailConfig, MailTransport, SmtpTls};
 use crate::pathutils::Paths;
+use crate::scoring::{ReputationFormula, ReputationWeights};
+
+/// Note file URLs in API responses do not outlive the longest share link, a week
+const MAX_FILE_URL_TTL_MINUTES: i64 = 7 * 24 * 60;
+/// Digests cover at most a year
+const MAX_DIGEST_PERIOD_DAYS: i64 = 366;
+/// The webhook delivery log is kept for at most ten years
+const MAX_WEBHOOK_RETENTION_DAYS: i64 = 3650;
 
 #[derive(Parser, Clone)]
 #[clap(name = "")]
@@ -33,16 +42,141 @@ pub struct EnvVars {
     #[arg(env, default_value = "https://static.metakgp.org")]
     /// The URL of the static files server (odin's vault)
     static_files_url: String,
+    #[arg(env, default_value = "https://cfmn-server.metakgp.org")]
+    /// The public URL of this server, used in links to note files
+    public_api_url: String,
     #[arg(env, default_value = "/app/static_files")]
     /// The path where static files are served from
     static_file_storage_location: PathBuf,
+    #[arg(env, default_value = "/app/notes")]
+    /// The private path under which uploaded notes are stored. It must not be inside the
+    /// `static_file_storage_location`, notes are only served through the API.
+    notes_storage_location: PathBuf,
     #[arg(env, default_value = "notes/uploaded")]
-    /// The path where uploaded notes are stored temporarily, relative to the `static_file_storage_location`
+    /// The path where uploaded notes are stored, relative to the `notes_storage_location`
     uploaded_notes_path: PathBuf,
     #[arg(env, default_value = "previews/uploaded")]
     /// The path where uploaded notes are stored temporarily, relative to the `static_file_storage_location`
     previews_path: PathBuf,
+    #[arg(env, default_value = "course_packs")]
+    /// The path where generated course packs are cached, relative to the `static_file_storage_location`
+    course_packs_path: PathBuf,
+    #[arg(env, default_value = "2048")]
+    /// Size in megabytes the course pack cache is kept under, the least recently downloaded
+    /// packs are removed first
+    pub course_pack_cache_mb: u64,
+    #[arg(env, default_value = "")]
+    /// Path to the pdfium library used to build course packs, the system library is used if empty
+    pub pdfium_library_path: String,
+
+
+    // Moderation
+    #[arg(env, default_value = "")]
+    /// Comma separated list of email addresses of users who are made admins when they sign in
+    admin_emails: String,
+    #[arg(env, default_value = "5")]
+    /// Number of open reports after which a note is automatically hidden
+    pub report_hide_threshold: i64,
+    #[arg(env, default_value = "60")]
+    /// How often, in minutes, to look for vote rings
+    pub vote_ring_interval_minutes: u64,
+    #[arg(env, default_value = "3")]
+    /// Minimum number of upvotes two users must have given each other to be linked in a vote ring
+    pub vote_ring_min_mutual_votes: i64,
+    #[arg(env, default_value = "0.6")]
+    /// Fraction of their upvotes a cluster of users must have given each other to be flagged
+    pub vote_ring_min_share: f64,
+
+    // Leaderboard
+    #[arg(env, default_value = "15")]
+    /// How often, in minutes, to refresh the leaderboard stats of every user
+    pub leaderboard_refresh_minutes: u64,
+    #[arg(env, default_value = "legacy")]
+    /// Formula used to compute reputation: `legacy` or `weighted`
+    reputation_formula: String,
+    #[arg(env, default_value = "2.0")]
+    /// Weight of each note in the `weighted` reputation formula
+    reputation_note_weight: f64,
+    #[arg(env, default_value = "5.0")]
+    /// Weight of each upvote in the `weighted` reputation formula
+    reputation_upvote_weight: f64,
+    #[arg(env, default_value = "5.0")]
+    /// Weight of each downvote, subtracted, in the `weighted` reputation formula
+    reputation_downvote_weight: f64,
+    #[arg(env, default_value = "0.1")]
+    /// Weight of each download in the `weighted` reputation formula
+    reputation_download_weight: f64,
+
+    // Badges
+    #[arg(env, default_value = "60")]
+    /// How often, in minutes, to check for and award newly earned badges
+    pub badge_interval_minutes: u64,
+
+    // Downloads
+    #[arg(env, default_value = "24")]
+    /// Repeat downloads of a note by the same user or IP within this many hours are counted once
+    download_dedup_window_hours: i64,
+    #[arg(env, default_value = "false", action = clap::ArgAction::Set)]
+    /// Whether to take the client IP from the X-Real-IP and X-Forwarded-For headers set by the
+    /// reverse proxy. Only enable this when the server cannot be reached directly.
+    pub trust_proxy_headers: bool,
 
+    // Email
+    #[arg(env, default_value = "log")]
+    /// How emails are sent: `smtp`, `file` to write them to `mail_outbox_path`, or `log` to only
+    /// log them
+    mail_transport: String,
+    #[arg(env, default_value = "CFMN <cfmn@metakgp.org>")]
+    /// Sender of every email
+    mail_from: String,
+    #[arg(env, default_value = "localhost")]
+    smtp_host: String,
+    #[arg(env, default_value = "1025")]
+    smtp_port: u16,
+    #[arg(env, default_value = "")]
+    /// Left empty to send without authenticating
+    smtp_username: String,
+    #[arg(env, default_value = "")]
+    smtp_password: String,
+    #[arg(env, default_value = "none")]
+    /// TLS used by the SMTP server: `none`, `starttls` or `tls`
+    smtp_tls: String,
+    #[arg(env, default_value = "/app/mail")]
+    /// Directory emails are written to by the `file` transport
+    mail_outbox_path: PathBuf,
+    #[arg(env, default_value = "https://cfmn.metakgp.org")]
+    /// The public URL of the frontend, used in links in emails
+    pub frontend_url: String,
+    #[arg(env, default_value = "60")]
+    /// How often, in minutes, to look for users who are due a digest
+    pub digest_interval_minutes: u64,
+    #[arg(env, default_value = "7")]
+    /// Number of days each digest covers, up to a year
+    digest_period_days: i64,
+    #[arg(env, default_value = "")]
+    /// Key used to sign unsubscribe links in emails, defaults to the signing secret
+    email_link_signing_key: String,
+
+    // Webhooks
+    #[arg(env, default_value = "10")]
+    /// How often, in seconds, to send queued webhook deliveries
+    pub webhook_interval_seconds: u64,
+    #[arg(env, default_value = "8")]
+    /// Attempts at a webhook delivery before it is marked as failed
+    pub webhook_max_attempts: i32,
+    #[arg(env, default_value = "10")]
+    /// How long to wait for a webhook endpoint to respond, in seconds
+    pub webhook_timeout_seconds: u64,
+    #[arg(env, default_value = "30")]
+    /// Days delivered and failed webhook deliveries are kept in the delivery log
+    webhook_retention_days: i64,
+
+    #[arg(env, default_value = "")]
+    /// Key used to sign note file URLs, defaults to the signing secret
+    file_url_signing_key: String,
+    #[arg(env, default_value = "60")]
+    /// How long the note file URLs in API responses stay valid, in minutes, up to a week
+    file_url_ttl_minutes: i64,
 
     #[arg(env, default_value = "/app/log")]
     /// Location where logs are stored
@@ -51,20 +185,189 @@ pub struct EnvVars {
     #[arg(skip)]
     /// All paths must be handled using this
     pub paths: Paths,
+    #[arg(skip)]
+    /// The reputation formula in use, built from the `reputation_*` variables
+    pub reputation: ReputationFormula,
+    #[arg(skip)]
+    /// Window in which repeat downloads are counted once, from `download_dedup_window_hours`
+    pub download_dedup_window: chrono::Duration,
+    #[arg(skip)]
+    /// Period each digest covers, from `digest_period_days`
+    pub digest_period: chrono::Duration,
+    #[arg(skip)]
+    /// How long finished webhook deliveries are kept, from `webhook_retention_days`
+    pub webhook_retention: chrono::Duration,
+    #[arg(skip)]
+    /// How emails are sent, built from the `mail_*` and `smtp_*` variables
+    pub mail: MailConfig,
 }
 
 impl EnvVars {
     /// Processes the environment variables after reading, initializing the Paths struct.
     pub fn process(mut self) -> Result<Self, color_eyre::eyre::Error> {
+        if self.report_hide_threshold < 1 {
+            return Err(color_eyre::eyre::eyre!(
+                "REPORT_HIDE_THRESHOLD must be at least 1, got {}",
+                self.report_hide_threshold
+            ));
+        }
+        if self.vote_ring_min_mutual_votes < 1 {
+            return Err(color_eyre::eyre::eyre!(
+                "VOTE_RING_MIN_MUTUAL_VOTES must be at least 1, got {}",
+                self.vote_ring_min_mutual_votes
+            ));
+        }
+        // The range check also rejects NaN
+        if !(0.0..=1.0).contains(&self.vote_ring_min_share) {
+            return Err(color_eyre::eyre::eyre!(
+                "VOTE_RING_MIN_SHARE must be between 0 and 1, got {}",
+                self.vote_ring_min_share
+            ));
+        }
+        check_interval("VOTE_RING_INTERVAL_MINUTES", self.vote_ring_interval_minutes)?;
+        check_interval("LEADERBOARD_REFRESH_MINUTES", self.leaderboard_refresh_minutes)?;
+        check_interval("BADGE_INTERVAL_MINUTES", self.badge_interval_minutes)?;
+        check_interval("DIGEST_INTERVAL_MINUTES", self.digest_interval_minutes)?;
+        check_interval("WEBHOOK_INTERVAL_SECONDS", self.webhook_interval_seconds)?;
+        check_interval("WEBHOOK_TIMEOUT_SECONDS", self.webhook_timeout_seconds)?;
+        if self.webhook_max_attempts < 1 {
+            return Err(color_eyre::eyre::eyre!(
+                "WEBHOOK_MAX_ATTEMPTS must be at least 1, got {}",
+                self.webhook_max_attempts
+            ));
+        }
+
+        self.download_dedup_window = chrono::Duration::try_hours(self.download_dedup_window_hours)
+            .filter(|window| *window >= chrono::Duration::zero())
+            .ok_or_else(|| {
+                color_eyre::eyre::eyre!(
+                    "DOWNLOAD_DEDUP_WINDOW_HOURS must be a number of hours from 0 up, got {}",
+                    self.download_dedup_window_hours
+                )
+            })?;
+
+        self.digest_period = chrono::Duration::try_days(self.digest_period_days)
+            .filter(|_| (1..=MAX_DIGEST_PERIOD_DAYS).contains(&self.digest_period_days))
+            .ok_or_else(|| {
+                color_eyre::eyre::eyre!(
+                    "DIGEST_PERIOD_DAYS must be between 1 and {}, got {}",
+                    MAX_DIGEST_PERIOD_DAYS,
+                    self.digest_period_days
+                )
+            })?;
+
+        self.webhook_retention = chrono::Duration::try_days(self.webhook_retention_days)
+            .filter(|_| (1..=MAX_WEBHOOK_RETENTION_DAYS).contains(&self.webhook_retention_days))
+            .ok_or_else(|| {
+                color_eyre::eyre::eyre!(
+                    "WEBHOOK_RETENTION_DAYS must be between 1 and {}, got {}",
+                    MAX_WEBHOOK_RETENTION_DAYS,
+                    self.webhook_retention_days
+                )
+            })?;
+
+        let file_url_ttl = chrono::Duration::try_minutes(self.file_url_ttl_minutes)
+            .filter(|_| (1..=MAX_FILE_URL_TTL_MINUTES).contains(&self.file_url_ttl_minutes))
+            .ok_or_else(|| {
+                color_eyre::eyre::eyre!(
+                    "FILE_URL_TTL_MINUTES must be between 1 and {}, got {}",
+                    MAX_FILE_URL_TTL_MINUTES,
+                    self.file_url_ttl_minutes
+                )
+            })?;
         self.paths = Paths::new(
             &self.static_files_url,
+            &self.public_api_url,
             &self.static_file_storage_location,
+            &self.notes_storage_location,
             &self.uploaded_notes_path,
             &self.previews_path,
-        )?;
+            &self.course_packs_path,
+        )?
+        .with_note_url_signing(
+            if self.file_url_signing_key.is_empty() {
+                self.signing_secret.as_bytes()
+            } else {
+                self.file_url_signing_key.as_bytes()
+            },
+            file_url_ttl,
+        )
+        .with_email_link_signing(if self.email_link_signing_key.is_empty() {
+            self.signing_secret.as_bytes()
+        } else {
+            self.email_link_signing_key.as_bytes()
+        });
+
+        self.reputation = match self.reputation_formula.as_str() {
+            "legacy" => ReputationFormula::Legacy,
+            "weighted" => {
+                let weights = ReputationWeights {
+                    note: self.reputation_note_weight,
+                    upvote: self.reputation_upvote_weight,
+                    downvote: self.reputation_downvote_weight,
+                    download: self.reputation_download_weight,
+                };
+                weights.validate().map_err(|err| color_eyre::eyre::eyre!(err))?;
+                ReputationFormula::Weighted(weights)
+            }
+            other => {
+                return Err(color_eyre::eyre::eyre!(
+                    "Unknown reputation formula: {}. Available options are: legacy and weighted",
+                    other
+                ))
+            }
+        };
+
+        let transport = match self.mail_transport.as_str() {
+            "smtp" => MailTransport::Smtp {
+                host: self.smtp_host.clone(),
+                port: self.smtp_port,
+                tls: match self.smtp_tls.as_str() {
+                    "none" => SmtpTls::None,
+                    "starttls" => SmtpTls::StartTls,
+                    "tls" => SmtpTls::Tls,
+                    other => {
+                        return Err(color_eyre::eyre::eyre!(
+                            "Unknown SMTP TLS mode: {}. Available options are: none, starttls and tls",
+                            other
+                        ))
+                    }
+                },
+                credentials: (!self.smtp_username.is_empty())
+                    .then(|| (self.smtp_username.clone(), self.smtp_password.clone())),
+            },
+            "file" => MailTransport::File(std::path::absolute(&self.mail_outbox_path)?),
+            "log" => MailTransport::Log,
+            other => {
+                return Err(color_eyre::eyre::eyre!(
+                    "Unknown mail transport: {}. Available options are: smtp, file and log",
+                    other
+                ))
+            }
+        };
+        self.mail = MailConfig {
+            transport,
+            from: self.mail_from.parse()?,
+        };
 
         self.log_location = std::path::absolute(self.log_location)?;
 
         Ok(self)
     }
-}
\ No newline at end of file
+
+    /// Checks whether the given email belongs to one of the configured admins
+    pub fn is_admin_email(&self, email: &str) -> bool {
+        self.admin_emails
+            .split(',')
+            .map(|s| s.trim())
+            .any(|admin| !admin.is_empty() && admin.eq_ignore_ascii_case(email))
+    }
+}
+
+/// Background jobs cannot run every 0 minutes or seconds, `tokio::time::interval` panics on it
+fn check_interval(name: &str, value: u64) -> Result<(), color_eyre::eyre::Error> {
+    if value == 0 {
+        return Err(color_eyre::eyre::eyre!("{} must be greater than 0", name));
+    }
+    Ok(())
+}
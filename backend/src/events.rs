@@ -0,0 +1,198 @@
+//! Domain events, raised by the API once the change they describe is committed.
+//!
+//! Handlers describe what happened and [`publish`] works out who needs to hear about it, so
+//! that notifying users and queueing webhooks does not clutter the handlers. Like the audit log,
+//! failing to deliver an event is logged and never fails the action that raised it.
+
+use crate::db::handlers::comments::get_comment_by_id;
+use crate::db::handlers::notes::get_note_by_id;
+use crate::db::handlers::notifications::{get_note_subject, notify};
+use crate::db::handlers::webhooks::enqueue_deliveries;
+use crate::db::DBPoolWrapper;
+use crate::pathutils::Paths;
+use crate::webhooks::{event_types, Payload, WebhookNote};
+use chrono::Utc;
+use serde::Serialize;
+use uuid::Uuid;
+
+pub enum Event {
+    NoteCreated {
+        note_id: Uuid,
+    },
+    /// Raised once the note is gone, so it carries what is needed to describe it
+    NoteDeleted {
+        note_id: Uuid,
+        course_code: String,
+        course_name: String,
+    },
+    NoteReported {
+        note_id: Uuid,
+        report_id: Uuid,
+        reason: String,
+        /// Whether this report hid the note
+        auto_hidden: bool,
+    },
+    NoteUpvoted {
+        note_id: Uuid,
+    },
+    CommentAdded {
+        note_id: Uuid,
+        author_id: Uuid,
+        parent_id: Option<Uuid>,
+    },
+    /// Hidden automatically after reaching the report threshold
+    NoteAutoHidden {
+        note_id: Uuid,
+    },
+    /// A moderator upheld or dismissed the open reports on a note
+    ReportsClosed {
+        note_id: Uuid,
+        reporter_ids: Vec<Uuid>,
+        upheld: bool,
+    },
+}
+
+/// Kinds of notification, stored in `notifications.kind`
+pub mod kinds {
+    pub const NOTE_UPVOTED: &str = "note.upvoted";
+    pub const NOTE_COMMENTED: &str = "note.commented";
+    pub const COMMENT_REPLIED: &str = "comment.replied";
+    pub const NOTE_HIDDEN: &str = "note.hidden";
+    pub const REPORT_UPHELD: &str = "report.upheld";
+    pub const REPORT_DISMISSED: &str = "report.dismissed";
+}
+
+/// Publishes an event. `paths` is used to link to notes in webhook payloads.
+pub async fn publish(db_wrapper: &DBPoolWrapper, paths: &Paths, event: Event) {
+    if let Err(err) = queue_webhooks(db_wrapper, paths, &event).await {
+        tracing::error!("Failed to queue webhooks for event: {:?}", err);
+    }
+    if let Err(err) = deliver(db_wrapper, event).await {
+        tracing::error!("Failed to deliver event: {:?}", err);
+    }
+}
+
+/// Queues the event for the webhooks subscribed to it, if it is one webhooks can hear about
+async fn queue_webhooks(
+    db_wrapper: &DBPoolWrapper,
+    paths: &Paths,
+    event: &Event,
+) -> Result<(), sqlx::Error> {
+    let find_note = |note_id| async move {
+        match get_note_by_id(db_wrapper, note_id, None).await {
+            Ok(note) => Ok(Some(note)),
+            Err(sqlx::Error::RowNotFound) => Ok(None),
+            Err(err) => Err(err),
+        }
+    };
+
+    match event {
+        Event::NoteCreated { note_id } => {
+            // Notes that start out hidden, e.g. by users whose notes are hidden, are not announced
+            if let Some(note) = find_note(*note_id).await?.filter(|note| !note.note_is_hidden) {
+                let data = WebhookNote::new(note, paths);
+                queue_webhook(db_wrapper, event_types::NOTE_CREATED, data).await?;
+            }
+        }
+        Event::NoteDeleted { note_id, course_code, course_name } => {
+            let data = serde_json::json!({
+                "id": note_id,
+                "course_code": course_code,
+                "course_name": course_name,
+            });
+            queue_webhook(db_wrapper, event_types::NOTE_DELETED, data).await?;
+        }
+        Event::NoteReported { note_id, report_id, reason, auto_hidden } => {
+            if let Some(note) = find_note(*note_id).await? {
+                let data = serde_json::json!({
+                    "report_id": report_id,
+                    "reason": reason,
+                    "auto_hidden": auto_hidden,
+                    "note": WebhookNote::new(note, paths),
+                });
+                queue_webhook(db_wrapper, event_types::NOTE_REPORTED, data).await?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+async fn queue_webhook<T: Serialize>(
+    db_wrapper: &DBPoolWrapper,
+    event_type: &str,
+    data: T,
+) -> Result<(), sqlx::Error> {
+    let event_id = Uuid::new_v4();
+    let payload = Payload {
+        id: event_id,
+        event_type,
+        created_at: Utc::now(),
+        data,
+    };
+    let payload = serde_json::to_value(&payload).map_err(|err| sqlx::Error::Encode(err.into()))?;
+    enqueue_deliveries(db_wrapper, event_id, event_type, &payload).await?;
+    Ok(())
+}
+
+async fn deliver(db_wrapper: &DBPoolWrapper, event: Event) -> Result<(), sqlx::Error> {
+    let note_id = match &event {
+        // Only of interest to webhooks
+        Event::NoteCreated { .. } | Event::NoteDeleted { .. } | Event::NoteReported { .. } => {
+            return Ok(());
+        }
+        Event::NoteUpvoted { note_id }
+        | Event::CommentAdded { note_id, .. }
+        | Event::NoteAutoHidden { note_id }
+        | Event::ReportsClosed { note_id, .. } => *note_id,
+    };
+    // The note may have been deleted in the meantime
+    let Some(note) = get_note_subject(db_wrapper, note_id).await? else {
+        return Ok(());
+    };
+    let uploader = [note.uploader_user_id];
+
+    match event {
+        Event::NoteUpvoted { note_id } => {
+            notify(db_wrapper, &uploader, kinds::NOTE_UPVOTED, note_id, note_id, &note.course_code)
+                .await?;
+        }
+        Event::CommentAdded { note_id, author_id, parent_id } => {
+            let parent_author = match parent_id {
+                Some(parent_id) => get_comment_by_id(db_wrapper, parent_id)
+                    .await?
+                    .map(|parent| (parent.id, parent.user_id))
+                    .filter(|(_, user_id)| *user_id != author_id),
+                None => None,
+            };
+
+            // Uploaders replied to on their own note hear about the reply only
+            if let Some((parent_id, parent_author)) = parent_author {
+                notify(db_wrapper, &[parent_author], kinds::COMMENT_REPLIED, note_id, parent_id, &note.course_code)
+                    .await?;
+            }
+            if note.uploader_user_id != author_id
+                && parent_author.is_none_or(|(_, user_id)| user_id != note.uploader_user_id)
+            {
+                notify(db_wrapper, &uploader, kinds::NOTE_COMMENTED, note_id, note_id, &note.course_code)
+                    .await?;
+            }
+        }
+        Event::NoteAutoHidden { note_id } => {
+            notify(db_wrapper, &uploader, kinds::NOTE_HIDDEN, note_id, note_id, &note.course_code)
+                .await?;
+        }
+        Event::ReportsClosed { note_id, reporter_ids, upheld } => {
+            let kind = if upheld { kinds::REPORT_UPHELD } else { kinds::REPORT_DISMISSED };
+            notify(db_wrapper, &reporter_ids, kind, note_id, note_id, &note.course_code).await?;
+            if upheld {
+                notify(db_wrapper, &uploader, kinds::NOTE_HIDDEN, note_id, note_id, &note.course_code)
+                    .await?;
+            }
+        }
+        Event::NoteCreated { .. } | Event::NoteDeleted { .. } | Event::NoteReported { .. } => {}
+    }
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! Awards badges to users who have earned them, see `crate::badges` for the rules.
+
+use crate::badges::{BadgeRule, BADGES};
+use crate::db::handlers::badges::{award_badge, find_badge_candidates, is_badge_awarded};
+use crate::db::handlers::leaderboard::Semester;
+use crate::db::DBPoolWrapper;
+use chrono::Local;
+
+pub async fn award_badges(db_wrapper: &DBPoolWrapper) -> color_eyre::Result<()> {
+    // Semester rankings are only final once the semester is over
+    let last_semester = Semester::containing(Local::now().date_naive()).previous();
+
+    for badge in BADGES {
+        let context = match badge.rule {
+            BadgeRule::SemesterTop(_) => last_semester.label(),
+            _ => String::new(),
+        };
+        // A semester's ranking is awarded once, the first time the job runs after it ends
+        if matches!(badge.rule, BadgeRule::SemesterTop(_))
+            && is_badge_awarded(db_wrapper, badge.slug, &context).await?
+        {
+            continue;
+        }
+
+        let candidates = find_badge_candidates(db_wrapper, badge.rule, last_semester).await?;
+        if candidates.is_empty() {
+            continue;
+        }
+
+        let awarded = award_badge(db_wrapper, badge.slug, &context, &candidates).await?;
+        if !awarded.is_empty() {
+            tracing::info!("Awarded badge {} to {} user(s)", badge.slug, awarded.len());
+        }
+    }
+
+    Ok(())
+}
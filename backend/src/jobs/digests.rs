@@ -0,0 +1,102 @@
+//! Sends the weekly digest to every user who is due one, see `crate::mailer::digest`.
+
+use crate::db::handlers::email::{
+    get_digest_recipients, get_new_course_notes, get_upload_activity, mark_digest_sent,
+    DigestRecipient,
+};
+use crate::db::DBPoolWrapper;
+use crate::env::EnvVars;
+use crate::mailer::digest::Digest;
+use crate::mailer::Mailer;
+use chrono::Utc;
+use lettre::message::Mailbox;
+use uuid::Uuid;
+
+/// Recipients fetched at a time
+const DIGEST_BATCH_SIZE: i64 = 100;
+/// Most items shown in each section of a digest
+const MAX_DIGEST_ITEMS: i64 = 10;
+
+/// Builds and sends one user's digest, returning whether there was anything to send
+async fn send_digest(
+    db_wrapper: &DBPoolWrapper,
+    env_vars: &EnvVars,
+    mailer: &Mailer,
+    recipient: &DigestRecipient,
+) -> color_eyre::Result<bool> {
+    let notes = if recipient.course_notes {
+        get_new_course_notes(db_wrapper, recipient.user_id, recipient.since, MAX_DIGEST_ITEMS).await?
+    } else {
+        Vec::new()
+    };
+    let activity = if recipient.upload_activity {
+        get_upload_activity(db_wrapper, recipient.user_id, recipient.since, MAX_DIGEST_ITEMS).await?
+    } else {
+        Vec::new()
+    };
+
+    let digest = Digest {
+        name: recipient.name.clone(),
+        notes,
+        activity,
+        frontend_url: env_vars.frontend_url.trim_end_matches('/').to_string(),
+        unsubscribe_url: env_vars.paths.get_unsubscribe_url(recipient.user_id)?,
+    };
+    if digest.is_empty() {
+        return Ok(false);
+    }
+
+    let to = Mailbox::new(Some(recipient.name.clone()), recipient.email.parse()?);
+    mailer.send(digest.into_email(to)?).await?;
+    Ok(true)
+}
+
+pub async fn send_digests(
+    db_wrapper: &DBPoolWrapper,
+    env_vars: &EnvVars,
+    mailer: &Mailer,
+) -> color_eyre::Result<()> {
+    let now = Utc::now();
+    let due_before = now - env_vars.digest_period;
+    // Users whose digest failed are retried on the next run rather than in this one
+    let mut failed: Vec<Uuid> = Vec::new();
+    let mut sent = 0;
+
+    loop {
+        let recipients =
+            get_digest_recipients(db_wrapper, due_before, &failed, DIGEST_BATCH_SIZE).await?;
+        if recipients.is_empty() {
+            break;
+        }
+
+        for recipient in recipients {
+            match send_digest(db_wrapper, env_vars, mailer, &recipient).await {
+                Ok(was_sent) => {
+                    if was_sent {
+                        sent += 1;
+                    }
+                    // Left unmarked, the user would be fetched again in this run and sent the
+                    // same digest, so they are set aside until the next run
+                    if let Err(err) = mark_digest_sent(db_wrapper, recipient.user_id, now).await {
+                        tracing::error!(
+                            "Failed to record the digest sent to user {}: {:?}",
+                            recipient.user_id,
+                            err
+                        );
+                        failed.push(recipient.user_id);
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to send digest to user {}: {:?}", recipient.user_id, err);
+                    failed.push(recipient.user_id);
+                }
+            }
+        }
+    }
+
+    if sent > 0 || !failed.is_empty() {
+        tracing::info!("Sent {} digest(s), {} failed", sent, failed.len());
+    }
+
+    Ok(())
+}
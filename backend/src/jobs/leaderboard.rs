@@ -0,0 +1,16 @@
+//! Refreshes the leaderboard stats of every user.
+//!
+//! Stats are refreshed for the affected users whenever notes, votes or downloads change, this job
+//! picks up users who signed up since and anything changed outside of those paths.
+
+use crate::db::handlers::leaderboard::refresh_leaderboard_stats;
+use crate::db::DBPoolWrapper;
+
+pub async fn refresh_leaderboard(db_wrapper: &DBPoolWrapper) -> color_eyre::Result<()> {
+    let mut tx = db_wrapper.pool().begin().await?;
+    let refreshed = refresh_leaderboard_stats(&mut tx, db_wrapper.scorer(), None).await?;
+    tx.commit().await?;
+    tracing::debug!("Refreshed leaderboard stats of {} user(s)", refreshed);
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Background jobs that run periodically alongside the API server.
+
+mod badges;
+mod digests;
+mod leaderboard;
+mod vote_rings;
+mod webhooks;
+
+use crate::db::DBPoolWrapper;
+use crate::env::EnvVars;
+use crate::mailer::Mailer;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+/// How often old webhook deliveries are deleted
+const WEBHOOK_LOG_CLEANUP_MINUTES: u64 = 60;
+
+/// Starts every background job on the tokio runtime.
+pub fn spawn(db_wrapper: DBPoolWrapper, env_vars: EnvVars, mailer: Mailer) {
+    let config = vote_rings::VoteRingConfig {
+        min_mutual_votes: env_vars.vote_ring_min_mutual_votes,
+        min_share: env_vars.vote_ring_min_share,
+    };
+    {
+        let db_wrapper = db_wrapper.clone();
+        run_periodically(
+            "vote ring detection",
+            Duration::from_secs(env_vars.vote_ring_interval_minutes * 60),
+            move || {
+                let db_wrapper = db_wrapper.clone();
+                async move { vote_rings::detect_vote_rings(&db_wrapper, config).await }
+            },
+        );
+    }
+
+    {
+        let db_wrapper = db_wrapper.clone();
+        run_periodically(
+            "leaderboard refresh",
+            Duration::from_secs(env_vars.leaderboard_refresh_minutes * 60),
+            move || {
+                let db_wrapper = db_wrapper.clone();
+                async move { leaderboard::refresh_leaderboard(&db_wrapper).await }
+            },
+        );
+    }
+
+    {
+        let db_wrapper = db_wrapper.clone();
+        run_periodically(
+            "badge awards",
+            Duration::from_secs(env_vars.badge_interval_minutes * 60),
+            move || {
+                let db_wrapper = db_wrapper.clone();
+                async move { badges::award_badges(&db_wrapper).await }
+            },
+        );
+    }
+
+    match webhooks::WebhookSender::new(&env_vars) {
+        Ok(sender) => {
+            let db_wrapper = db_wrapper.clone();
+            run_periodically(
+                "webhook deliveries",
+                Duration::from_secs(env_vars.webhook_interval_seconds),
+                move || {
+                    let db_wrapper = db_wrapper.clone();
+                    let sender = sender.clone();
+                    async move { webhooks::send_webhooks(&db_wrapper, &sender).await }
+                },
+            );
+        }
+        Err(err) => tracing::error!("Webhooks will not be sent, failed to build the HTTP client: {:?}", err),
+    }
+
+    {
+        let db_wrapper = db_wrapper.clone();
+        let retention = env_vars.webhook_retention;
+        run_periodically(
+            "webhook log cleanup",
+            Duration::from_secs(WEBHOOK_LOG_CLEANUP_MINUTES * 60),
+            move || {
+                let db_wrapper = db_wrapper.clone();
+                async move { webhooks::prune_webhook_log(&db_wrapper, retention).await }
+            },
+        );
+    }
+
+    run_periodically(
+        "email digests",
+        Duration::from_secs(env_vars.digest_interval_minutes * 60),
+        move || {
+            let db_wrapper = db_wrapper.clone();
+            let env_vars = env_vars.clone();
+            let mailer = mailer.clone();
+            async move { digests::send_digests(&db_wrapper, &env_vars, &mailer).await }
+        },
+    );
+}
+
+/// Runs `job` every `period`, starting right away. Failures are logged and the job is retried on
+/// the next tick.
+fn run_periodically<F, Fut>(name: &'static str, period: Duration, job: F)
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = color_eyre::Result<()>> + Send,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            tracing::debug!("Running job: {}", name);
+            if let Err(err) = job().await {
+                tracing::error!("Job {} failed: {:?}", name, err);
+            }
+        }
+    });
+}
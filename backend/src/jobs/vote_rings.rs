@@ -0,0 +1,170 @@
+//! Looks for clusters of accounts that mostly upvote each other's notes.
+//!
+//! Two users are linked when each has upvoted the other's notes at least `min_mutual_votes`
+//! times. Linked users are grouped into clusters, and a cluster is flagged for moderators when
+//! at least `min_share` of all the upvotes its members have cast went to other members.
+
+use crate::db::handlers::leaderboard::refresh_leaderboard_stats;
+use crate::db::handlers::vote_rings::{
+    exclude_confirmed_ring_votes, get_upvote_edges, upsert_vote_ring,
+};
+use crate::db::DBPoolWrapper;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Clone, Copy)]
+pub struct VoteRingConfig {
+    pub min_mutual_votes: i64,
+    pub min_share: f64,
+}
+
+/// Minimal union-find over user ids
+#[derive(Default)]
+struct Clusters {
+    parent: HashMap<Uuid, Uuid>,
+}
+
+impl Clusters {
+    fn find(&mut self, id: Uuid) -> Uuid {
+        let parent = *self.parent.entry(id).or_insert(id);
+        if parent == id {
+            return id;
+        }
+        let root = self.find(parent);
+        self.parent.insert(id, root);
+        root
+    }
+
+    fn union(&mut self, a: Uuid, b: Uuid) {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+        if root_a != root_b {
+            self.parent.insert(root_a, root_b);
+        }
+    }
+
+    /// Every cluster with more than one member, each sorted
+    fn groups(mut self) -> Vec<Vec<Uuid>> {
+        let ids: Vec<Uuid> = self.parent.keys().copied().collect();
+        let mut groups: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        for id in ids {
+            let root = self.find(id);
+            groups.entry(root).or_default().push(id);
+        }
+
+        groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort();
+                group
+            })
+            .collect()
+    }
+}
+
+pub async fn detect_vote_rings(
+    db_wrapper: &DBPoolWrapper,
+    config: VoteRingConfig,
+) -> color_eyre::Result<()> {
+    let edges = get_upvote_edges(db_wrapper).await?;
+
+    let mut votes_between: HashMap<(Uuid, Uuid), i64> = HashMap::new();
+    let mut votes_cast: HashMap<Uuid, i64> = HashMap::new();
+    for edge in &edges {
+        votes_between.insert((edge.voter_id, edge.uploader_id), edge.votes);
+        *votes_cast.entry(edge.voter_id).or_default() += edge.votes;
+    }
+
+    let mut clusters = Clusters::default();
+    for (&(voter, uploader), &votes) in &votes_between {
+        let returned = votes_between.get(&(uploader, voter)).copied().unwrap_or(0);
+        if votes >= config.min_mutual_votes && returned >= config.min_mutual_votes {
+            clusters.union(voter, uploader);
+        }
+    }
+
+    let mut flagged = 0;
+    for members in clusters.groups() {
+        let member_set: HashSet<Uuid> = members.iter().copied().collect();
+        let mutual_votes: i64 = votes_between
+            .iter()
+            .filter(|((voter, uploader), _)| member_set.contains(voter) && member_set.contains(uploader))
+            .map(|(_, votes)| votes)
+            .sum();
+        let total_votes: i64 = members.iter().filter_map(|id| votes_cast.get(id)).sum();
+
+        if (mutual_votes as f64) < config.min_share * total_votes as f64 {
+            continue;
+        }
+
+        upsert_vote_ring(db_wrapper, &members, mutual_votes, total_votes).await?;
+        flagged += 1;
+    }
+
+    let excluded = exclude_confirmed_ring_votes(db_wrapper).await?;
+    if excluded > 0 {
+        let mut tx = db_wrapper.pool().begin().await?;
+        refresh_leaderboard_stats(&mut tx, db_wrapper.scorer(), None).await?;
+        tx.commit().await?;
+    }
+
+    tracing::info!(
+        "Vote ring detection flagged {} cluster(s), excluded {} new vote(s)",
+        flagged,
+        excluded
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(count: usize) -> Vec<Uuid> {
+        let mut ids: Vec<Uuid> = (0..count).map(|_| Uuid::new_v4()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn links_are_transitive() {
+        let users = ids(4);
+        let mut clusters = Clusters::default();
+        clusters.union(users[0], users[1]);
+        clusters.union(users[2], users[1]);
+        clusters.union(users[2], users[3]);
+
+        assert_eq!(clusters.groups(), vec![users]);
+    }
+
+    #[test]
+    fn separate_clusters_stay_apart() {
+        let users = ids(5);
+        let mut clusters = Clusters::default();
+        clusters.union(users[0], users[3]);
+        clusters.union(users[1], users[4]);
+        // Linking a pair again, or a user to themselves, changes nothing
+        clusters.union(users[3], users[0]);
+        clusters.union(users[2], users[2]);
+
+        let mut groups = clusters.groups();
+        groups.sort();
+        assert_eq!(groups, vec![vec![users[0], users[3]], vec![users[1], users[4]]]);
+    }
+
+    #[test]
+    fn merging_clusters_joins_every_member() {
+        let users = ids(6);
+        let mut clusters = Clusters::default();
+        clusters.union(users[0], users[1]);
+        clusters.union(users[1], users[2]);
+        clusters.union(users[3], users[4]);
+        clusters.union(users[4], users[5]);
+        assert_eq!(clusters.find(users[0]), clusters.find(users[2]));
+        assert_ne!(clusters.find(users[0]), clusters.find(users[3]));
+
+        clusters.union(users[2], users[5]);
+        assert_eq!(clusters.groups(), vec![users]);
+    }
+}
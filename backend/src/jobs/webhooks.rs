@@ -0,0 +1,133 @@
+//! Sends queued webhook deliveries, see `crate::webhooks`.
+
+use crate::db::handlers::webhooks::{
+    claim_due_deliveries, delete_old_deliveries, record_attempt, AttemptOutcome, DueDelivery,
+};
+use crate::db::DBPoolWrapper;
+use crate::env::EnvVars;
+use crate::webhooks::{signature, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER};
+use chrono::{Duration, Utc};
+use futures_util::future::join_all;
+use std::time::Instant;
+
+/// Deliveries claimed and sent at a time
+const WEBHOOK_BATCH_SIZE: i64 = 50;
+/// Delay before the first retry, doubled after every failed attempt
+const WEBHOOK_RETRY_BASE_SECONDS: i64 = 30;
+const WEBHOOK_RETRY_MAX_SECONDS: i64 = 6 * 60 * 60;
+/// How much of the response body of a failed attempt is kept in the delivery log
+const MAX_LOGGED_BODY_LENGTH: usize = 500;
+
+#[derive(Clone)]
+pub struct WebhookSender {
+    client: reqwest::Client,
+    /// Deliveries are claimed for a little longer than an attempt can take
+    lease_seconds: i64,
+    max_attempts: i32,
+}
+
+impl WebhookSender {
+    pub fn new(env_vars: &EnvVars) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(env_vars.webhook_timeout_seconds))
+            // A redirect could send the signed payload somewhere the admin did not register
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent("CFMN-Webhooks/1.0")
+            .build()?;
+
+        Ok(Self {
+            client,
+            lease_seconds: env_vars.webhook_timeout_seconds as i64 + 60,
+            max_attempts: env_vars.webhook_max_attempts,
+        })
+    }
+
+    async fn attempt(&self, delivery: &DueDelivery) -> AttemptOutcome {
+        let body = delivery.payload.to_string();
+        let started = Instant::now();
+        let result = self
+            .client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event_type)
+            .header(DELIVERY_HEADER, delivery.event_id.to_string())
+            .header(SIGNATURE_HEADER, signature(&delivery.secret, Utc::now().timestamp(), body.as_bytes()))
+            .body(body)
+            .send()
+            .await;
+
+        let (response_status, error, delivered) = match result {
+            Ok(response) => {
+                let status = response.status();
+                if status.is_success() {
+                    (Some(status.as_u16() as i32), None, true)
+                } else {
+                    let body = response.text().await.unwrap_or_default();
+                    let error = body.chars().take(MAX_LOGGED_BODY_LENGTH).collect::<String>();
+                    (Some(status.as_u16() as i32), Some(error), false)
+                }
+            }
+            Err(err) => (None, Some(err.to_string()), false),
+        };
+
+        AttemptOutcome {
+            response_status,
+            error,
+            duration_ms: started.elapsed().as_millis().min(i32::MAX as u128) as i32,
+            delivered,
+        }
+    }
+}
+
+/// When to retry a delivery that has failed `attempts` times
+fn retry_at(attempts: i32) -> chrono::DateTime<Utc> {
+    let delay = WEBHOOK_RETRY_BASE_SECONDS
+        .saturating_mul(1i64 << (attempts - 1).clamp(0, 20))
+        .min(WEBHOOK_RETRY_MAX_SECONDS);
+    Utc::now() + Duration::seconds(delay)
+}
+
+/// Sends every delivery that is due, a batch at a time
+pub async fn send_webhooks(db_wrapper: &DBPoolWrapper, sender: &WebhookSender) -> color_eyre::Result<()> {
+    loop {
+        let deliveries = claim_due_deliveries(db_wrapper, WEBHOOK_BATCH_SIZE, sender.lease_seconds).await?;
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+        let claimed = deliveries.len() as i64;
+
+        let outcomes = join_all(deliveries.iter().map(|delivery| sender.attempt(delivery))).await;
+        for (delivery, outcome) in deliveries.iter().zip(outcomes) {
+            if !outcome.delivered {
+                tracing::warn!(
+                    "Webhook delivery {} to {} failed (attempt {}): {}",
+                    delivery.id,
+                    delivery.url,
+                    delivery.attempts + 1,
+                    outcome.error.as_deref().unwrap_or("")
+                );
+            }
+            record_attempt(
+                db_wrapper,
+                delivery.id,
+                &outcome,
+                retry_at(delivery.attempts + 1),
+                sender.max_attempts,
+            )
+            .await?;
+        }
+
+        if claimed < WEBHOOK_BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+/// Deletes delivered and failed deliveries older than the retention period from the delivery log
+pub async fn prune_webhook_log(db_wrapper: &DBPoolWrapper, retention: Duration) -> color_eyre::Result<()> {
+    let deleted = delete_old_deliveries(db_wrapper, Utc::now() - retention).await?;
+    if deleted > 0 {
+        tracing::info!("Deleted {} old webhook deliveries", deleted);
+    }
+    Ok(())
+}
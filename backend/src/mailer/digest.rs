@@ -0,0 +1,43 @@
+//! The weekly digest email, rendered from `templates/emails/digest.{txt,html}`.
+
+use super::Email;
+use crate::db::handlers::email::{DigestNote, UploadActivity};
+use askama::Template;
+use lettre::message::Mailbox;
+
+pub struct Digest {
+    pub name: String,
+    pub notes: Vec<DigestNote>,
+    pub activity: Vec<UploadActivity>,
+    pub frontend_url: String,
+    pub unsubscribe_url: String,
+}
+
+#[derive(Template)]
+#[template(path = "emails/digest.txt")]
+struct DigestText<'a> {
+    digest: &'a Digest,
+}
+
+#[derive(Template)]
+#[template(path = "emails/digest.html")]
+struct DigestHtml<'a> {
+    digest: &'a Digest,
+}
+
+impl Digest {
+    /// Digests with nothing in them are not sent
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty() && self.activity.is_empty()
+    }
+
+    pub fn into_email(self, to: Mailbox) -> Result<Email, askama::Error> {
+        Ok(Email {
+            to,
+            subject: "Your weekly CFMN digest".to_string(),
+            text: DigestText { digest: &self }.render()?,
+            html: DigestHtml { digest: &self }.render()?,
+            unsubscribe_url: Some(self.unsubscribe_url),
+        })
+    }
+}
@@ -0,0 +1,146 @@
+//! Sending emails.
+//!
+//! Emails go out over SMTP in production. In development they can be written to a directory as
+//! `.eml` files or only logged, or sent to a local SMTP stand-in such as MailHog
+//! (`SMTP_HOST=localhost`, `SMTP_PORT=1025`, `SMTP_TLS=none`).
+
+pub mod digest;
+
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy)]
+pub enum SmtpTls {
+    None,
+    StartTls,
+    /// TLS from the start of the connection, usually on port 465
+    Tls,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum MailTransport {
+    Smtp {
+        host: String,
+        port: u16,
+        tls: SmtpTls,
+        /// Username and password, if the server requires them
+        credentials: Option<(String, String)>,
+    },
+    /// Writes each email to the directory as an `.eml` file
+    File(PathBuf),
+    #[default]
+    Log,
+}
+
+/// How emails are sent, as set in the config
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    pub transport: MailTransport,
+    pub from: Mailbox,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            transport: MailTransport::default(),
+            from: "cfmn@metakgp.org"
+                .parse()
+                .expect("Default sender address should be valid"),
+        }
+    }
+}
+
+/// An email with a plain text and an HTML version of the same content
+pub struct Email {
+    pub to: Mailbox,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+    /// Link that unsubscribes the recipient, sent in the `List-Unsubscribe` header
+    pub unsubscribe_url: Option<String>,
+}
+
+#[derive(Clone)]
+enum Transport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    File(AsyncFileTransport<Tokio1Executor>),
+    Log,
+}
+
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Transport,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(config: &MailConfig) -> Result<Self, color_eyre::eyre::Error> {
+        let transport = match &config.transport {
+            MailTransport::Smtp { host, port, tls, credentials } => {
+                let builder = match tls {
+                    SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+                    SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+                    SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+                };
+                let builder = match credentials {
+                    Some((username, password)) => {
+                        builder.credentials(Credentials::new(username.clone(), password.clone()))
+                    }
+                    None => builder,
+                };
+                Transport::Smtp(builder.port(*port).build())
+            }
+            MailTransport::File(path) => {
+                std::fs::create_dir_all(path)?;
+                Transport::File(AsyncFileTransport::new(path))
+            }
+            MailTransport::Log => Transport::Log,
+        };
+
+        Ok(Self {
+            transport,
+            from: config.from.clone(),
+        })
+    }
+
+    pub async fn send(&self, email: Email) -> Result<(), color_eyre::eyre::Error> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .to(email.to)
+            .subject(email.subject);
+        if let Some(url) = email.unsubscribe_url {
+            builder = builder
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe"),
+                    format!("<{}>", url),
+                ))
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+                    "List-Unsubscribe=One-Click".to_string(),
+                ));
+        }
+        let message = builder.multipart(MultiPart::alternative_plain_html(email.text, email.html))?;
+
+        match &self.transport {
+            Transport::Smtp(transport) => {
+                transport.send(message).await?;
+            }
+            Transport::File(transport) => {
+                transport.send(message).await?;
+            }
+            Transport::Log => {
+                tracing::info!(
+                    "Email not sent (log transport):\n{}",
+                    String::from_utf8_lossy(&message.formatted())
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
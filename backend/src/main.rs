@@ -1,11 +1,18 @@
 // backend/src/main.rs
 mod api;
+mod badges;
 mod db;
 mod env;
+mod events;
+mod jobs;
+mod mailer;
 mod pathutils;
+mod scoring;
+mod webhooks;
 
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use std::net::SocketAddr;
 use axum::extract::DefaultBodyLimit;
 use clap::Parser;
@@ -38,10 +45,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     tracing::subscriber::set_global_default(subscriber)?;
 
+    // Notes used to be stored under the public static files root
+    let moved_notes = env_vars.paths.move_public_notes()?;
+    if moved_notes > 0 {
+        tracing::info!("Moved {} notes out of the public static files directory.", moved_notes);
+    }
 
     let db_wrapper = db::DBPoolWrapper::new(env_vars.clone()).await;
     tracing::info!("Database connection established.");
 
+    let mailer = mailer::Mailer::new(&env_vars.mail)?;
+    jobs::spawn(db_wrapper.clone(), env_vars.clone(), mailer);
+
     // Liberal CORS setup for development - allow all origins, methods, and headers
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -55,13 +70,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let app = api::router::create_router(db_wrapper, env_vars)
         .layer(DefaultBodyLimit::max(file_size_limit * 1024 * 1024))
         .layer(RequestBodyLimitLayer::new( file_size_limit * 1024 * 1024))
-        .layer(cors);
+        .layer(cors)
+        // Every request gets an id (or keeps the one set by the proxy), used by the audit log
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     tracing::info!("Server listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app.into_make_service()).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
\ No newline at end of file
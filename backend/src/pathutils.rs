@@ -1,28 +1,49 @@
-/// Derived from https://github.com/metakgp/iqps-go
+//! Derived from https://github.com/metakgp/iqps-go
 
 use std::{
     fs,
     path::{self, Path, PathBuf},
 };
 
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use url::Url;
+use uuid::Uuid;
+
+/// How long unsubscribe links in emails stay valid
+const UNSUBSCRIBE_LINK_TTL_DAYS: i64 = 90;
 
 #[derive(Clone)]
 /// Struct containing all the paths and URLs required to parse or create any note's slug, absolute path, or URL.
 pub struct Paths {
     /// URL of the static files server
     static_files_url: Url,
+    /// Public URL of this API server, used for links to routes that serve files
+    api_url: Url,
+    /// Key used to sign note file URLs
+    note_url_key: Vec<u8>,
+    /// How long note file URLs stay valid unless asked otherwise
+    note_url_ttl: Duration,
+    /// Key used to sign links in emails, kept apart from the note URL key
+    email_link_key: Vec<u8>,
     /// The absolute path to the location from where the static files server serves files
+    #[allow(dead_code)]
     static_files_path: PathBuf,
-    /// The absolute system path to the notes directory on the server
+    /// The absolute system path to the notes directory on the server, outside the static files
+    /// root so that notes are only reachable through the API
     notes_system_path: PathBuf,
-    /// The slug to the notes directory
-    notes_path_slug: PathBuf,
+    /// Where notes used to be stored, under the static files root. Anything left there is moved
+    /// to `notes_system_path` on startup.
+    public_notes_system_path: PathBuf,
     /// The absolute system path to the previews directory on the server
     previews_system_path: PathBuf,
     /// The slug to the previews directory
     previews_path_slug: PathBuf,
+    /// The absolute system path to the directory where generated course packs are cached
+    course_packs_system_path: PathBuf,
 
+    #[allow(dead_code)]
     log_location: PathBuf,
 }
 
@@ -30,18 +51,26 @@ impl Default for Paths {
     /// Provides a default configuration for local development.
     fn default() -> Self {
         let static_file_storage_location = PathBuf::from("./static_files");
+        let notes_storage_location = PathBuf::from("./notes");
         let notes_path_slug = PathBuf::from("notes/uploaded");
         let previews_path_slug = PathBuf::from("notes/previews");
+        let course_packs_path_slug = PathBuf::from("course_packs");
         let log_location = PathBuf::from("./logs");
 
         Self {
             static_files_url: Url::parse("http://localhost:3000")
                 .expect("Default localhost URL should be valid"),
+            api_url: Url::parse("http://localhost:8085")
+                .expect("Default localhost URL should be valid"),
+            note_url_key: Vec::new(),
+            note_url_ttl: Duration::hours(1),
+            email_link_key: Vec::new(),
             static_files_path: static_file_storage_location.clone(),
-            notes_system_path: static_file_storage_location.join(&notes_path_slug),
-            notes_path_slug,
+            notes_system_path: notes_storage_location.join(&notes_path_slug),
+            public_notes_system_path: static_file_storage_location.join(&notes_path_slug),
             previews_system_path: static_file_storage_location.join(&previews_path_slug),
             previews_path_slug,
+            course_packs_system_path: static_file_storage_location.join(&course_packs_path_slug),
             log_location,
         }
     }
@@ -53,20 +82,32 @@ impl Paths {
     ///
     /// # Arguments
     /// * `static_files_url` - The static files server URL (e.g., "https://static.metakgp.org").
+    /// * `api_url` - The public URL of this server (e.g., "https://cfmn-server.metakgp.org").
     /// * `static_file_storage_location` - The absolute path from which static files are served.
-    /// * `notes_relative_path` - The path for notes, relative to the static storage location (e.g., "notes/uploaded").
+    /// * `notes_storage_location` - The private path under which notes are stored, it must not be served statically.
+    /// * `notes_relative_path` - The path for notes, relative to the notes storage location (e.g., "notes/uploaded").
     /// * `previews_relative_path` - The path for previews, relative to the static storage location (e.g., "notes/previews").
+    /// * `course_packs_relative_path` - The path for cached course packs, relative to the static storage location (e.g., "course_packs").
     pub fn new(
         static_files_url: &str,
+        api_url: &str,
         static_file_storage_location: &Path,
+        notes_storage_location: &Path,
         notes_relative_path: &Path,
         previews_relative_path: &Path,
+        course_packs_relative_path: &Path,
     ) -> Result<Self, color_eyre::eyre::Error> {
         let static_files_abs_path = path::absolute(static_file_storage_location)?;
 
         // --- Notes Paths ---
-        let notes_path_slug = notes_relative_path.to_owned();
-        let notes_system_path = static_files_abs_path.join(&notes_path_slug);
+        let notes_system_path = path::absolute(notes_storage_location)?.join(notes_relative_path);
+        let public_notes_system_path = static_files_abs_path.join(notes_relative_path);
+        if notes_system_path.starts_with(&static_files_abs_path) {
+            return Err(color_eyre::eyre::eyre!(
+                "Notes must not be stored under the static files root ({}), they would be served publicly",
+                static_files_abs_path.display()
+            ));
+        }
         if !notes_system_path.exists() {
             fs::create_dir_all(&notes_system_path)?;
         }
@@ -78,40 +119,159 @@ impl Paths {
             fs::create_dir_all(&previews_system_path)?;
         }
 
+        // --- Course Pack Paths ---
+        let course_packs_system_path = static_files_abs_path.join(course_packs_relative_path);
+        if !course_packs_system_path.exists() {
+            fs::create_dir_all(&course_packs_system_path)?;
+        }
+
         // --- Log Location ---
         let log_location = static_files_abs_path.join("logs");
 
         Ok(Self {
             static_files_url: Url::parse(static_files_url)?,
+            // Routes are joined onto the URL, which would replace its last segment were it not
+            // for a trailing slash
+            api_url: Url::parse(&format!("{}/", api_url.trim_end_matches('/')))?,
+            note_url_key: Vec::new(),
+            note_url_ttl: Duration::hours(1),
+            email_link_key: Vec::new(),
             static_files_path: static_files_abs_path,
             notes_system_path,
-            notes_path_slug,
+            public_notes_system_path,
             previews_system_path,
             previews_path_slug,
+            course_packs_system_path,
             log_location,
         })
     }
 
-    // --- Note Functions ---
+    /// Sets the key used to sign note file URLs and how long the URLs stay valid by default.
+    pub fn with_note_url_signing(mut self, key: &[u8], ttl: Duration) -> Self {
+        self.note_url_key = key.to_vec();
+        self.note_url_ttl = ttl;
+        self
+    }
 
-    pub fn get_note_slug(&self, filename: &str) -> String {
-        self.notes_path_slug
-            .join(filename)
-            .to_string_lossy()
-            .to_string()
+    /// Sets the key used to sign links in emails.
+    pub fn with_email_link_signing(mut self, key: &[u8]) -> Self {
+        self.email_link_key = key.to_vec();
+        self
     }
 
+    // --- Note Functions ---
+
     pub fn get_note_path(&self, filename: &str) -> PathBuf {
         self.notes_system_path.join(filename)
     }
 
-    pub fn get_note_url(&self, filename: &str) -> Result<String, color_eyre::eyre::Error> {
-        let slug = self.get_note_slug(filename);
-        self.get_url_from_slug(&slug)
+    /// Moves notes left in the old public notes directory into the private one. Returns the
+    /// number of files moved. Only meant to be run once on startup, before serving requests.
+    pub fn move_public_notes(&self) -> std::io::Result<usize> {
+        if !self.public_notes_system_path.is_dir() {
+            return Ok(0);
+        }
+
+        let mut moved = 0;
+        for entry in fs::read_dir(&self.public_notes_system_path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let destination = self.notes_system_path.join(entry.file_name());
+            // The two directories are usually on different volumes, where a rename fails
+            if fs::rename(entry.path(), &destination).is_err() {
+                fs::copy(entry.path(), &destination)?;
+                fs::remove_file(entry.path())?;
+            }
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    /// Notes are not served statically, the URL points to the API route that serves the file
+    /// after checking visibility and counting the download. The URL is signed and expires after
+    /// the default TTL.
+    pub fn get_note_url(&self, note_id: Uuid) -> Result<String, color_eyre::eyre::Error> {
+        self.get_signed_note_url(note_id, Utc::now() + self.note_url_ttl)
+    }
+
+    /// Signed URL to a note's file that is valid until `expires_at`.
+    pub fn get_signed_note_url(
+        &self,
+        note_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<String, color_eyre::eyre::Error> {
+        let expires = expires_at.timestamp();
+        let signature = hex::encode(self.note_url_mac(note_id, expires).finalize().into_bytes());
+
+        let mut url = self.api_url.join(&format!("api/notes/{}/file", note_id))?;
+        url.query_pairs_mut()
+            .append_pair("expires", &expires.to_string())
+            .append_pair("sig", &signature);
+        Ok(url.as_str().to_string())
+    }
+
+    /// Checks the signature of a note file URL. Expiry must be checked separately.
+    pub fn verify_note_url(&self, note_id: Uuid, expires: i64, signature: &str) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        self.note_url_mac(note_id, expires)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+
+    fn note_url_mac(&self, note_id: Uuid, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.note_url_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(format!("{}:{}", note_id, expires).as_bytes());
+        mac
+    }
+
+    // --- Email Functions ---
+
+    /// Link in emails that turns off the user's digest without signing in. It is signed with the
+    /// email link key and expires after `UNSUBSCRIBE_LINK_TTL_DAYS`.
+    pub fn get_unsubscribe_url(&self, user_id: Uuid) -> Result<String, color_eyre::eyre::Error> {
+        let expires = (Utc::now() + Duration::days(UNSUBSCRIBE_LINK_TTL_DAYS)).timestamp();
+        let signature = hex::encode(self.unsubscribe_mac(user_id, expires).finalize().into_bytes());
+
+        let mut url = self.api_url.join("api/email/unsubscribe")?;
+        url.query_pairs_mut()
+            .append_pair("user", &user_id.to_string())
+            .append_pair("expires", &expires.to_string())
+            .append_pair("sig", &signature);
+        Ok(url.as_str().to_string())
+    }
+
+    /// Checks the signature of an unsubscribe link. Expiry must be checked separately.
+    pub fn verify_unsubscribe(&self, user_id: Uuid, expires: i64, signature: &str) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        self.unsubscribe_mac(user_id, expires)
+            .verify_slice(&signature)
+            .is_ok()
     }
 
-    pub fn get_notes_dir(&self) -> &Path {
-        &self.notes_system_path
+    fn unsubscribe_mac(&self, user_id: Uuid, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.email_link_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(format!("unsubscribe:{}:{}", user_id, expires).as_bytes());
+        mac
+    }
+
+    /// Public URL of the Atom feed at the given path segments under `/feeds`, e.g.
+    /// `["courses", "CS10001.atom"]`. Each segment is percent-encoded.
+    pub fn get_feed_url(&self, segments: &[String]) -> Result<String, color_eyre::eyre::Error> {
+        let mut url = self.api_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| color_eyre::eyre::eyre!("The API URL cannot have a path"))?
+            .pop_if_empty()
+            .push("feeds")
+            .extend(segments);
+        Ok(url.as_str().to_string())
     }
 
     // --- Preview Image Functions ---
@@ -136,9 +296,74 @@ impl Paths {
         &self.previews_system_path
     }
 
+    // --- Course Pack Functions ---
+
+    /// Path of the cached course pack with the given content hash
+    pub fn get_course_pack_path(&self, hash: &str) -> PathBuf {
+        self.course_packs_system_path.join(format!("{}.pdf", hash))
+    }
+
+    pub fn get_course_packs_dir(&self) -> &Path {
+        &self.course_packs_system_path
+    }
+
 
     pub fn get_url_from_slug(&self, slug: &str) -> Result<String, color_eyre::eyre::Error> {
         Ok(self.static_files_url.join(slug)?.as_str().to_string())
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits a signed note URL into its expiry and signature
+    fn signature_of(url: &str) -> (i64, String) {
+        let url = Url::parse(url).unwrap();
+        let param = |name: &str| {
+            url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned()).unwrap()
+        };
+        (param("expires").parse().unwrap(), param("sig"))
+    }
+
+    fn signing_paths(key: &[u8]) -> Paths {
+        Paths::default().with_note_url_signing(key, Duration::minutes(30))
+    }
+
+    #[test]
+    fn signed_note_urls_verify() {
+        let paths = signing_paths(b"key");
+        let note_id = Uuid::new_v4();
+        let expires_at = Utc::now() + Duration::hours(2);
+        let (expires, sig) = signature_of(&paths.get_signed_note_url(note_id, expires_at).unwrap());
+
+        assert_eq!(expires, expires_at.timestamp());
+        assert!(paths.verify_note_url(note_id, expires, &sig));
+    }
+
+    #[test]
+    fn default_urls_expire_after_the_ttl() {
+        let paths = signing_paths(b"key");
+        let before = Utc::now();
+        let (expires, _) = signature_of(&paths.get_note_url(Uuid::new_v4()).unwrap());
+        let after = Utc::now();
+
+        assert!(expires >= (before + Duration::minutes(30)).timestamp());
+        assert!(expires <= (after + Duration::minutes(30)).timestamp());
+    }
+
+    #[test]
+    fn altered_note_urls_do_not_verify() {
+        let paths = signing_paths(b"key");
+        let note_id = Uuid::new_v4();
+        let (expires, sig) =
+            signature_of(&paths.get_signed_note_url(note_id, Utc::now() + Duration::hours(1)).unwrap());
+
+        assert!(!paths.verify_note_url(Uuid::new_v4(), expires, &sig));
+        assert!(!paths.verify_note_url(note_id, expires + 1, &sig));
+        assert!(!paths.verify_note_url(note_id, expires, &sig[..sig.len() - 2]));
+        assert!(!paths.verify_note_url(note_id, expires, "not hex"));
+        assert!(!signing_paths(b"other key").verify_note_url(note_id, expires, &sig));
+    }
+}
@@ -0,0 +1,162 @@
+//! Reputation formulas used to rank uploaders on the leaderboard.
+//!
+//! Stats are counted in SQL and scored here, so a formula can be swapped through the config or
+//! previewed against the current stats without touching any query.
+
+use serde::{Deserialize, Serialize};
+
+/// What a user's reputation is computed from, within one leaderboard window
+#[derive(Debug, Clone, Copy)]
+pub struct ReputationStats {
+    pub notes: i64,
+    pub upvotes: i64,
+    pub downvotes: i64,
+    pub downloads: i64,
+}
+
+pub trait ReputationScorer: Send + Sync {
+    fn score(&self, stats: &ReputationStats) -> f64;
+}
+
+/// The original formula: `(upvotes / notes) * (notes + upvotes + downloads)`.
+///
+/// Averaging upvotes per note rewards a single well voted note over a body of work.
+pub struct LegacyScorer;
+
+impl ReputationScorer for LegacyScorer {
+    fn score(&self, stats: &ReputationStats) -> f64 {
+        if stats.notes == 0 {
+            return 0.0;
+        }
+        let (notes, upvotes, downloads) =
+            (stats.notes as f64, stats.upvotes as f64, stats.downloads as f64);
+        (upvotes / notes) * (notes + upvotes + downloads)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReputationWeights {
+    pub note: f64,
+    pub upvote: f64,
+    pub downvote: f64,
+    pub download: f64,
+}
+
+impl Default for ReputationWeights {
+    fn default() -> Self {
+        Self {
+            note: 2.0,
+            upvote: 5.0,
+            downvote: 5.0,
+            download: 0.1,
+        }
+    }
+}
+
+impl ReputationWeights {
+    /// Checks that every weight is a finite number that is not negative. Downvotes are
+    /// subtracted, so their weight is positive as well.
+    pub fn validate(&self) -> Result<(), String> {
+        let weights = [
+            ("note", self.note),
+            ("upvote", self.upvote),
+            ("downvote", self.downvote),
+            ("download", self.download),
+        ];
+        for (name, weight) in weights {
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(format!(
+                    "The {} weight must be a number of at least 0, got {}",
+                    name, weight
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A weighted sum of the stats, with downvotes counting against the user. Never negative.
+pub struct WeightedScorer {
+    pub weights: ReputationWeights,
+}
+
+impl ReputationScorer for WeightedScorer {
+    fn score(&self, stats: &ReputationStats) -> f64 {
+        let weights = &self.weights;
+        let score = weights.note * stats.notes as f64 + weights.upvote * stats.upvotes as f64
+            - weights.downvote * stats.downvotes as f64
+            + weights.download * stats.downloads as f64;
+        score.max(0.0)
+    }
+}
+
+/// A formula along with its parameters, as set in the config or proposed for a preview
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(tag = "formula", rename_all = "lowercase")]
+pub enum ReputationFormula {
+    #[default]
+    Legacy,
+    Weighted(ReputationWeights),
+}
+
+impl ReputationFormula {
+    pub fn scorer(&self) -> Box<dyn ReputationScorer> {
+        match self {
+            ReputationFormula::Legacy => Box::new(LegacyScorer),
+            ReputationFormula::Weighted(weights) => Box::new(WeightedScorer { weights: *weights }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(notes: i64, upvotes: i64, downvotes: i64, downloads: i64) -> ReputationStats {
+        ReputationStats { notes, upvotes, downvotes, downloads }
+    }
+
+    fn weighted() -> Box<dyn ReputationScorer> {
+        ReputationFormula::Weighted(ReputationWeights::default()).scorer()
+    }
+
+    #[test]
+    fn legacy_scores_upvotes_per_note() {
+        let scorer = ReputationFormula::Legacy.scorer();
+        assert_eq!(scorer.score(&stats(0, 0, 0, 0)), 0.0);
+        assert_eq!(scorer.score(&stats(0, 3, 0, 10)), 0.0);
+        assert_eq!(scorer.score(&stats(2, 4, 1, 10)), 2.0 * (2.0 + 4.0 + 10.0));
+    }
+
+    #[test]
+    fn weighted_sums_the_stats() {
+        // 2 * 3 notes + 5 * 4 upvotes - 5 * 1 downvote + 0.1 * 20 downloads
+        assert_eq!(weighted().score(&stats(3, 4, 1, 20)), 6.0 + 20.0 - 5.0 + 2.0);
+        assert_eq!(weighted().score(&stats(0, 0, 0, 0)), 0.0);
+    }
+
+    #[test]
+    fn weighted_is_never_negative() {
+        assert_eq!(weighted().score(&stats(1, 0, 10, 0)), 0.0);
+    }
+
+    #[test]
+    fn legacy_favours_one_voted_note_where_weighted_favours_a_body_of_work() {
+        let one_note = stats(1, 5, 0, 0);
+        let many_notes = stats(10, 6, 0, 0);
+
+        let legacy = ReputationFormula::Legacy.scorer();
+        assert!(legacy.score(&one_note) > legacy.score(&many_notes));
+        assert!(weighted().score(&one_note) < weighted().score(&many_notes));
+    }
+
+    #[test]
+    fn weights_must_be_finite_and_not_negative() {
+        assert!(ReputationWeights::default().validate().is_ok());
+        assert!(ReputationWeights { note: 0.0, ..Default::default() }.validate().is_ok());
+        assert!(ReputationWeights { upvote: f64::NAN, ..Default::default() }.validate().is_err());
+        assert!(ReputationWeights { download: f64::INFINITY, ..Default::default() }.validate().is_err());
+        assert!(ReputationWeights { downvote: -1.0, ..Default::default() }.validate().is_err());
+    }
+}
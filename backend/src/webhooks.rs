@@ -0,0 +1,112 @@
+//! Outgoing webhooks, registered by admins to hear about note events.
+//!
+//! Events are queued in `webhook_deliveries` when they are published and sent by a background job,
+//! so a slow or broken endpoint never holds up the API. Each delivery is a `POST` of
+//!
+//! ```json
+//! { "id": "<event id>", "type": "note.created", "created_at": "...", "data": { ... } }
+//! ```
+//!
+//! with the headers
+//! - `X-CFMN-Event`: the event type
+//! - `X-CFMN-Delivery`: the event id, the same across retries, to ignore repeat deliveries
+//! - `X-CFMN-Signature`: `t=<unix timestamp>,v1=<hex HMAC-SHA256 of "<timestamp>.<body>">`,
+//!   keyed with the webhook's secret. Receivers should also reject old timestamps.
+//!
+//! Any `2xx` response counts as delivered. Anything else is retried with exponential backoff.
+
+use crate::api::models::PublicUser;
+use crate::db::models::NoteWithUser;
+use crate::pathutils::Paths;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+pub const EVENT_HEADER: &str = "X-CFMN-Event";
+pub const DELIVERY_HEADER: &str = "X-CFMN-Delivery";
+pub const SIGNATURE_HEADER: &str = "X-CFMN-Signature";
+
+/// Types of event webhooks can subscribe to
+pub mod event_types {
+    pub const NOTE_CREATED: &str = "note.created";
+    pub const NOTE_DELETED: &str = "note.deleted";
+    pub const NOTE_REPORTED: &str = "note.reported";
+
+    pub const ALL: &[&str] = &[NOTE_CREATED, NOTE_DELETED, NOTE_REPORTED];
+}
+
+/// A note as sent in webhook payloads. The uploader of an anonymous note is not revealed.
+#[derive(Serialize)]
+pub struct WebhookNote {
+    pub id: Uuid,
+    pub course_code: String,
+    pub course_name: String,
+    pub description: Option<String>,
+    pub professor_names: Option<Vec<String>>,
+    pub tags: Vec<String>,
+    pub year: i64,
+    pub semester: String,
+    pub uploader: PublicUser,
+    pub preview_image_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookNote {
+    pub fn new(note: NoteWithUser, paths: &Paths) -> Self {
+        let uploader = if note.note_is_anonymous {
+            PublicUser::anonymous()
+        } else {
+            PublicUser {
+                id: Some(note.user_id),
+                display_name: note.user_display_name,
+                picture: note.user_picture,
+            }
+        };
+        let preview_image_url = if note.note_has_preview_image {
+            paths.get_preview_url(&format!("{}.jpg", note.note_id)).ok()
+        } else {
+            None
+        };
+
+        Self {
+            id: note.note_id,
+            course_code: note.note_course_code,
+            course_name: note.note_course_name,
+            description: note.note_description,
+            professor_names: note.note_professor_names,
+            tags: note.note_tags,
+            year: note.note_year,
+            semester: note.note_semester,
+            uploader,
+            preview_image_url,
+            created_at: note.note_created_at,
+        }
+    }
+}
+
+/// The body of every delivery
+#[derive(Serialize)]
+pub struct Payload<'a, T: Serialize> {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub event_type: &'a str,
+    pub created_at: DateTime<Utc>,
+    pub data: T,
+}
+
+/// The value of the signature header for `body` sent at `timestamp`
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Generates the secret a new webhook signs its payloads with
+pub fn generate_secret() -> String {
+    format!("whsec_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
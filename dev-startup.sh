@@ -194,16 +194,17 @@ create_directories() {
 
     # Default paths if not set in .env
     STATIC_STORAGE="${STATIC_FILE_STORAGE_LOCATION:-${HOME}/static}"
+    NOTES_STORAGE="${NOTES_STORAGE_LOCATION:-${HOME}/notes}"
     # Extract directory from LOG_LOCATION file path (e.g., /path/to/backend.log -> /path/to)
     LOG_FILE="${LOG_LOCATION:-${HOME}/log/backend.log}"
     LOG_DIR="$(dirname "${LOG_FILE}")"
 
     # Create directories
-    mkdir -p "${STATIC_STORAGE}/cfmn/notes/uploaded"
+    mkdir -p "${NOTES_STORAGE}/cfmn/notes/uploaded"
     mkdir -p "${STATIC_STORAGE}/cfmn/previews/uploaded"
     mkdir -p "${LOG_DIR}"
 
-    print_success "Created directory: ${STATIC_STORAGE}/cfmn/notes/uploaded"
+    print_success "Created directory: ${NOTES_STORAGE}/cfmn/notes/uploaded"
     print_success "Created directory: ${STATIC_STORAGE}/cfmn/previews/uploaded"
     print_success "Created directory: ${LOG_DIR}"
 }
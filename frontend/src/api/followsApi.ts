@@ -0,0 +1,52 @@
+import { authenticatedFetch } from "./authApi.ts";
+import type { PublicUser, ResponseNote } from "../types.ts";
+
+export type FollowKind = 'course' | 'professor' | 'user';
+
+export interface Follow {
+    kind: FollowKind;
+    target: string; // course code, professor name or user id
+    user: PublicUser | null; // set when following a user
+    created_at: string;
+}
+
+export interface FeedPage {
+    notes: ResponseNote[];
+    next_cursor: string | null; // null on the last page
+}
+
+class FollowsAPI {
+    private async fetchWithErrorHandling(url: string, options?: RequestInit): Promise<any> {
+        const response = await authenticatedFetch(url, options);
+        if (!response.ok) {
+            throw new Error(`HTTP error! status: ${response.status}`);
+        }
+        return response.status === 204 ? null : response.json();
+    }
+
+    // GET /api/follows
+    async getFollows(): Promise<Follow[]> {
+        return this.fetchWithErrorHandling('/api/follows');
+    }
+
+    // PUT /api/follows/:kind/:target
+    async follow(kind: FollowKind, target: string): Promise<Follow> {
+        return this.fetchWithErrorHandling(`/api/follows/${kind}/${encodeURIComponent(target)}`, { method: 'PUT' });
+    }
+
+    // DELETE /api/follows/:kind/:target
+    async unfollow(kind: FollowKind, target: string): Promise<void> {
+        await this.fetchWithErrorHandling(`/api/follows/${kind}/${encodeURIComponent(target)}`, { method: 'DELETE' });
+    }
+
+    // GET /api/feed?cursor=&limit=20
+    async getFeed(cursor?: string, limit: number = 20): Promise<FeedPage> {
+        const params = new URLSearchParams({ limit: String(limit) });
+        if (cursor) {
+            params.set('cursor', cursor);
+        }
+        return this.fetchWithErrorHandling(`/api/feed?${params}`);
+    }
+}
+
+export const followsApi = new FollowsAPI();
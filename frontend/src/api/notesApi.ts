@@ -1,4 +1,4 @@
-import type { ResponseNote, DBVote, VoteType } from "../types.ts";
+import type { ResponseNote, DBVote, VoteType, DownvoteReason } from "../types.ts";
 import { authenticatedFetch } from "./authApi.ts";
 
 class NotesAPI {
@@ -34,9 +34,9 @@ class NotesAPI {
         return this.fetchWithErrorHandling(url);
     }
 
-    // POST /api/notes/:note_id/vote?vote_type=type - Vote on a note
-    async voteOnNote(noteId: string, voteType: VoteType): Promise<DBVote | null> {
-        const url = `/api/notes/${noteId}/vote?vote_type=${voteType}`;
+    // POST /api/notes/:note_id/vote?vote_type=type&reason=reason - Vote on a note, downvotes need a reason
+    async voteOnNote(noteId: string, voteType: VoteType, reason?: DownvoteReason): Promise<DBVote | null> {
+        const url = `/api/notes/${noteId}/vote?vote_type=${voteType}${reason ? `&reason=${reason}` : ''}`;
 
         try {
             const response = await authenticatedFetch(url, {
@@ -148,9 +148,8 @@ class NotesAPI {
             throw new Error(`Failed to delete note: ${response.status}`);
         }
     }
+    // The file URL points at /api/notes/:note_id/file, which counts the download itself
     async downloadNote(note: ResponseNote): Promise<void> {
-        const url = `/api/notes/${note.id}/download`;
-        await this.fetchWithErrorHandling(url);
         window.open(note.file_url, '_blank');
     }
 }
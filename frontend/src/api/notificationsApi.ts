@@ -0,0 +1,54 @@
+import { authenticatedFetch } from "./authApi.ts";
+
+export interface Notification {
+    id: string;
+    kind: string;
+    note_id: string;
+    course_code: string;
+    count: number; // similar events are grouped into one notification
+    message: string;
+    is_read: boolean;
+    created_at: string;
+    updated_at: string;
+}
+
+export interface NotificationPage {
+    notifications: Notification[];
+    page: number;
+    per_page: number;
+    total: number;
+    unread: number;
+}
+
+class NotificationsAPI {
+    private async fetchWithErrorHandling(url: string, options?: RequestInit): Promise<any> {
+        const response = await authenticatedFetch(url, options);
+        if (!response.ok) {
+            throw new Error(`HTTP error! status: ${response.status}`);
+        }
+        return response.json();
+    }
+
+    // GET /api/notifications?page=1&per_page=20&unread_only=false
+    async getNotifications(page: number = 1, perPage: number = 20, unreadOnly: boolean = false): Promise<NotificationPage> {
+        return this.fetchWithErrorHandling(`/api/notifications?page=${page}&per_page=${perPage}&unread_only=${unreadOnly}`);
+    }
+
+    // GET /api/notifications/unread-count
+    async getUnreadCount(): Promise<number> {
+        const data = await this.fetchWithErrorHandling('/api/notifications/unread-count');
+        return data.unread;
+    }
+
+    // POST /api/notifications/read, marks every notification as read if no ids are given
+    async markRead(ids?: string[]): Promise<number> {
+        const data = await this.fetchWithErrorHandling('/api/notifications/read', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ ids }),
+        });
+        return data.read;
+    }
+}
+
+export const notificationsApi = new NotificationsAPI();
@@ -1,16 +1,54 @@
 import { authenticatedFetch } from "./authApi.ts";
+import type { AuthUser, Badge, PublicUser } from "../types.ts";
 
 export interface LeaderboardEntry {
     id: string;
-    full_name: string;
+    display_name: string;
     picture: string;
     reputation: number;
     total_notes: number;
     total_upvotes: number;
+    total_downvotes: number;
     total_downloads: number;
     rank: number;
+    badges: Badge[];
 }
 
+export interface UserProfile extends PublicUser {
+    bio: string | null;
+    department: string | null;
+    graduation_year: number | null;
+    member_since: string;
+    stats: {
+        reputation: number;
+        rank: number;
+        total_notes: number;
+        total_upvotes: number;
+        total_downvotes: number;
+        total_downloads: number;
+        courses_covered: number;
+        total_comments: number;
+    };
+    badges: Badge[];
+}
+
+// Fields left out are unchanged, null clears a field
+export interface ProfileUpdate {
+    display_name?: string | null;
+    bio?: string | null;
+    department?: string | null;
+    graduation_year?: number | null;
+    show_initials_only?: boolean;
+}
+
+export interface EmailPreferences {
+    weekly_digest: boolean;
+    course_notes: boolean;
+    upload_activity: boolean;
+}
+
+export type LeaderboardWindow = 'week' | 'month' | 'semester' | 'all';
+
 class UserAPI {
     private async fetchWithErrorHandling(url: string, options?: RequestInit): Promise<any> {
         try {
@@ -25,15 +63,57 @@ class UserAPI {
         }
     }
 
-    // GET /api/users/leaderboard?limit=20
-    async getLeaderboard(limit: number = 20): Promise<LeaderboardEntry[]> {
-        const url = `/api/users/leaderboard?limit=${limit}`;
+    // GET /api/users/leaderboard?limit=20&window=all
+    async getLeaderboard(limit: number = 20, window: LeaderboardWindow = 'all'): Promise<LeaderboardEntry[]> {
+        const url = `/api/users/leaderboard?limit=${limit}&window=${window}`;
         return this.fetchWithErrorHandling(url);
     }
 
-    // GET /api/users/:user_id/leaderboard-position
-    async getUserLeaderboardPosition(userId: string): Promise<LeaderboardEntry | null> {
-        const url = `/api/users/${userId}/leaderboard-position`;
+    // GET /api/users/:user_id
+    async getUserProfile(userId: string): Promise<UserProfile> {
+        return this.fetchWithErrorHandling(`/api/users/${userId}`);
+    }
+
+    // PATCH /api/users/me
+    async updateProfile(update: ProfileUpdate): Promise<AuthUser> {
+        return this.fetchWithErrorHandling('/api/users/me', {
+            method: 'PATCH',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(update),
+        });
+    }
+
+    // GET /api/users/me/email-preferences
+    async getEmailPreferences(): Promise<EmailPreferences> {
+        return this.fetchWithErrorHandling('/api/users/me/email-preferences');
+    }
+
+    // PATCH /api/users/me/email-preferences
+    async updateEmailPreferences(update: Partial<EmailPreferences>): Promise<EmailPreferences> {
+        return this.fetchWithErrorHandling('/api/users/me/email-preferences', {
+            method: 'PATCH',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(update),
+        });
+    }
+
+    // GET /api/users/me/export
+    async exportAccount(): Promise<Blob> {
+        const response = await authenticatedFetch('/api/users/me/export');
+        if (!response.ok) {
+            throw new Error(`HTTP error! status: ${response.status}`);
+        }
+        return response.blob();
+    }
+
+    // DELETE /api/users/me?notes=delete|anonymise
+    async deleteAccount(notes: 'delete' | 'anonymise'): Promise<void> {
+        await this.fetchWithErrorHandling(`/api/users/me?notes=${notes}`, { method: 'DELETE' });
+    }
+
+    // GET /api/users/:user_id/leaderboard-position?window=all
+    async getUserLeaderboardPosition(userId: string, window: LeaderboardWindow = 'all'): Promise<LeaderboardEntry | null> {
+        const url = `/api/users/${userId}/leaderboard-position?window=${window}`;
         try {
             return await this.fetchWithErrorHandling(url);
         } catch (error) {
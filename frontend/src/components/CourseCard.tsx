@@ -200,7 +200,7 @@ const CourseCard: React.FC<CourseCardProps> = ({ note, showEditButton = false, o
                             </p>
                         )}
                         <p>
-                            <span className="font-semibold text-gray-500">By:</span> {note.uploader_user.full_name}
+                            <span className="font-semibold text-gray-500">By:</span> {note.uploader_user.display_name}
                         </p>
                     </div>
 
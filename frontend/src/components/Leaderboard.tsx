@@ -84,7 +84,7 @@ const Leaderboard: React.FC = () => {
                 {entry.picture ? (
                     <img
                         src={entry.picture}
-                        alt={entry.full_name}
+                        alt={entry.display_name}
                         className="w-12 h-12 rounded-full object-cover"
                         referrerPolicy="no-referrer"
                     />
@@ -95,7 +95,7 @@ const Leaderboard: React.FC = () => {
                 )}
                 <div className="flex-1 min-w-0">
                     <h3 className="text-base font-semibold text-text-base truncate">
-                        {entry.full_name}
+                        {entry.display_name}
                         {isCurrentUser && (
                             <span className="ml-2 text-xs bg-blue-600 text-white px-2 py-1 rounded-full">
                                 You
@@ -2,7 +2,7 @@ import React, { useState, useEffect } from 'react';
 import { useParams, useNavigate } from 'react-router-dom';
 import { User, FileText, ThumbsUp, Download, TrendingUp, ArrowLeft } from 'lucide-react';
 import { useAuth } from '../contexts/AuthContext';
-import { userApi, type LeaderboardEntry } from '../api/userApi';
+import { userApi, type UserProfile } from '../api/userApi';
 import { notesApi } from '../api/notesApi';
 import type { ResponseNote } from '../types';
 import CourseCard from '../components/CourseCard';
@@ -13,7 +13,7 @@ const ProfilePage: React.FC = () => {
   const { user: currentUser, isAuthenticated } = useAuth();
   const navigate = useNavigate();
 
-  const [profileData, setProfileData] = useState<LeaderboardEntry | null>(null);
+  const [profileData, setProfileData] = useState<UserProfile | null>(null);
   const [userNotes, setUserNotes] = useState<ResponseNote[]>([]);
   const [loading, setLoading] = useState(true);
   const [error, setError] = useState<string | null>(null);
@@ -39,9 +39,9 @@ const ProfilePage: React.FC = () => {
       setLoading(true);
       setError(null);
 
-      // Fetch user stats from leaderboard
-      const stats = await userApi.getUserLeaderboardPosition(targetUserId);
-      setProfileData(stats);
+      // Fetch the user's public profile and stats
+      const profile = await userApi.getUserProfile(targetUserId);
+      setProfileData(profile);
 
       // Fetch user's notes
       const notes = await notesApi.getUserNotes(targetUserId);
@@ -110,7 +110,7 @@ const ProfilePage: React.FC = () => {
             {profileData.picture ? (
               <img
                 src={profileData.picture}
-                alt={profileData.full_name}
+                alt={profileData.display_name}
                 className="w-32 h-32 rounded-full object-cover border-4 border-primary"
                 referrerPolicy="no-referrer"
               />
@@ -123,7 +123,7 @@ const ProfilePage: React.FC = () => {
             {/* User Info */}
             <div className="flex-1 text-center md:text-left">
               <h1 className="text-3xl font-bold text-text-base mb-2">
-                {profileData.full_name}
+                {profileData.display_name}
                 {isOwnProfile && (
                   <span className="ml-3 text-sm bg-primary text-white px-3 py-1 rounded-full">
                     Your Profile
@@ -139,7 +139,7 @@ const ProfilePage: React.FC = () => {
                     <span className="text-sm text-text-muted">Reputation</span>
                   </div>
                   <p className="text-2xl font-bold text-text-base text-center md:text-left">
-                    {profileData.reputation.toFixed(0)}
+                    {profileData.stats.reputation.toFixed(0)}
                   </p>
                 </div>
 
@@ -149,7 +149,7 @@ const ProfilePage: React.FC = () => {
                     <span className="text-sm text-text-muted">Notes</span>
                   </div>
                   <p className="text-2xl font-bold text-text-base text-center md:text-left">
-                    {profileData.total_notes}
+                    {profileData.stats.total_notes}
                   </p>
                 </div>
 
@@ -159,7 +159,7 @@ const ProfilePage: React.FC = () => {
                     <span className="text-sm text-text-muted">Upvotes</span>
                   </div>
                   <p className="text-2xl font-bold text-text-base text-center md:text-left">
-                    {profileData.total_upvotes}
+                    {profileData.stats.total_upvotes}
                   </p>
                 </div>
 
@@ -169,7 +169,7 @@ const ProfilePage: React.FC = () => {
                     <span className="text-sm text-text-muted">Downloads</span>
                   </div>
                   <p className="text-2xl font-bold text-text-base text-center md:text-left">
-                    {profileData.total_downloads}
+                    {profileData.stats.total_downloads}
                   </p>
                 </div>
               </div>
@@ -180,7 +180,7 @@ const ProfilePage: React.FC = () => {
         {/* Uploaded Notes Section */}
         <div className="mb-8">
           <h2 className="text-2xl font-bold text-text-base mb-6">
-            {isOwnProfile ? 'Your Uploaded Notes' : `Notes by ${profileData.full_name.split(' ')[0]}`}
+            {isOwnProfile ? 'Your Uploaded Notes' : `Notes by ${profileData.display_name.split(' ')[0]}`}
             <span className="ml-3 text-lg text-text-muted font-normal">
               ({userNotes.length})
             </span>
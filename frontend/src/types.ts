@@ -1,19 +1,17 @@
-export type VoteType = 'upvote' | 'remove'; // 'downvote' removed for optimism
+export type VoteType = 'upvote' | 'downvote' | 'remove';
+export type DownvoteReason = 'outdated' | 'wrong_course' | 'illegible' | 'incomplete' | 'other';
 export interface DBVote {
     id: string;
     user_id: string;
     note_id: string;
     is_upvote: boolean;
     created_at: string | null;
+    downvote_reason: DownvoteReason | null;
 }
 
-export interface ResponseUser {
-    id: string;
-    google_id: string;
-    email: string;
-    full_name: string;
-    reputation: number;
-    created_at: string;
+export interface PublicUser {
+    id: string | null; // null in place of the uploader of an anonymous note
+    display_name: string;
     picture: string;
 }
 
@@ -25,12 +23,16 @@ export interface ResponseNote {
     professor_names?: string[];
     tags: string[];
     is_public: boolean;
+    is_hidden: boolean;
+    is_anonymous: boolean; // the uploader is only shown to themselves and to moderators
     preview_image_url?: string;
     file_url: string;
-    uploader_user: ResponseUser;
+    uploader_user: PublicUser;
     created_at: string;
     upvotes: number;
     downvotes: number;
+    score: number;
+    comment_count: number;
     user_vote: boolean | null;
     downloads: number;
     year: number;
@@ -53,6 +55,16 @@ export interface CourseGridProps {
     notes: ResponseNote[];
 }
 // API response types
+export type UserRole = 'user' | 'moderator' | 'admin';
+
+export interface Badge {
+    badge: string;
+    name: string;
+    description: string;
+    context: string | null; // e.g. the semester, for badges that can be earned again
+    awarded_at: string;
+}
+
 export interface AuthUser {
     id: string;
     google_id: string;
@@ -61,6 +73,14 @@ export interface AuthUser {
     reputation: number;
     created_at: string;
     picture: string;
+    role: UserRole;
+    display_name: string | null;
+    bio: string | null;
+    department: string | null;
+    graduation_year: number | null;
+    show_initials_only: boolean;
+    public_name: string; // the name other users see
+    badges?: Badge[]; // only included by /auth/me
 }
 
 export interface AuthContextType {